@@ -0,0 +1,31 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use nalgebra::DMatrix;
+use product_space::{density, density_par, proximity};
+
+const COUNTRIES: usize = 1000;
+const PRODUCTS: usize = 10000;
+
+// deterministic pseudo-random binary rca, about a fifth ones
+fn synthetic_rca() -> DMatrix<f64> {
+    let mut state: u64 = 42;
+    DMatrix::from_fn(COUNTRIES, PRODUCTS, |_, _| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        if (state >> 33) % 5 == 0 { 1.0 } else { 0.0 }
+    })
+}
+
+fn bench_density(c: &mut Criterion) {
+    let rca = synthetic_rca();
+    let mut prox = proximity(&rca);
+    prox.apply(|x| if x.is_nan() { 0.0 } else { x });
+
+    c.bench_function("density serial 1000x10000", |b| b.iter(|| density(&rca, &prox)));
+    c.bench_function("density parallel 1000x10000", |b| b.iter(|| density_par(&rca, &prox)));
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_density
+}
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use nalgebra::DMatrix;
+use std::collections::HashMap;
+use product_space::{density, fair_share, proximity, rca, ProductSpace};
+
+const COUNTRIES: usize = 200;
+const PRODUCTS: usize = 5000;
+const YEARS: u32 = 30;
+
+// deterministic pseudo-random export values, about a third zeros
+fn synthetic_mcp(seed: u64) -> DMatrix<f64> {
+    let mut state = seed;
+    DMatrix::from_fn(COUNTRIES, PRODUCTS, |_, _| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let x = (state >> 33) % 3000;
+        if x < 1000 { 0.0 } else { x as f64 }
+    })
+}
+
+fn index(prefix: &str, n: usize) -> HashMap<String, usize> {
+    (0..n).map(|i| (format!("{}{}", prefix, i), i)).collect()
+}
+
+fn synthetic_mcps() -> HashMap<u32, DMatrix<f64>> {
+    (0..YEARS)
+        .map(|i| (2000 + i, synthetic_mcp(42 + i as u64)))
+        .collect()
+}
+
+// The build steps are benched through the public api (benches can't
+// reach crate internals): `rca` and `proximity` for one year, and a
+// build without proximity for rca across all years.
+fn bench_steps(c: &mut Criterion) {
+    let mcp = synthetic_mcp(42);
+    let binary = fair_share(&rca(&mcp), Some(1.0));
+    let mut prox = proximity(&binary);
+    prox.apply(|x| if x.is_nan() { 0.0 } else { x });
+
+    c.bench_function("rca 200x5000", |b| b.iter(|| rca(&mcp)));
+    c.bench_function("proximity 200x5000", |b| b.iter(|| proximity(&binary)));
+    c.bench_function("density 200x5000", |b| b.iter(|| density(&binary, &prox)));
+}
+
+fn bench_build(c: &mut Criterion) {
+    let mcps = synthetic_mcps();
+    let country_idx = index("c", COUNTRIES);
+    let product_idx = index("p", PRODUCTS);
+
+    c.bench_function("build rca only 200x5000 30 years", |b| b.iter(|| {
+        ProductSpace::builder(country_idx.clone(), product_idx.clone(), mcps.clone())
+            .rca_cutoff(Some(1.0))
+            .compute_proximity(false)
+            .build()
+    }));
+    c.bench_function("ProductSpace::new 200x5000 30 years", |b| b.iter(|| {
+        ProductSpace::new(country_idx.clone(), product_idx.clone(), mcps.clone(), Some(1.0))
+    }));
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_steps, bench_build
+}
+criterion_main!(benches);
@@ -23,7 +23,7 @@ fn main() -> Result<(), Error> {
 
     timeit!("rca 1yr test",
         {
-            let rca = ps.rca(&[2017], None)
+            let rca = ps.rca_year(2017, None)
                 .ok_or_else(|| format_err!("no rca for 2017?"))?;
             println!("RCA test against simoes ps_calcs for 2017");
             println!("nzl::0204, expect 149.962669: {:?}", rca.get("nzl", "0204")?);
@@ -34,7 +34,7 @@ fn main() -> Result<(), Error> {
 
     timeit!("rca 1yr no cutoff 1.0, x3",
         for year in 2015..=2017 {
-            let rca = ps.rca(&[year as u32], None)
+            let rca = ps.rca_year(year as u32, None)
                 .ok_or_else(|| format_err!("no rca for year"))?;
             println!("usa::0101, {}: {}", year, rca.get("usa", "0101")?);
         }
@@ -54,7 +54,7 @@ fn main() -> Result<(), Error> {
 
     timeit!("density 1yr cutoff 1.0, x3",
         for year in 2015..=2017 {
-            let density = ps.density(&[year], Some(1.0))
+            let density = ps.density_year(year, Some(1.0))
                 .ok_or_else(|| format_err!("no rca for 2017?"))?;
             println!("usa::0101, {}: {:?}", year, density.get("usa", "0101")?);
         }
@@ -116,6 +116,12 @@ pub fn ps_from_tsv_reader(filepath: PathBuf) -> Result<ProductSpace, Error> {
         .map(|y| (y, DMatrix::zeros(country_set.len(), product_set.len())))
         .collect();
 
+    // sorted, so indexes are the same across runs
+    let mut country_set: Vec<_> = country_set.into_iter().collect();
+    country_set.sort();
+    let mut product_set: Vec<_> = product_set.into_iter().collect();
+    product_set.sort();
+
     let country_idx: HashMap<_,_> = country_set.into_iter()
         .enumerate()
         .map(|(v,k)| (k,v))
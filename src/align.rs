@@ -0,0 +1,197 @@
+use nalgebra::DMatrix;
+use std::collections::HashMap;
+
+use crate::{Error, ProductSpace};
+
+/// Which countries and products `ProductSpace::align` keeps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlignMode {
+    /// in either product space; missing cells are zero
+    Union,
+    /// in both product spaces
+    Intersection,
+}
+
+impl ProductSpace {
+    /// Reindexes both product spaces to the same countries and products,
+    /// so they can be merged or compared cell by cell. Cells missing from
+    /// one are padded with zeros. Each is rebuilt with its own settings.
+    ///
+    /// The new indexes are sorted by name.
+    ///
+    /// With `AlignMode::Union`, a product only in the other product space
+    /// has no exports in this one, so its rca is NaN for every country,
+    /// as for any untraded product; likewise a padded country's row. They
+    /// go through the sanitize policy as usual: left as NaN with `Keep`,
+    /// the product columns zeroed with `ZeroUntraded`, and
+    /// `Error::NonFinite` with `ErrorOnNonFinite`.
+    ///
+    /// Returns `Error::McpsDropped` if either has dropped its mcps, and
+    /// errors from rebuilding.
+    pub fn align(
+        a: &ProductSpace,
+        b: &ProductSpace,
+        mode: AlignMode,
+        ) -> Result<(ProductSpace, ProductSpace), Error>
+    {
+        let country_idx = join(&a.country_idx, &b.country_idx, mode);
+        let product_idx = join(&a.product_idx, &b.product_idx, mode);
+
+        let a = a.reindexed(&country_idx, &product_idx)?;
+        let b = b.reindexed(&country_idx, &product_idx)?;
+
+        Ok((a, b))
+    }
+
+    fn reindexed(
+        &self,
+        country_idx: &HashMap<String, usize>,
+        product_idx: &HashMap<String, usize>,
+        ) -> Result<ProductSpace, Error>
+    {
+        let mcps = self.mcps()?;
+
+        // old idx -> new idx, for entries kept
+        let rows = mapping(&self.country_idx, country_idx);
+        let cols = mapping(&self.product_idx, product_idx);
+
+        let mcps = mcps.iter()
+            .map(|(year, mcp)| {
+                let mut aligned = DMatrix::zeros(country_idx.len(), product_idx.len());
+
+                for (old_i, new_i) in &rows {
+                    for (old_j, new_j) in &cols {
+                        aligned[(*new_i, *new_j)] = mcp[(*old_i, *old_j)];
+                    }
+                }
+
+                (*year, aligned)
+            })
+            .collect();
+
+        self.rebuilder(country_idx.clone(), product_idx.clone(), mcps).build_inner(false)
+    }
+}
+
+fn join(
+    a: &HashMap<String, usize>,
+    b: &HashMap<String, usize>,
+    mode: AlignMode,
+    ) -> HashMap<String, usize>
+{
+    let mut names: Vec<&String> = match mode {
+        AlignMode::Union => a.keys().chain(b.keys().filter(|k| !a.contains_key(*k))).collect(),
+        AlignMode::Intersection => a.keys().filter(|k| b.contains_key(*k)).collect(),
+    };
+    names.sort();
+
+    names.into_iter()
+        .enumerate()
+        .map(|(v,k)| (k.clone(), v))
+        .collect()
+}
+
+fn mapping(
+    old: &HashMap<String, usize>,
+    new: &HashMap<String, usize>,
+    ) -> Vec<(usize, usize)>
+{
+    old.iter()
+        .filter_map(|(k, old_idx)| new.get(k).map(|new_idx| (*old_idx, *new_idx)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ProductSpaceBuilder, SanitizePolicy};
+
+    fn idx(names: &[&str]) -> HashMap<String, usize> {
+        names.iter()
+            .enumerate()
+            .map(|(v,k)| (k.to_string(), v))
+            .collect()
+    }
+
+    fn test_spaces() -> (ProductSpace, ProductSpace) {
+        test_spaces_with(SanitizePolicy::Keep)
+    }
+
+    fn test_spaces_with(sanitize: SanitizePolicy) -> (ProductSpace, ProductSpace) {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(3,3,(1..10).map(f64::from).collect()));
+        let a = ProductSpaceBuilder::new(idx(&["a", "b", "c"]), idx(&["01", "02", "03"]), mcps)
+            .sanitize(sanitize)
+            .build();
+
+        // overlaps a on b, c and 02, 03, in reverse order
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(3,3,(11..20).map(f64::from).collect()));
+        let b = ProductSpaceBuilder::new(idx(&["d", "c", "b"]), idx(&["04", "03", "02"]), mcps)
+            .sanitize(sanitize)
+            .build();
+
+        (a, b)
+    }
+
+    #[test]
+    fn test_align_union_padding() {
+        // in a, 04 and d are padding, with no exports
+        let (a, b) = test_spaces();
+        let (a, _) = ProductSpace::align(&a, &b, AlignMode::Union).unwrap();
+        assert!(a.rcas_by_year[&2017].column(3).iter().all(|x| x.is_nan()));
+        assert!(a.rcas_by_year[&2017].row(3).iter().all(|x| x.is_nan()));
+
+        let (a, b) = test_spaces_with(SanitizePolicy::ZeroUntraded);
+        let (a, _) = ProductSpace::align(&a, &b, AlignMode::Union).unwrap();
+        assert!(a.rcas_by_year[&2017].column(3).iter().all(|x| *x == 0.0));
+        assert!(a.rcas_by_year[&2017][(3, 0)].is_nan());
+
+        let (a, b) = test_spaces_with(SanitizePolicy::ErrorOnNonFinite);
+        match ProductSpace::align(&a, &b, AlignMode::Union) {
+            Err(Error::NonFinite { year, matrix }) => {
+                assert_eq!(year, 2017);
+                assert_eq!(matrix, "rca");
+            },
+            _ => panic!("expected non-finite error"),
+        }
+
+        // nothing is padded in the intersection
+        assert!(ProductSpace::align(&a, &b, AlignMode::Intersection).is_ok());
+    }
+
+    #[test]
+    fn test_align_union() {
+        let (a, b) = test_spaces();
+        let (a, b) = ProductSpace::align(&a, &b, AlignMode::Union).unwrap();
+
+        assert_eq!(*a.country_idx, idx(&["a", "b", "c", "d"]));
+        assert_eq!(*b.product_idx, idx(&["01", "02", "03", "04"]));
+
+        // by column
+        assert_eq!(a.mcps().unwrap()[&2017], DMatrix::from_vec(4,4,vec![
+            1.0,2.0,3.0,0.0,
+            4.0,5.0,6.0,0.0,
+            7.0,8.0,9.0,0.0,
+            0.0,0.0,0.0,0.0,
+        ]));
+        assert_eq!(b.mcps().unwrap()[&2017], DMatrix::from_vec(4,4,vec![
+            0.0,0.0,0.0,0.0,
+            0.0,19.0,18.0,17.0,
+            0.0,16.0,15.0,14.0,
+            0.0,13.0,12.0,11.0,
+        ]));
+    }
+
+    #[test]
+    fn test_align_intersection() {
+        let (a, b) = test_spaces();
+        let (a, b) = ProductSpace::align(&a, &b, AlignMode::Intersection).unwrap();
+
+        assert_eq!(*a.country_idx, idx(&["b", "c"]));
+        assert_eq!(*b.product_idx, idx(&["02", "03"]));
+
+        assert_eq!(a.mcps().unwrap()[&2017], DMatrix::from_vec(2,2,vec![5.0,6.0,8.0,9.0]));
+        assert_eq!(b.mcps().unwrap()[&2017], DMatrix::from_vec(2,2,vec![19.0,18.0,16.0,15.0]));
+    }
+}
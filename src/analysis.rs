@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+
+use crate::complexity::complexity_by_diversity;
+use crate::mcp::names_by_index;
+use crate::stats::{cmp_ranked, sort_ranked};
+use crate::{
+    density,
+    Complexity,
+    Density,
+    Error,
+    ProductSpace,
+    Rca,
+};
+
+/// Everything derived from one binary rca for a set of years.
+/// See `ProductSpace::analyze`
+pub struct YearAnalysis {
+    /// binary rca
+    pub rca: Rca,
+    /// density from the binary rca
+    pub density: Density,
+    pub complexity: Complexity,
+    /// number of products each country has binary rca in
+    pub diversity: HashMap<String, u32>,
+    /// number of countries with binary rca in each product
+    pub ubiquity: HashMap<String, u32>,
+}
+
+impl ProductSpace {
+    /// Calculates the binary rca once (with the fair share default of 1.0
+    /// if there's no cutoff), and derives density, diversity, ubiquity,
+    /// eci and pci from it.
+    ///
+    /// Note that density here always uses the binary rca, where
+    /// `density(years, None)` uses the continuous rca.
+    pub fn analyze(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<YearAnalysis, Error>
+    {
+        let missing = || Error::MissingYears { years: years.to_vec() };
+
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+        let proximity = self.try_proximity_matrix(years)?.ok_or_else(missing)?;
+
+        let mut density = density(&rca, &proximity);
+        density.apply(|x| self.sanitized(x));
+        let (eci, pci) = complexity_by_diversity(&rca);
+
+        let kc = rca.column_sum();
+        let kp = rca.row_sum();
+
+        let diversity = self.country_idx.iter()
+            .map(|(country, i)| (country.clone(), kc[*i] as u32))
+            .collect();
+        let ubiquity = self.product_idx.iter()
+            .map(|(product, i)| (product.clone(), kp[*i] as u32))
+            .collect();
+
+        Ok(YearAnalysis {
+            rca: Rca {
+                country_idx: self.country_idx.clone(),
+                product_idx: self.product_idx.clone(),
+                m: rca,
+                provenance: self.binary_provenance(years, rca_cutoff),
+            },
+            density: Density {
+                country_idx: self.country_idx.clone(),
+                product_idx: self.product_idx.clone(),
+                m: density,
+                provenance: self.binary_provenance(years, rca_cutoff),
+            },
+            complexity: Complexity {
+                country_idx: self.country_idx.clone(),
+                product_idx: self.product_idx.clone(),
+                eci: eci.column(0).into_owned(),
+                pci: pci.column(0).into_owned(),
+                provenance: self.binary_provenance(years, rca_cutoff),
+            },
+            diversity,
+            ubiquity,
+        })
+    }
+}
+
+impl ProductSpace {
+    /// For every country, its frontier (products without binary rca),
+    /// each with its relatedness (density, as in `analyze`), sorted by
+    /// relatedness descending. Ties are by product code, and NaN is last.
+    ///
+    /// Calculated once for all countries, which is much cheaper than a
+    /// call per country. The density matrix is held while the result is
+    /// built, and the result itself can be nearly as large (a String
+    /// and f64 per frontier cell), so for large product spaces this
+    /// costs a few times the memory of one year's rca.
+    pub fn all_frontier_relatedness(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<HashMap<String, Vec<(String, f64)>>, Error>
+    {
+        let missing = || Error::MissingYears { years: years.to_vec() };
+
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+        let proximity = self.try_proximity_matrix(years)?.ok_or_else(missing)?;
+        let density = density(&rca, &proximity);
+
+        let products = names_by_index(&self.product_idx);
+
+        Ok(self.country_idx.iter()
+            .map(|(country, row)| {
+                let mut frontier: Vec<_> = products.iter()
+                    .enumerate()
+                    .filter(|(col, _)| rca[(*row, *col)] == 0.0)
+                    .map(|(col, product)| (product.to_string(), self.sanitized(density[(*row, col)])))
+                    .collect();
+                sort_ranked(&mut frontier);
+
+                (country.clone(), frontier)
+            })
+            .collect())
+    }
+
+    /// For every country, its frontier product (without binary rca)
+    /// with the highest relatedness, and that relatedness: the first
+    /// item of `all_frontier_relatedness`, without building and sorting
+    /// the whole frontier. Ties are by product code, and NaN is only
+    /// picked if the whole frontier is NaN. Countries with binary rca in
+    /// every product have no frontier and are left out.
+    pub fn best_opportunity(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<HashMap<String, (String, f64)>, Error>
+    {
+        let missing = || Error::MissingYears { years: years.to_vec() };
+
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+        let proximity = self.try_proximity_matrix(years)?.ok_or_else(missing)?;
+        let density = density(&rca, &proximity);
+
+        let products = names_by_index(&self.product_idx);
+
+        Ok(self.country_idx.iter()
+            .filter_map(|(country, row)| {
+                products.iter()
+                    .enumerate()
+                    .filter(|(col, _)| rca[(*row, *col)] == 0.0)
+                    .map(|(col, product)| (*product, self.sanitized(density[(*row, col)])))
+                    .min_by(|x, y| cmp_ranked(*x, *y))
+                    .map(|(product, x)| (country.clone(), (product.to_string(), x)))
+            })
+            .collect())
+    }
+
+    /// Each product with its ubiquity, the number of countries with
+    /// binary rca in it, in product index order. Uses the fair share
+    /// default of 1.0 if there's no cutoff, as in `complexity`.
+    pub fn products_by_ubiquity(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<Vec<(String, u32)>, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+        let kp = rca.row_sum();
+
+        let mut res: Vec<_> = self.product_idx.iter()
+            .map(|(product, i)| (*i, product.clone(), kp[*i] as u32))
+            .collect();
+        res.sort();
+
+        Ok(res.into_iter()
+            .map(|(_, product, ubiquity)| (product, ubiquity))
+            .collect())
+    }
+
+    /// Products exported (with binary rca) by exactly `k` countries,
+    /// in product index order. See `products_by_ubiquity`
+    pub fn products_with_ubiquity(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        k: u32,
+        ) -> Result<Vec<String>, Error>
+    {
+        Ok(self.products_by_ubiquity(years, rca_cutoff)?
+            .into_iter()
+            .filter(|(_, ubiquity)| *ubiquity == k)
+            .map(|(product, _)| product)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::DMatrix;
+    use super::*;
+    use crate::Mcp;
+
+    #[test]
+    fn test_analyze() {
+        let vals = DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let analysis = ps.analyze(&[2017], Some(1.0)).unwrap();
+
+        // binary rows: a [0,1,1], b [1,0,0], c [1,0,0]
+        assert_eq!(analysis.rca.get("a", "02").unwrap(), 1.0);
+        assert_eq!(analysis.diversity["a"], 2);
+        assert_eq!(analysis.diversity["b"], 1);
+        assert_eq!(analysis.ubiquity["01"], 2);
+        assert_eq!(analysis.ubiquity["03"], 1);
+
+        let expected = ps.complexity(&[2017], Some(1.0)).unwrap();
+        assert_eq!(analysis.complexity.eci_vector(), expected.eci_vector());
+
+        let expected = ps.density(&[2017], Some(1.0)).unwrap();
+        assert_eq!(analysis.density.matrix(), expected.matrix());
+
+        assert!(ps.analyze(&[2099], Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_all_frontier_relatedness() {
+        let vals = DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let res = ps.all_frontier_relatedness(&[2017], Some(1.0)).unwrap();
+        let density = ps.analyze(&[2017], Some(1.0)).unwrap().density;
+
+        // binary rows: a [0,1,1], b [1,0,0], c [1,0,0]
+        assert_eq!(res.len(), 3);
+        assert_eq!(res["a"], vec![("01".to_string(), density.get("a", "01").unwrap())]);
+
+        let products: Vec<_> = res["b"].iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(products.len(), 2);
+        assert!(products.contains(&"02") && products.contains(&"03"));
+        assert!(res["b"][0].1 >= res["b"][1].1);
+        assert_eq!(res["b"][0].1, density.get("b", products[0]).unwrap());
+
+        assert!(ps.all_frontier_relatedness(&[2099], Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_best_opportunity() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2), ("d".to_string(),3)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // binary rows: a [1,0,1], b [0,1,0], c [0,0,1], d [1,1,0]
+        // proximity: [[1, .5, .5], [.5, 1, 0], [.5, 0, 1]]
+        let res = ps.best_opportunity(&[2017], Some(1.0)).unwrap();
+        assert_eq!(res.len(), 4);
+        assert_eq!(res["a"], ("02".to_string(), 0.5 / 1.5));
+        assert_eq!(res["b"], ("01".to_string(), 0.25));
+        assert_eq!(res["d"], ("03".to_string(), 0.5 / 1.5));
+
+        let all = ps.all_frontier_relatedness(&[2017], Some(1.0)).unwrap();
+        for (country, best) in &res {
+            assert_eq!(best, &all[country][0]);
+        }
+
+        assert!(ps.best_opportunity(&[2099], Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_products_by_ubiquity() {
+        let vals = DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // binary rows: a [0,1,1], b [1,0,0], c [1,0,0]
+        assert_eq!(
+            ps.products_by_ubiquity(&[2017], Some(1.0)).unwrap(),
+            vec![("01".to_string(), 2), ("02".to_string(), 1), ("03".to_string(), 1)],
+        );
+        assert_eq!(
+            ps.products_with_ubiquity(&[2017], Some(1.0), 1).unwrap(),
+            vec!["02".to_string(), "03".to_string()],
+        );
+        assert!(ps.products_with_ubiquity(&[2017], Some(1.0), 3).unwrap().is_empty());
+        assert!(ps.products_by_ubiquity(&[2099], Some(1.0)).is_err());
+    }
+}
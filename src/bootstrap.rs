@@ -0,0 +1,219 @@
+use nalgebra::DMatrix;
+use std::collections::HashMap;
+
+use crate::stats::ranks;
+use crate::complexity::complexity_by_diversity;
+use crate::{Error, ProductSpace};
+
+/// What to resample with replacement in `complexity_bootstrap`. The
+/// other dimension is the one ranked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resample {
+    /// resample products, and rank countries by eci
+    Products,
+    /// resample countries, and rank products by pci
+    Countries,
+}
+
+/// Complexity rank over bootstrap resamples, where 1.0 is the most
+/// complex. Ties get the average rank.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RankStats {
+    pub mean: f64,
+    /// sample std (ddof 1), NaN with fewer than two samples
+    pub std: f64,
+    /// resamples the entity was ranked in
+    pub samples: usize,
+}
+
+impl ProductSpace {
+    /// How stable complexity rankings are: the binary rca is resampled
+    /// `resamples` times (products or countries, with replacement),
+    /// complexity recalculated, and the rank of each country (or
+    /// product) collected.
+    ///
+    /// In each resample, countries and products left with no binary
+    /// rca are dropped, since they have no complexity, so an entity can
+    /// be ranked in fewer than `resamples` resamples. Resamples where
+    /// complexity isn't finite (e.g. all countries the same) are skipped.
+    ///
+    /// Binary rca uses the fair share default of 1.0 if there's no cutoff,
+    /// as in `complexity`. Results are the same for the same `seed`.
+    pub fn complexity_bootstrap(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        resample: Resample,
+        resamples: usize,
+        seed: u64,
+        ) -> Result<HashMap<String, RankStats>, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+
+        let stats = bootstrap_ranks(&rca, resample, resamples, &mut Rng::new(seed));
+
+        let idx = match resample {
+            Resample::Products => &self.country_idx,
+            Resample::Countries => &self.product_idx,
+        };
+
+        Ok(idx.iter()
+            .map(|(name, i)| (name.clone(), stats[*i]))
+            .collect())
+    }
+}
+
+// rank stats by row for `Resample::Products`, or by column for
+// `Resample::Countries`
+fn bootstrap_ranks(
+    rca: &DMatrix<f64>,
+    resample: Resample,
+    resamples: usize,
+    rng: &mut Rng,
+    ) -> Vec<RankStats>
+{
+    // work on products, so resampling countries is on the transpose,
+    // with eci of the transpose being pci
+    let rca = match resample {
+        Resample::Products => rca.clone(),
+        Resample::Countries => rca.transpose(),
+    };
+    let (nrows, ncols) = rca.shape();
+
+    let mut sums = vec![0.0; nrows];
+    let mut sums_sq = vec![0.0; nrows];
+    let mut counts = vec![0; nrows];
+
+    for _ in 0..resamples {
+        let picks: Vec<usize> = (0..ncols).map(|_| rng.below(ncols)).collect();
+        let sampled = DMatrix::from_fn(nrows, ncols, |i, j| rca[(i, picks[j])]);
+
+        let kept_rows: Vec<usize> = (0..nrows)
+            .filter(|i| sampled.row(*i).iter().any(|x| *x != 0.0))
+            .collect();
+        let kept_cols: Vec<usize> = (0..ncols)
+            .filter(|j| sampled.column(*j).iter().any(|x| *x != 0.0))
+            .collect();
+        if kept_rows.len() < 2 || kept_cols.len() < 2 {
+            continue;
+        }
+
+        let m = DMatrix::from_fn(kept_rows.len(), kept_cols.len(), |i, j| {
+            sampled[(kept_rows[i], kept_cols[j])]
+        });
+
+        // eci of rows, whether they're countries or (transposed) products
+        let eci = match resample {
+            Resample::Products => complexity_by_diversity(&m).0,
+            Resample::Countries => complexity_by_diversity(&m.transpose()).1,
+        };
+        if !eci.iter().all(|x| x.is_finite()) {
+            continue;
+        }
+
+        // rank 1.0 is the most complex
+        let negated: Vec<f64> = eci.iter().map(|x| -x).collect();
+        for (rank, row) in ranks(&negated).into_iter().zip(&kept_rows) {
+            sums[*row] += rank;
+            sums_sq[*row] += rank * rank;
+            counts[*row] += 1;
+        }
+    }
+
+    (0..nrows)
+        .map(|i| {
+            let n = counts[i] as f64;
+            let mean = sums[i] / n;
+            let std = if counts[i] < 2 {
+                std::f64::NAN
+            } else {
+                ((sums_sq[i] - n * mean * mean) / (n - 1.0)).max(0.0).sqrt()
+            };
+
+            RankStats { mean, std, samples: counts[i] }
+        })
+        .collect()
+}
+
+// xorshift64*, so resamples are reproducible without a rand dependency
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // state can't be zero
+        Rng(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // slightly biased for large n, which doesn't matter here
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // country i exports products 0..=i
+    fn nested() -> DMatrix<f64> {
+        DMatrix::from_fn(5, 5, |i, j| if j <= i { 1.0 } else { 0.0 })
+    }
+
+    #[test]
+    fn test_bootstrap_ranks() {
+        let res = bootstrap_ranks(&nested(), Resample::Products, 50, &mut Rng::new(1));
+        assert_eq!(res.len(), 5);
+
+        assert!(res.iter().any(|stats| stats.samples > 0));
+        for stats in &res {
+            assert!(stats.samples <= 50);
+            if stats.samples > 0 {
+                assert!(stats.mean >= 1.0 && stats.mean <= 5.0);
+            }
+        }
+
+        // same seed, same resamples (compared as bits, for NaN)
+        let bits = |res: &[RankStats]| -> Vec<(usize, u64, u64)> {
+            res.iter().map(|s| (s.samples, s.mean.to_bits(), s.std.to_bits())).collect()
+        };
+        let again = bootstrap_ranks(&nested(), Resample::Products, 50, &mut Rng::new(1));
+        assert_eq!(bits(&res), bits(&again));
+
+        let res = bootstrap_ranks(&nested(), Resample::Countries, 50, &mut Rng::new(1));
+        assert_eq!(res.len(), 5);
+        assert!(res.iter().any(|stats| stats.samples > 0));
+
+        let res = bootstrap_ranks(&nested(), Resample::Products, 0, &mut Rng::new(1));
+        assert!(res.iter().all(|stats| stats.samples == 0 && stats.mean.is_nan()));
+    }
+
+    #[test]
+    fn test_complexity_bootstrap() {
+        let vals = DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let res = ps.complexity_bootstrap(&[2017], Some(1.0), Resample::Products, 10, 7).unwrap();
+        assert_eq!(res.len(), 3);
+        assert!(res.contains_key("a"));
+
+        let res = ps.complexity_bootstrap(&[2017], Some(1.0), Resample::Countries, 10, 7).unwrap();
+        assert!(res.contains_key("03"));
+
+        assert!(ps.complexity_bootstrap(&[2099], Some(1.0), Resample::Products, 10, 7).is_err());
+    }
+}
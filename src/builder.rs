@@ -0,0 +1,1471 @@
+use log::warn;
+use nalgebra::DMatrix;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::ingest::{report, IngestPhase, ProgressFn};
+use crate::mcp::names_by_index;
+use crate::{
+    apply_fair_share_with,
+    cosine_proximity,
+    proximity,
+    validate_cutoff,
+    rca_leave_one_out,
+    rca_with,
+    Error,
+    Comparison,
+    RcaVariant,
+    ValueKind,
+    ProductSpace,
+    ProximityBasis,
+    SparseProximity,
+    ProximityMethod,
+};
+
+/// Options for constructing a `ProductSpace`.
+///
+/// `ProductSpace::new` and `ProductSpace::try_new` are shortcuts for
+/// a builder with only the rca cutoff set.
+pub struct ProductSpaceBuilder {
+    country_idx: Arc<HashMap<String, usize>>,
+    product_idx: HashMap<String, usize>,
+    mcps: HashMap<u32, DMatrix<f64>>,
+
+    rca_cutoff: Option<f64>,
+    comparison: Comparison,
+    proximity_basis: ProximityBasis,
+    proximity_method: ProximityMethod,
+    min_trade_filter: Option<MinTradeFilter>,
+    compute_proximity: bool,
+    sparse_proximity: Option<f64>,
+    interpolate_missing_years: bool,
+    leave_one_out: bool,
+    rca_cap: Option<f64>,
+    sanitize: SanitizePolicy,
+    negative_policy: NegativePolicy,
+    adjustment: Option<DMatrix<f64>>,
+    // mcps already adjusted, when rebuilding from a product space's mcps
+    adjusted: bool,
+    value_kind: ValueKind,
+    rca_variant: RcaVariant,
+    progress: Option<ProgressFn>,
+}
+
+impl ProductSpaceBuilder {
+    pub fn new(
+        country_idx: HashMap<String, usize>,
+        product_idx: HashMap<String, usize>,
+        mcps: HashMap<u32, DMatrix<f64>>,
+        ) -> Self
+    {
+        Self::with_country_index(Arc::new(country_idx), product_idx, mcps)
+    }
+
+    /// Like `new`, but with a country index that can be shared with
+    /// other product spaces (e.g. the same countries under another
+    /// product classification), rather than each having a copy.
+    /// See `ProductSpaceSet`
+    pub fn with_country_index(
+        country_idx: Arc<HashMap<String, usize>>,
+        product_idx: HashMap<String, usize>,
+        mcps: HashMap<u32, DMatrix<f64>>,
+        ) -> Self
+    {
+        Self {
+            country_idx,
+            product_idx,
+            mcps,
+            rca_cutoff: None,
+            comparison: Comparison::default(),
+            proximity_basis: ProximityBasis::default(),
+            proximity_method: ProximityMethod::default(),
+            min_trade_filter: None,
+            compute_proximity: true,
+            sparse_proximity: None,
+            interpolate_missing_years: false,
+            leave_one_out: false,
+            rca_cap: None,
+            sanitize: SanitizePolicy::default(),
+            negative_policy: NegativePolicy::default(),
+            adjustment: None,
+            adjusted: false,
+            value_kind: ValueKind::default(),
+            rca_variant: RcaVariant::default(),
+            progress: None,
+        }
+    }
+
+    /// cutoff used for the binary rca that proximity is built on.
+    /// Defaults to the fair share default of 1.0. `build` panics on an
+    /// invalid cutoff (see `validate_cutoff`); `try_build` returns
+    /// `Error::InvalidCutoff`.
+    pub fn rca_cutoff(mut self, rca_cutoff: Option<f64>) -> Self {
+        self.rca_cutoff = rca_cutoff;
+        self
+    }
+
+    /// how rca is compared to the cutoff, for both the construction
+    /// cutoff and per-call cutoffs. Defaults to `Comparison::Gte`
+    pub fn comparison(mut self, comparison: Comparison) -> Self {
+        self.comparison = comparison;
+        self
+    }
+
+    /// which rca proximity is calculated from. Defaults to
+    /// `ProximityBasis::BinaryRca`
+    pub fn proximity_basis(mut self, proximity_basis: ProximityBasis) -> Self {
+        self.proximity_basis = proximity_basis;
+        self
+    }
+
+    /// how proximity is calculated. Defaults to
+    /// `ProximityMethod::ConditionalProbability`
+    pub fn proximity_method(mut self, proximity_method: ProximityMethod) -> Self {
+        self.proximity_method = proximity_method;
+        self
+    }
+
+    /// whether to calculate and cache proximity for every year on
+    /// build. Defaults to true.
+    ///
+    /// If false, proximity is calculated on each call that needs it
+    /// (`proximity`, `density`, etc.) for only the years requested, and
+    /// isn't cached, so `proximity_ref` returns None. This saves the
+    /// construction time and memory when only rca or complexity is needed.
+    ///
+    /// The cost moves to the calls: each one recalculates every year it
+    /// uses, about countries x products^2 per year, and nothing is kept
+    /// between calls (caching it would bring back the memory this
+    /// saves). When proximity is needed more than once or twice, leave
+    /// this on.
+    pub fn compute_proximity(mut self, compute_proximity: bool) -> Self {
+        self.compute_proximity = compute_proximity;
+        self
+    }
+
+    /// with a threshold, each year's cached proximity keeps only the
+    /// entries at or above it (see `SparseProximity`), instead of the
+    /// dense products x products matrix. Defaults to None, dense.
+    ///
+    /// Dropped entries are taken as 0.0 everywhere proximity is used, so
+    /// this is an approximation: density loses the contribution of weak
+    /// links to both its numerator and denominator, and the network
+    /// exports (`Proximity::mst`, `Proximity::atlas_network`) can't use a
+    /// dropped link. `proximity` and multi-year density still build a
+    /// dense matrix for the call; one year's density is calculated from
+    /// the sparse entries (see `density_sparse`). `proximity_ref` returns
+    /// None, use `sparse_proximity_ref`. No effect without
+    /// `compute_proximity`.
+    ///
+    /// The threshold must be finite and at least 0.0, as a cutoff (see
+    /// `validate_cutoff`): `build` panics, and `try_build` returns
+    /// `Error::InvalidCutoff`.
+    pub fn sparse_proximity(mut self, threshold: Option<f64>) -> Self {
+        self.sparse_proximity = threshold;
+        self
+    }
+
+    /// whether a year missing from a multi-year rca (as in `rca`,
+    /// `density` and `complexity`) is linearly interpolated from the
+    /// nearest years on either side, before averaging or the cutoff.
+    /// Defaults to false, where a missing year adds nothing: zeros in
+    /// the average, and no constraint with a cutoff.
+    ///
+    /// Only whole years are filled, and only interior gaps: both
+    /// neighbors are the nearest years that are requested and found, so
+    /// a missing first or last year is still skipped, and a year found
+    /// but not requested is never used. Interpolation is of the rca as
+    /// cached, in the `RcaVariant`'s terms. Proximity is still averaged
+    /// over the years found.
+    pub fn interpolate_missing_years(mut self, interpolate: bool) -> Self {
+        self.interpolate_missing_years = interpolate;
+        self
+    }
+
+    /// whether each country's rca leaves its own exports out of the world
+    /// totals, comparing it to the rest of the world (see
+    /// `rca_leave_one_out`). Defaults to false.
+    ///
+    /// This matters most for large exporters. It's slower to build, since
+    /// the world totals are different for every country. The only
+    /// exporter of a product gets the ordinary rca for it; see
+    /// `rca_leave_one_out`.
+    pub fn leave_one_out(mut self, leave_one_out: bool) -> Self {
+        self.leave_one_out = leave_one_out;
+        self
+    }
+
+    /// caps each year's rca, as cached, at this value, to limit outliers
+    /// from tiny denominators (e.g. rca in the hundreds for a product
+    /// with a small world market). Defaults to None, uncapped.
+    ///
+    /// Everything built on the cached rca sees the capped values:
+    /// averages over years, density from continuous rca, and proximity
+    /// from `ProximityBasis::ContinuousRca`. So use it deliberately, not
+    /// only for plots (for those, see `ProductSpace::rca_capped`).
+    /// Given as Balassa rca, as cutoffs are; a cap below the construction
+    /// cutoff leaves no binary rca. `build` panics on an invalid cap (see
+    /// `validate_cutoff`).
+    pub fn rca_cap(mut self, rca_cap: Option<f64>) -> Self {
+        self.rca_cap = rca_cap;
+        self
+    }
+
+    /// how NaN and inf in rca, proximity and density are handled.
+    /// Defaults to `SanitizePolicy::Keep`
+    pub fn sanitize(mut self, sanitize: SanitizePolicy) -> Self {
+        self.sanitize = sanitize;
+        self
+    }
+
+    /// how negative mcps, e.g. from net exports, are handled on build.
+    /// Defaults to `NegativePolicy::Keep`.
+    ///
+    /// Applied after the adjustment. The product space keeps the mcps
+    /// as handled.
+    pub fn negative_policy(mut self, negative_policy: NegativePolicy) -> Self {
+        self.negative_policy = negative_policy;
+        self
+    }
+
+    /// factors (country x product, like the mcps) multiplied into every
+    /// year's mcp before anything else, e.g. to take out re-exports.
+    /// The product space keeps the adjusted mcps, not the raw.
+    ///
+    /// Must be the same shape as the mcps, or `Error::ShapeMismatch`.
+    pub fn adjustment(mut self, adjustment: DMatrix<f64>) -> Self {
+        self.adjustment = Some(adjustment);
+        self
+    }
+
+    /// whether the mcps are export values or shares (e.g. from a panel
+    /// of shares), which changes how rca is calculated; see `rca_with`.
+    /// Defaults to `ValueKind::Levels`.
+    ///
+    /// With shares, every country's total is about 1.0, so the min trade
+    /// filter isn't meaningful.
+    pub fn value_kind(mut self, value_kind: ValueKind) -> Self {
+        self.value_kind = value_kind;
+        self
+    }
+
+    /// how rca is expressed, applied after the sanitize policy, so a
+    /// value it zeroes gets the variant's value for rca 0.0 (e.g. -1.0
+    /// for `Symmetric`), not fair share. Defaults to `RcaVariant::Balassa`.
+    ///
+    /// Cutoffs are still given as Balassa rca; see `RcaVariant`.
+    pub fn rca_variant(mut self, rca_variant: RcaVariant) -> Self {
+        self.rca_variant = rca_variant;
+        self
+    }
+
+    /// called as each year's rca and proximity are built
+    pub fn progress(mut self, progress: ProgressFn) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// countries whose total exports in a year are below the filter
+    /// are zeroed out before rca, so they don't count towards the world
+    /// totals, and get an rca of 0.0. Excluded countries are listed by
+    /// `ProductSpace::excluded_countries`. A year is degenerate if too
+    /// few countries are left after the filter.
+    pub fn min_trade_filter(mut self, min_trade_filter: MinTradeFilter) -> Self {
+        self.min_trade_filter = Some(min_trade_filter);
+        self
+    }
+
+    /// Years with fewer than two exporting countries or exported products
+    /// are left out, since rca and proximity are meaningless for them.
+    ///
+    /// Panics if the rca cutoff is invalid (see `validate_cutoff`), the
+    /// adjustment is the wrong shape, an mcp is negative with
+    /// `NegativePolicy::Error`, or if
+    /// the sanitize policy is `SanitizePolicy::ErrorOnNonFinite` and NaN
+    /// or inf are found; use `try_build` to get the error. In
+    /// particular, only `try_build` honours `ErrorOnNonFinite` without
+    /// panicking.
+    pub fn build(self) -> ProductSpace {
+        match self.build_inner(false) {
+            Ok(ps) => ps,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Like `build`, but returns an error instead of leaving out
+    /// degenerate years (`Error::DegenerateYear`). Also returns `Error::InvalidCutoff`, `Error::ShapeMismatch` for the
+    /// adjustment, `Error::NegativeValue` from the negative policy, and
+    /// `Error::NonFinite` from the sanitize policy.
+    pub fn try_build(self) -> Result<ProductSpace, Error> {
+        self.build_inner(true)
+    }
+
+    pub(crate) fn build_inner(mut self, checked: bool) -> Result<ProductSpace, Error> {
+        validate_cutoff(self.rca_cutoff)?;
+        validate_cutoff(self.rca_cap)?;
+        // NaN would keep nothing, and a negative threshold isn't a proximity
+        validate_cutoff(self.sparse_proximity)?;
+
+        if let Some(adjustment) = self.adjustment.take() {
+            self.adjusted = true;
+            for mcp in self.mcps.values_mut() {
+                if mcp.shape() != adjustment.shape() {
+                    return Err(Error::ShapeMismatch { expected: mcp.shape(), found: adjustment.shape() });
+                }
+                mcp.component_mul_assign(&adjustment);
+            }
+        }
+
+        handle_negatives(&mut self.mcps, self.negative_policy, &self.country_idx, &self.product_idx)?;
+
+        let mut progress = self.progress.take();
+
+        let mut excluded_rows = match self.min_trade_filter {
+            Some(filter) => min_trade_exclusions(&self.mcps, filter),
+            None => HashMap::new(),
+        };
+
+        // judged on what's left after the min trade filter
+        let mut degenerate_years: Vec<u32> = self.mcps.iter()
+            .filter(|(year, mcp)| {
+                let excluded = excluded_rows.get(year).map(|rows| rows.as_slice()).unwrap_or(&[]);
+                is_degenerate(mcp, excluded)
+            })
+            .map(|(year, _)| *year)
+            .collect();
+        degenerate_years.sort();
+
+        if checked {
+            if let Some(year) = degenerate_years.first() {
+                return Err(Error::DegenerateYear { year: *year });
+            }
+        } else {
+            for year in degenerate_years {
+                warn!("year {}: removed, fewer than two exporting countries or exported products", year);
+                self.mcps.remove(&year);
+                excluded_rows.remove(&year);
+            }
+        }
+
+        let mut rcas_by_year = rcas_by_year(&self.mcps, &excluded_rows, self.value_kind, self.leave_one_out, &mut progress);
+        if self.sanitize == SanitizePolicy::ZeroUntraded {
+            zero_untraded(&mut rcas_by_year, &self.product_idx);
+        }
+        sanitize_by_year(&mut rcas_by_year, self.sanitize, "rca")?;
+        if self.rca_variant != RcaVariant::Balassa {
+            for rca in rcas_by_year.values_mut() {
+                let variant = self.rca_variant;
+                rca.apply(|x| variant.apply(x));
+            }
+            // the variant can't make finite rca non-finite, except from
+            // negative mcps kept as given
+            if self.sanitize == SanitizePolicy::ErrorOnNonFinite {
+                sanitize_by_year(&mut rcas_by_year, self.sanitize, "rca")?;
+            }
+        }
+        if let Some(cap) = self.rca_cap {
+            let cap = self.rca_variant.apply(cap);
+            for rca in rcas_by_year.values_mut() {
+                rca.apply(|x| if x > cap { cap } else { x });
+            }
+        }
+
+        let cutoff = binary_cutoff(self.rca_variant, self.rca_cutoff);
+        let rcas_cutoff_by_year = rcas_cutoff_by_year(&rcas_by_year, Some(cutoff), self.comparison);
+
+        let proximity_rcas = match self.proximity_basis {
+            ProximityBasis::BinaryRca => &rcas_cutoff_by_year,
+            ProximityBasis::ContinuousRca => &rcas_by_year,
+        };
+        let (proximities_by_year, sparse_proximities_by_year) = match (self.compute_proximity, self.sparse_proximity) {
+            (false, _) => (HashMap::new(), HashMap::new()),
+            (true, None) => {
+                (proximities_by_year(proximity_rcas, self.proximity_method, self.sanitize, &mut progress)?, HashMap::new())
+            },
+            (true, Some(threshold)) => {
+                (HashMap::new(), sparse_proximities_by_year(proximity_rcas, self.proximity_method, self.sanitize, threshold, &mut progress)?)
+            },
+        };
+
+        let country_names = names_by_index(&self.country_idx);
+        let excluded_countries = excluded_rows.iter()
+            .map(|(year, rows)| {
+                let mut countries: Vec<String> = rows.iter()
+                    .map(|row| country_names[*row].to_owned())
+                    .collect();
+                countries.sort();
+                if !countries.is_empty() {
+                    warn!("year {}: countries below min trade excluded: {:?}", year, countries);
+                }
+                (*year, countries)
+            })
+            .collect();
+
+        Ok(ProductSpace {
+            country_idx: self.country_idx,
+            product_idx: Arc::new(self.product_idx),
+            rca_cutoff: self.rca_cutoff,
+            comparison: self.comparison,
+            proximity_basis: self.proximity_basis,
+            proximity_method: self.proximity_method,
+            value_kind: self.value_kind,
+            rca_variant: self.rca_variant,
+            min_trade_filter: self.min_trade_filter,
+            adjusted: self.adjusted,
+            negative_policy: self.negative_policy,
+            compute_proximity: self.compute_proximity,
+            sparse_proximity: self.sparse_proximity,
+            interpolate_missing_years: self.interpolate_missing_years,
+            leave_one_out: self.leave_one_out,
+            rca_cap: self.rca_cap,
+            sanitize: self.sanitize,
+            excluded_countries,
+            mcps: Some(self.mcps),
+            rcas_by_year,
+            rcas_cutoff_by_year,
+            proximities_by_year,
+            sparse_proximities_by_year,
+            cutoff_products: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+impl ProductSpace {
+    /// Product space from rca already calculated elsewhere, skipping
+    /// mcps to rca. Binary rca and proximity are calculated from it as
+    /// in `new`, with the default builder settings.
+    ///
+    /// There are no raw mcps, so anything using them returns
+    /// `Error::McpsDropped` (see `drop_mcps`).
+    ///
+    /// Returns `Error::InvalidCutoff`, or `Error::ShapeMismatch` if a
+    /// year's rca isn't countries x products.
+    pub fn from_rcas(
+        country_idx: HashMap<String, usize>,
+        product_idx: HashMap<String, usize>,
+        rcas_by_year: HashMap<u32, DMatrix<f64>>,
+        rca_cutoff: Option<f64>,
+        ) -> Result<ProductSpace, Error>
+    {
+        validate_cutoff(rca_cutoff)?;
+
+        let shape = (country_idx.len(), product_idx.len());
+        if let Some(rca) = rcas_by_year.values().find(|rca| rca.shape() != shape) {
+            return Err(Error::ShapeMismatch { expected: shape, found: rca.shape() });
+        }
+
+        let comparison = Comparison::default();
+        let proximity_method = ProximityMethod::default();
+        let sanitize = SanitizePolicy::default();
+
+        let rcas_cutoff_by_year = rcas_cutoff_by_year(&rcas_by_year, rca_cutoff, comparison);
+        let proximities_by_year = proximities_by_year(&rcas_cutoff_by_year, proximity_method, sanitize, &mut None)?;
+
+        Ok(ProductSpace {
+            country_idx: Arc::new(country_idx),
+            product_idx: Arc::new(product_idx),
+            rca_cutoff,
+            comparison,
+            proximity_basis: ProximityBasis::default(),
+            proximity_method,
+            value_kind: ValueKind::default(),
+            rca_variant: RcaVariant::default(),
+            min_trade_filter: None,
+            adjusted: false,
+            negative_policy: NegativePolicy::default(),
+            compute_proximity: true,
+            sparse_proximity: None,
+            interpolate_missing_years: false,
+            leave_one_out: false,
+            rca_cap: None,
+            sanitize,
+            excluded_countries: HashMap::new(),
+            mcps: None,
+            rcas_by_year,
+            rcas_cutoff_by_year,
+            proximities_by_year,
+            sparse_proximities_by_year: HashMap::new(),
+            cutoff_products: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// builder with the same settings this product space was built with,
+    /// for rebuilding from new indexes and mcps
+    pub(crate) fn rebuilder(
+        &self,
+        country_idx: HashMap<String, usize>,
+        product_idx: HashMap<String, usize>,
+        mcps: HashMap<u32, DMatrix<f64>>,
+        ) -> ProductSpaceBuilder
+    {
+        let mut builder = ProductSpaceBuilder::new(country_idx, product_idx, mcps)
+            .rca_cutoff(self.rca_cutoff)
+            .comparison(self.comparison)
+            .proximity_basis(self.proximity_basis)
+            .proximity_method(self.proximity_method)
+            .compute_proximity(self.compute_proximity)
+            .sparse_proximity(self.sparse_proximity)
+            .interpolate_missing_years(self.interpolate_missing_years)
+            .leave_one_out(self.leave_one_out)
+            .rca_cap(self.rca_cap)
+            .sanitize(self.sanitize)
+            .negative_policy(self.negative_policy)
+            .value_kind(self.value_kind)
+            .rca_variant(self.rca_variant);
+
+        if let Some(filter) = self.min_trade_filter {
+            builder = builder.min_trade_filter(filter);
+        }
+        builder.adjusted = self.adjusted;
+
+        builder
+    }
+
+    /// Recalculates the cached binary rca, and the proximity built on it,
+    /// with a new construction cutoff. Cheaper than rebuilding from the
+    /// mcps, since rca is kept.
+    ///
+    /// Returns `Error::InvalidCutoff`, or `Error::NonFinite` from the
+    /// sanitize policy, leaving the product space unchanged.
+    pub fn rebuild_cutoff(&mut self, rca_cutoff: Option<f64>) -> Result<(), Error> {
+        validate_cutoff(rca_cutoff)?;
+
+        let cutoff = binary_cutoff(self.rca_variant, rca_cutoff);
+        let rcas_cutoff = rcas_cutoff_by_year(&self.rcas_by_year, Some(cutoff), self.comparison);
+
+        if self.compute_proximity && self.proximity_basis == ProximityBasis::BinaryRca {
+            match self.sparse_proximity {
+                Some(threshold) => {
+                    self.sparse_proximities_by_year = sparse_proximities_by_year(&rcas_cutoff, self.proximity_method, self.sanitize, threshold, &mut None)?;
+                },
+                None => {
+                    self.proximities_by_year = proximities_by_year(&rcas_cutoff, self.proximity_method, self.sanitize, &mut None)?;
+                },
+            }
+        }
+
+        self.rcas_cutoff_by_year = rcas_cutoff;
+        self.rca_cutoff = rca_cutoff;
+        self.clear_cache();
+
+        Ok(())
+    }
+}
+
+/// How NaN and inf are handled in the cached rca and proximity, and in
+/// density. These come from zero denominators, e.g. a country with no
+/// exports (rca), or a product no country has an advantage in
+/// (proximity, and so density).
+///
+/// Proximity has NaN zeroed for all but `ErrorOnNonFinite`, as it
+/// always has.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub enum SanitizePolicy {
+    /// leave values as calculated
+    Keep,
+    /// replace NaN and inf with 0.0
+    ZeroOut,
+    /// `Error::NonFinite` for the first year with NaN or inf in rca or
+    /// proximity, checked on build. If those are finite, density will
+    /// be too. Only `try_build` returns the error; `build` panics with
+    /// it. Proximity that isn't computed on build is checked as it's
+    /// calculated: `try_proximity` and methods returning `Result` return
+    /// the error, and methods returning `Option` return None.
+    ErrorOnNonFinite,
+    /// rca of 0.0 for products no country exports in a year (whose rca
+    /// is NaN for every country, since c/d is zero), listed at warn
+    /// level. Other values are left as calculated.
+    ///
+    /// This applies to rca calculated from mcps: on build (so to every
+    /// result from the cached rca) and in `ProductSpace::rca_relative_to`.
+    /// Proximity, density and `ProductSpace::world_share` are left as
+    /// with `Keep`, and `ProductSpace::from_rcas` takes rca as given.
+    ZeroUntraded,
+}
+
+impl Default for SanitizePolicy {
+    fn default() -> Self {
+        SanitizePolicy::Keep
+    }
+}
+
+/// How negative mcps are handled on build. rca assumes exports are
+/// nonnegative: a negative value throws off its country's and product's
+/// totals, and so every rca they're part of. These come from e.g. net
+/// exports (exports minus imports). Defaults to `Keep`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub enum NegativePolicy {
+    /// leave negative values as given, as before this option existed
+    Keep,
+    /// `Error::NegativeValue` for the first negative value, by year
+    Error,
+    /// replace negative values with 0.0, so net importers count as not
+    /// exporting, logged at warn level
+    ClampZero,
+    /// use the absolute value, logged at warn level
+    Abs,
+}
+
+impl Default for NegativePolicy {
+    fn default() -> Self {
+        NegativePolicy::Keep
+    }
+}
+
+fn handle_negatives(
+    mcps: &mut HashMap<u32, DMatrix<f64>>,
+    policy: NegativePolicy,
+    country_idx: &HashMap<String, usize>,
+    product_idx: &HashMap<String, usize>,
+    ) -> Result<(), Error>
+{
+    let mut years: Vec<u32> = mcps.keys().cloned().collect();
+    years.sort();
+
+    for year in years {
+        let mcp = mcps.get_mut(&year).expect("year from keys");
+
+        let count = mcp.iter().filter(|x| **x < 0.0).count();
+        if count == 0 {
+            continue;
+        }
+
+        match policy {
+            NegativePolicy::Keep => (),
+            NegativePolicy::Error => {
+                let i = mcp.iter().position(|x| *x < 0.0).expect("counted above");
+                let (row, col) = (i % mcp.nrows(), i / mcp.nrows());
+                return Err(Error::NegativeValue {
+                    year,
+                    country: names_by_index(country_idx)[row].to_owned(),
+                    product: names_by_index(product_idx)[col].to_owned(),
+                });
+            },
+            NegativePolicy::ClampZero => {
+                warn!("year {}: {} negative mcps clamped to 0.0", year, count);
+                mcp.apply(|x| if x < 0.0 { 0.0 } else { x });
+            },
+            NegativePolicy::Abs => {
+                warn!("year {}: {} negative mcps replaced by their absolute value", year, count);
+                mcp.apply(|x| x.abs());
+            },
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn sanitize(
+    m: &mut DMatrix<f64>,
+    policy: SanitizePolicy,
+    year: u32,
+    matrix: &str,
+    ) -> Result<(), Error>
+{
+    match policy {
+        // rca only, see `zero_untraded`
+        SanitizePolicy::Keep | SanitizePolicy::ZeroUntraded => {},
+        SanitizePolicy::ZeroOut => {
+            let count = m.iter().filter(|x| !x.is_finite()).count();
+            if count > 0 {
+                warn!("year {}: {} non-finite {} cells zeroed", year, count, matrix);
+                m.apply(|x| if x.is_finite() { x } else { 0.0 });
+            }
+        },
+        SanitizePolicy::ErrorOnNonFinite => {
+            if !m.iter().all(|x| x.is_finite()) {
+                return Err(Error::NonFinite { year, matrix: matrix.into() });
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn zero_untraded(
+    rcas: &mut HashMap<u32, DMatrix<f64>>,
+    product_idx: &HashMap<String, usize>,
+    )
+{
+    for (year, rca) in rcas.iter_mut() {
+        zero_untraded_year(rca, *year, product_idx);
+    }
+}
+
+// columns that are NaN for every country are products no one exports
+pub(crate) fn zero_untraded_year(
+    rca: &mut DMatrix<f64>,
+    year: u32,
+    product_idx: &HashMap<String, usize>,
+    )
+{
+    let product_names = names_by_index(product_idx);
+
+    let mut untraded = vec![];
+    for j in 0..rca.ncols() {
+        let mut col = rca.column_mut(j);
+        if col.iter().all(|x| x.is_nan()) {
+            col.fill(0.0);
+            untraded.push(product_names[j].to_owned());
+        }
+    }
+
+    if !untraded.is_empty() {
+        untraded.sort();
+        warn!("year {}: untraded products given rca of 0.0: {:?}", year, untraded);
+    }
+}
+
+// by year order, so the error is for the first year
+fn sanitize_by_year(
+    ms: &mut HashMap<u32, DMatrix<f64>>,
+    policy: SanitizePolicy,
+    matrix: &str,
+    ) -> Result<(), Error>
+{
+    let mut years: Vec<_> = ms.keys().cloned().collect();
+    years.sort();
+
+    for year in years {
+        if let Some(m) = ms.get_mut(&year) {
+            sanitize(m, policy, year, matrix)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Minimum total exports for a country in a year to be included in rca
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct MinTradeFilter {
+    pub value: f64,
+}
+
+// with only one country, all rca is 1.0, and with only one product
+// proximity is 1x1
+fn is_degenerate(mcp: &DMatrix<f64>, excluded_rows: &[usize]) -> bool {
+    let countries = mcp.column_sum().iter()
+        .enumerate()
+        .filter(|(i, x)| **x != 0.0 && !excluded_rows.contains(i))
+        .count();
+    let products = mcp.column_iter()
+        .filter(|col| {
+            let total: f64 = col.iter()
+                .enumerate()
+                .filter(|(i, _)| !excluded_rows.contains(i))
+                .map(|(_, x)| x)
+                .sum();
+            total != 0.0
+        })
+        .count();
+
+    countries < 2 || products < 2
+}
+
+// rows (countries) below the min trade value, by year
+fn min_trade_exclusions(
+    mcps: &HashMap<u32, DMatrix<f64>>,
+    filter: MinTradeFilter,
+    ) -> HashMap<u32, Vec<usize>>
+{
+    mcps.iter()
+        .map(|(year, mcp)| {
+            let totals = mcp.column_sum();
+            let rows = totals.iter()
+                .enumerate()
+                .filter(|(_, total)| **total < filter.value)
+                .map(|(i, _)| i)
+                .collect();
+            (*year, rows)
+        })
+        .collect()
+}
+
+fn rcas_by_year(
+    mcps: &HashMap<u32, DMatrix<f64>>,
+    excluded_rows: &HashMap<u32, Vec<usize>>,
+    value_kind: ValueKind,
+    leave_one_out: bool,
+    progress: &mut Option<ProgressFn>,
+    ) -> HashMap<u32, DMatrix<f64>>
+{
+    let rca = |m: &DMatrix<f64>| if leave_one_out {
+        rca_leave_one_out(m, value_kind)
+    } else {
+        rca_with(m, value_kind)
+    };
+
+    mcps.iter()
+        .enumerate()
+        .map(|(i, (year, mcp))| {
+            let rca_matrix = match excluded_rows.get(year) {
+                Some(rows) if !rows.is_empty() => {
+                    let mut filtered = mcp.clone();
+                    for row in rows {
+                        filtered.row_mut(*row).fill(0.0);
+                    }
+
+                    // excluded rows are NaN after rca, from the zero total
+                    let mut rca_matrix = rca(&filtered);
+                    for row in rows {
+                        rca_matrix.row_mut(*row).fill(0.0);
+                    }
+                    rca_matrix
+                },
+                _ => rca(&mcp),
+            };
+            report(progress, IngestPhase::BuildingRca, i + 1);
+            (*year, rca_matrix)
+        })
+        .collect()
+}
+
+/// the cutoff for binary rca (None for the default of 1.0), in the
+/// terms of the rca variant
+pub(crate) fn binary_cutoff(variant: RcaVariant, rca_cutoff: Option<f64>) -> f64 {
+    variant.apply(rca_cutoff.unwrap_or(1.0))
+}
+
+fn rcas_cutoff_by_year(
+    rcas_by_year: &HashMap<u32, DMatrix<f64>>,
+    rca_cutoff: Option<f64>,
+    comparison: Comparison,
+    ) -> HashMap<u32, DMatrix<f64>>
+{
+    rcas_by_year.iter()
+        .map(|(year, rca)| {
+            let mut rca_matrix = rca.clone();
+            apply_fair_share_with(&mut rca_matrix, rca_cutoff, comparison);
+
+            (*year, rca_matrix)
+        })
+        .collect()
+}
+
+fn proximities_by_year(
+    rcas: &HashMap<u32, DMatrix<f64>>,
+    method: ProximityMethod,
+    policy: SanitizePolicy,
+    progress: &mut Option<ProgressFn>,
+    ) -> Result<HashMap<u32, DMatrix<f64>>, Error>
+{
+    let mut years: Vec<_> = rcas.keys().cloned().collect();
+    years.sort();
+
+    years.into_iter()
+        .enumerate()
+        .map(|(i, year)| {
+            let prox = year_proximity(&rcas[&year], method, policy, year)?;
+            report(progress, IngestPhase::BuildingProximity, i + 1);
+            Ok((year, prox))
+        })
+        .collect()
+}
+
+// like proximities_by_year, but each year is made sparse as soon as
+// it's calculated, so only one dense proximity is held at a time
+fn sparse_proximities_by_year(
+    rcas: &HashMap<u32, DMatrix<f64>>,
+    method: ProximityMethod,
+    policy: SanitizePolicy,
+    threshold: f64,
+    progress: &mut Option<ProgressFn>,
+    ) -> Result<HashMap<u32, SparseProximity>, Error>
+{
+    let mut years: Vec<_> = rcas.keys().cloned().collect();
+    years.sort();
+
+    years.into_iter()
+        .enumerate()
+        .map(|(i, year)| {
+            let prox = year_proximity(&rcas[&year], method, policy, year)?;
+            report(progress, IngestPhase::BuildingProximity, i + 1);
+            Ok((year, SparseProximity::from_dense(&prox, threshold)))
+        })
+        .collect()
+}
+
+pub(crate) fn year_proximity(
+    rca: &DMatrix<f64>,
+    method: ProximityMethod,
+    policy: SanitizePolicy,
+    year: u32,
+    ) -> Result<DMatrix<f64>, Error>
+{
+    let mut prox = match method {
+        ProximityMethod::ConditionalProbability => proximity(&rca),
+        ProximityMethod::Cosine => cosine_proximity(&rca),
+    };
+    if policy != SanitizePolicy::ErrorOnNonFinite {
+        // TODO check if this zeroing is ok
+        // This fixed the "everything is Nan issue
+        let count = prox.iter().filter(|x| x.is_nan()).count();
+        if count > 0 {
+            warn!("year {}: {} NaN proximity cells zeroed", year, count);
+            prox.apply(|x| if x.is_nan() { 0.0 } else { x });
+        }
+    }
+    sanitize(&mut prox, policy, year, "proximity")?;
+    Ok(prox)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fair_share, rca, DensityOptions, LOG_RCA_FLOOR};
+
+    fn test_builder() -> ProductSpaceBuilder {
+        let vals = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+        )
+    }
+
+    #[test]
+    fn test_proximity_basis() {
+        let m = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+
+        let ps = test_builder()
+            .rca_cutoff(Some(1.0))
+            .build();
+        let expected = proximity(&fair_share(&rca(&m), Some(1.0)));
+        assert_eq!(ps.proximities_by_year[&2017], expected);
+
+        let ps = test_builder()
+            .rca_cutoff(Some(1.0))
+            .proximity_basis(ProximityBasis::ContinuousRca)
+            .build();
+        let expected = proximity(&rca(&m));
+        assert_eq!(ps.proximities_by_year[&2017], expected);
+    }
+
+    #[test]
+    fn test_proximity_method() {
+        let m = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+
+        let ps = test_builder()
+            .rca_cutoff(Some(1.0))
+            .proximity_method(ProximityMethod::Cosine)
+            .build();
+        let mut expected = cosine_proximity(&fair_share(&rca(&m), Some(1.0)));
+        expected.apply(|x| if x.is_nan() { 0.0 } else { x });
+        assert_eq!(ps.proximities_by_year[&2017], expected);
+
+        // rebuilds keep the method
+        let mut ps = ps;
+        ps.rebuild_cutoff(Some(0.5)).unwrap();
+        let mut expected = cosine_proximity(&fair_share(&rca(&m), Some(0.5)));
+        expected.apply(|x| if x.is_nan() { 0.0 } else { x });
+        assert_eq!(ps.proximities_by_year[&2017], expected);
+    }
+
+    #[test]
+    fn test_min_trade_filter() {
+        // rows: [1,3,5] total 9, [2,4,6] total 12, [10,10,10] total 30
+        let vals = DMatrix::from_vec(3,3,vec![1.0,2.0,10.0,3.0,4.0,10.0,5.0,6.0,10.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+        )
+            .min_trade_filter(MinTradeFilter { value: 10.0 })
+            .build();
+
+        assert_eq!(ps.excluded_countries()[&2017], vec!["a".to_string()]);
+
+        // excluded country doesn't count towards world totals
+        let expected = rca(&DMatrix::from_vec(2,3,vec![2.0,10.0,4.0,10.0,6.0,10.0]));
+        let res = &ps.rcas_by_year[&2017];
+
+        assert_eq!(res.row(0).iter().cloned().collect::<Vec<_>>(), vec![0.0, 0.0, 0.0]);
+        assert_eq!(res[(1,2)], expected[(0,2)]);
+        assert_eq!(res[(2,0)], expected[(1,0)]);
+    }
+
+    #[test]
+    fn test_comparison() {
+        // rca for product 02 is exactly 1.0 for both countries
+        let ps = test_builder()
+            .rca_cutoff(Some(1.0))
+            .comparison(Comparison::Gt)
+            .build();
+
+        let expected = DMatrix::from_vec(2,3,vec![0.0,1.0,0.0,0.0,1.0,0.0]);
+        assert_eq!(ps.rcas_cutoff_by_year[&2017], expected);
+
+        // per-call cutoffs use the same comparison
+        assert_eq!(ps.rca(&[2017], Some(1.0)).unwrap().m, expected);
+    }
+
+    #[test]
+    fn test_compute_proximity() {
+        let ps = test_builder()
+            .rca_cutoff(Some(1.0))
+            .build();
+
+        let lazy = test_builder()
+            .rca_cutoff(Some(1.0))
+            .compute_proximity(false)
+            .build();
+
+        assert!(lazy.proximities_by_year.is_empty());
+        assert!(lazy.proximity_ref(2017).is_none());
+        assert_eq!(lazy.proximity(&[2017]).unwrap().m, ps.proximity(&[2017]).unwrap().m);
+        assert_eq!(lazy.proximity(&[2016, 2017]).unwrap().m, ps.proximity(&[2016, 2017]).unwrap().m);
+        assert_eq!(lazy.density_matrix(&[2017], None), ps.density_matrix(&[2017], None));
+        assert!(lazy.proximity(&[2099]).is_none());
+    }
+
+    #[test]
+    fn test_sparse_proximity() {
+        let mut mcps = HashMap::new();
+        // proximity: [[1, .5, .5], [.5, 1, 0], [.5, 0, 1]]
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+        let builder = || ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2), ("d".to_string(),3)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps.clone(),
+        ).rca_cutoff(Some(1.0));
+
+        let dense = builder().build();
+        let ps = builder().sparse_proximity(Some(0.0)).build();
+
+        assert!(ps.proximities_by_year.is_empty());
+        assert!(ps.proximity_ref(2017).is_none());
+        assert_eq!(ps.sparse_proximity_ref(2017).unwrap().nnz(), 7);
+        assert_eq!(ps.proximity(&[2017]).unwrap().m, dense.proximity(&[2017]).unwrap().m);
+
+        let res = ps.density_matrix(&[2017], Some(1.0)).unwrap();
+        let expected = dense.density_matrix(&[2017], Some(1.0)).unwrap();
+        for (x, y) in res.iter().zip(expected.iter()) {
+            assert!((x - y).abs() < 1e-12);
+        }
+
+        // only the diagonal is kept
+        let ps = builder().sparse_proximity(Some(0.6)).build();
+        assert_eq!(ps.proximity(&[2017]).unwrap().m, DMatrix::identity(3, 3));
+        assert_eq!(ps.density_matrix(&[2017], Some(1.0)), ps.rca_matrix(&[2017], Some(1.0)));
+        assert_eq!(
+            dense.density_matrix_with_options(&[2017], Some(1.0), DensityOptions { min_proximity: Some(0.6), ..Default::default() }),
+            ps.density_matrix(&[2017], Some(1.0)),
+        );
+        assert_eq!(ps.memory_report().proximities.by_year[&2017], 3 * (std::mem::size_of::<usize>() + 8));
+
+        match builder().sparse_proximity(Some(std::f64::NAN)).try_build() {
+            Err(Error::InvalidCutoff { cutoff }) => assert!(cutoff.is_nan()),
+            _ => panic!("NaN threshold should be invalid"),
+        }
+        assert!(builder().sparse_proximity(Some(-0.1)).try_build().is_err());
+    }
+
+    #[test]
+    fn test_sanitize() {
+        // b has no exports, so its rca is NaN
+        let vals = DMatrix::from_vec(3,3,vec![1.0,0.0,2.0,3.0,0.0,4.0,5.0,0.0,6.0]);
+        let builder = || {
+            let mut mcps = HashMap::new();
+            mcps.insert(2017, vals.clone());
+
+            ProductSpaceBuilder::new(
+                [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+                [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+                mcps,
+            )
+        };
+
+        let ps = builder().build();
+        assert!(ps.rcas_by_year[&2017][(1,0)].is_nan());
+
+        let ps = builder().sanitize(SanitizePolicy::ZeroOut).build();
+        assert_eq!(ps.rcas_by_year[&2017][(1,0)], 0.0);
+        assert!(ps.rcas_by_year[&2017].iter().all(|x| x.is_finite()));
+
+        match builder().sanitize(SanitizePolicy::ErrorOnNonFinite).try_build() {
+            Err(Error::NonFinite { year, matrix }) => {
+                assert_eq!(year, 2017);
+                assert_eq!(matrix, "rca");
+            },
+            _ => panic!("expected non-finite error"),
+        }
+
+        assert!(test_builder().sanitize(SanitizePolicy::ErrorOnNonFinite).try_build().is_ok());
+
+        // proximity not computed on build is checked as it's calculated.
+        // At 1.1 no country has 02 or 03, so their proximity is NaN
+        let lazy = || test_builder().rca_cutoff(Some(1.1)).compute_proximity(false);
+
+        let ps = lazy().sanitize(SanitizePolicy::ErrorOnNonFinite).try_build().unwrap();
+        match ps.try_proximity(&[2017]) {
+            Err(Error::NonFinite { year, matrix }) => {
+                assert_eq!(year, 2017);
+                assert_eq!(matrix, "proximity");
+            },
+            _ => panic!("expected non-finite error"),
+        }
+        assert!(ps.proximity(&[2017]).is_none());
+        assert!(ps.density_series("a", "01", Some(1.1)).is_err());
+
+        assert!(lazy().build().try_proximity(&[2017]).is_ok());
+    }
+
+    #[test]
+    fn test_zero_untraded() {
+        // no one exports 03
+        let vals = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,0.0,0.0]);
+        let builder = || {
+            let mut mcps = HashMap::new();
+            mcps.insert(2017, vals.clone());
+
+            ProductSpaceBuilder::new(
+                [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+                [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+                mcps,
+            )
+        };
+
+        let ps = builder().build();
+        assert!(ps.rcas_by_year[&2017].column(2).iter().all(|x| x.is_nan()));
+
+        let ps = builder().sanitize(SanitizePolicy::ZeroUntraded).build();
+        let res = &ps.rcas_by_year[&2017];
+        assert!(res.column(2).iter().all(|x| *x == 0.0));
+        assert_eq!(res.columns(0, 2).into_owned(), rca(&vals).columns(0, 2).into_owned());
+        assert_eq!(ps.rcas_cutoff_by_year[&2017].column(2).iter().sum::<f64>(), 0.0);
+    }
+
+    #[test]
+    fn test_from_rcas() {
+        let m = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+        let built = test_builder().rca_cutoff(Some(1.0)).build();
+
+        let mut rcas = HashMap::new();
+        rcas.insert(2017, rca(&m));
+        let ps = ProductSpace::from_rcas(
+            (*built.country_idx).clone(),
+            (*built.product_idx).clone(),
+            rcas,
+            Some(1.0),
+        ).unwrap();
+
+        assert_eq!(ps.rcas_cutoff_by_year, built.rcas_cutoff_by_year);
+        assert_eq!(ps.proximities_by_year, built.proximities_by_year);
+        assert_eq!(ps.density_matrix(&[2017], None), built.density_matrix(&[2017], None));
+        assert!(ps.mcps().is_err());
+
+        let mut rcas = HashMap::new();
+        rcas.insert(2017, DMatrix::zeros(3,3));
+        match ProductSpace::from_rcas((*built.country_idx).clone(), (*built.product_idx).clone(), rcas, None) {
+            Err(Error::ShapeMismatch { expected, found }) => {
+                assert_eq!(expected, (2,3));
+                assert_eq!(found, (3,3));
+            },
+            _ => panic!("expected shape mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_value_kind() {
+        let shares = DMatrix::from_vec(2,2,vec![0.25,0.5,0.75,0.3]);
+        let builder = || {
+            let mut mcps = HashMap::new();
+            mcps.insert(2017, shares.clone());
+
+            ProductSpaceBuilder::new(
+                [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+                [("01".to_string(),0usize), ("02".to_string(),1)].iter().cloned().collect(),
+                mcps,
+            )
+        };
+
+        let ps = builder().value_kind(ValueKind::Shares).build();
+        assert_eq!(ps.rcas_by_year[&2017], rca_with(&shares, ValueKind::Shares));
+
+        let ps = builder().build();
+        assert_eq!(ps.rcas_by_year[&2017], rca(&shares));
+    }
+
+    #[test]
+    fn test_invalid_cutoff() {
+        match test_builder().rca_cutoff(Some(-1.0)).try_build() {
+            Err(Error::InvalidCutoff { cutoff }) => assert_eq!(cutoff, -1.0),
+            _ => panic!("expected invalid cutoff"),
+        }
+
+        let mut ps = test_builder().rca_cutoff(Some(1.0)).build();
+        assert!(ps.rebuild_cutoff(Some(std::f64::NAN)).is_err());
+        assert_eq!(ps.build_cutoff(), Some(1.0));
+
+        // per-call cutoffs
+        assert!(ps.rca(&[2017], Some(std::f64::NAN)).is_none());
+        assert!(ps.density(&[2017], Some(-0.5)).is_none());
+        match ps.rca_series("a", "01", Some(-1.0)) {
+            Err(Error::InvalidCutoff { .. }) => (),
+            _ => panic!("expected invalid cutoff"),
+        }
+    }
+
+    #[test]
+    fn test_adjustment() {
+        // halve a's exports of 01
+        let adjustment = DMatrix::from_vec(2,3,vec![0.5,1.0,1.0,1.0,1.0,1.0]);
+        let ps = test_builder()
+            .adjustment(adjustment)
+            .build();
+
+        let expected = rca(&DMatrix::from_vec(2,3,vec![0.5,2.0,3.0,4.0,5.0,6.0]));
+        assert_eq!(ps.rcas_by_year[&2017], expected);
+        assert_eq!(ps.mcps().unwrap()[&2017][(0,0)], 0.5);
+
+        match test_builder().adjustment(DMatrix::from_element(2,2,1.0)).try_build() {
+            Err(Error::ShapeMismatch { expected, found }) => {
+                assert_eq!(expected, (2,3));
+                assert_eq!(found, (2,2));
+            },
+            _ => panic!("expected shape mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_negative_policy() {
+        // b's net exports of 01 are negative
+        let negative_builder = || {
+            let mut mcps = HashMap::new();
+            mcps.insert(2017, DMatrix::from_vec(2,3,vec![1.0,-2.0,3.0,4.0,5.0,6.0]));
+
+            ProductSpaceBuilder::new(
+                [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+                [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+                mcps,
+            )
+        };
+
+        // kept as given by default
+        let ps = negative_builder().build();
+        assert_eq!(ps.mcps().unwrap()[&2017][(1,0)], -2.0);
+
+        match negative_builder().negative_policy(NegativePolicy::Error).try_build() {
+            Err(Error::NegativeValue { year, country, product }) => {
+                assert_eq!(year, 2017);
+                assert_eq!(country, "b");
+                assert_eq!(product, "01");
+            },
+            _ => panic!("expected negative value"),
+        }
+
+        let ps = negative_builder().negative_policy(NegativePolicy::ClampZero).build();
+        let expected = rca(&DMatrix::from_vec(2,3,vec![1.0,0.0,3.0,4.0,5.0,6.0]));
+        assert_eq!(ps.rcas_by_year[&2017], expected);
+        assert_eq!(ps.mcps().unwrap()[&2017][(1,0)], 0.0);
+
+        let ps = negative_builder().negative_policy(NegativePolicy::Abs).build();
+        let expected = rca(&DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]));
+        assert_eq!(ps.rcas_by_year[&2017], expected);
+    }
+
+    #[test]
+    fn test_rca_variant() {
+        let m = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+        let balassa = test_builder().build();
+
+        for variant in &[RcaVariant::Symmetric, RcaVariant::Log] {
+            let mut ps = test_builder().rca_variant(*variant).build();
+
+            let mut expected = rca(&m);
+            expected.apply(|x| variant.apply(x));
+            assert_eq!(ps.rcas_by_year[&2017], expected);
+
+            // cutoffs are still balassa rca, so binary rca is the same
+            assert_eq!(ps.rcas_cutoff_by_year, balassa.rcas_cutoff_by_year);
+            assert_eq!(ps.proximities_by_year, balassa.proximities_by_year);
+            assert_eq!(ps.rca_matrix(&[2017], Some(0.9)), balassa.rca_matrix(&[2017], Some(0.9)));
+            assert_eq!(ps.binary_rca_matrix(&[2017], Some(1.1)), balassa.binary_rca_matrix(&[2017], Some(1.1)));
+            assert_eq!(ps.rca_series("a", "02", Some(1.0)).unwrap(), balassa.rca_series("a", "02", Some(1.0)).unwrap());
+
+            let mut balassa = test_builder().build();
+            ps.rebuild_cutoff(Some(0.5)).unwrap();
+            balassa.rebuild_cutoff(Some(0.5)).unwrap();
+            assert_eq!(ps.rcas_cutoff_by_year, balassa.rcas_cutoff_by_year);
+        }
+
+        // rca of 0.0 stays finite in log terms
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,3,vec![1.0,0.0,2.0,4.0,3.0,5.0]));
+        let ps = ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+        )
+        .rca_variant(RcaVariant::Log)
+        .sanitize(SanitizePolicy::ErrorOnNonFinite)
+        .try_build()
+        .unwrap();
+        assert_eq!(ps.rcas_by_year[&2017][(1, 0)], LOG_RCA_FLOOR);
+        assert_eq!(ps.rcas_cutoff_by_year[&2017][(1, 0)], 0.0);
+        assert!(ps.density_matrix(&[2017], None).unwrap().iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn test_rca_cap() {
+        let m = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+        let uncapped = test_builder().rca_cutoff(Some(1.0)).build();
+        let ps = test_builder().rca_cutoff(Some(1.0)).rca_cap(Some(1.1)).build();
+
+        let mut expected = rca(&m);
+        expected.apply(|x| x.min(1.1));
+        assert_eq!(ps.rcas_by_year[&2017], expected);
+        assert_eq!(ps.rcas_cutoff_by_year, uncapped.rcas_cutoff_by_year);
+
+        // per call, after the cached rca
+        let res = uncapped.rca_capped(&[2017], None, Some(1.1)).unwrap();
+        assert_eq!(res.m, expected);
+        assert_eq!(uncapped.rca_capped(&[2017], Some(1.0), Some(1.1)).unwrap().m, uncapped.rcas_cutoff_by_year[&2017]);
+        assert!(uncapped.rca_capped(&[2017], None, Some(-1.0)).is_none());
+
+        assert!(test_builder().rca_cap(Some(std::f64::NAN)).try_build().is_err());
+    }
+
+    #[test]
+    fn test_leave_one_out() {
+        let m = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+        let ps = test_builder().rca_cutoff(Some(1.0)).leave_one_out(true).build();
+
+        assert_eq!(ps.rcas_by_year[&2017], rca_leave_one_out(&m, ValueKind::Levels));
+        assert_eq!(ps.rcas_cutoff_by_year[&2017], DMatrix::from_vec(2,3,vec![0.0,1.0,1.0,1.0,1.0,0.0]));
+
+        let ps = test_builder().build();
+        assert_eq!(ps.rcas_by_year[&2017], rca(&m));
+
+        // a is the only exporter of 01, so it has the ordinary rca there,
+        // whatever the sanitize policy
+        let m = DMatrix::from_vec(2,3,vec![1.0,0.0,2.0,4.0,3.0,5.0]);
+        let builder = |sanitize| {
+            let mut mcps = HashMap::new();
+            mcps.insert(2017, m.clone());
+
+            ProductSpaceBuilder::new(
+                [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+                [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+                mcps,
+            )
+            .rca_cutoff(Some(1.0))
+            .leave_one_out(true)
+            .sanitize(sanitize)
+        };
+
+        for sanitize in &[
+            SanitizePolicy::Keep,
+            SanitizePolicy::ZeroOut,
+            SanitizePolicy::ErrorOnNonFinite,
+            SanitizePolicy::ZeroUntraded,
+        ] {
+            let ps = builder(*sanitize).try_build().unwrap();
+            let rca_2017 = &ps.rcas_by_year[&2017];
+            assert_eq!(rca_2017[(0, 0)], rca(&m)[(0, 0)]);
+            assert_eq!(ps.rcas_cutoff_by_year[&2017][(0, 0)], 1.0);
+            assert!(rca_2017.iter().all(|x| x.is_finite()));
+        }
+    }
+
+    #[test]
+    fn test_rebuild_cutoff() {
+        let mut ps = test_builder()
+            .rca_cutoff(Some(1.0))
+            .build();
+
+        ps.rebuild_cutoff(Some(1.1)).unwrap();
+
+        let expected = test_builder()
+            .rca_cutoff(Some(1.1))
+            .build();
+
+        assert_eq!(ps.rca_cutoff, Some(1.1));
+        assert_eq!(ps.rcas_cutoff_by_year, expected.rcas_cutoff_by_year);
+        assert_eq!(ps.proximities_by_year, expected.proximities_by_year);
+    }
+
+    #[test]
+    fn test_degenerate_single_country() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]));
+        // only a exports
+        mcps.insert(2017, DMatrix::from_vec(2,3,vec![1.0,0.0,3.0,0.0,5.0,0.0]));
+
+        let builder = || ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps.clone(),
+        );
+
+        match builder().try_build() {
+            Err(Error::DegenerateYear { year }) => assert_eq!(year, 2017),
+            _ => panic!("expected degenerate year"),
+        }
+
+        let ps = builder().build();
+        assert!(ps.rcas_by_year.get(&2017).is_none());
+        assert!(ps.rcas_by_year.get(&2016).is_some());
+    }
+
+    #[test]
+    fn test_degenerate_single_product() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,1,vec![1.0,2.0]));
+
+        let builder = || ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize)].iter().cloned().collect(),
+            mcps.clone(),
+        );
+
+        match builder().try_build() {
+            Err(Error::DegenerateYear { year }) => assert_eq!(year, 2017),
+            _ => panic!("expected degenerate year"),
+        }
+
+        assert!(builder().build().proximities_by_year.is_empty());
+    }
+
+    #[test]
+    fn test_degenerate_after_min_trade_filter() {
+        let mut mcps = HashMap::new();
+        // totals a 9, b 120: only b is left after the filter
+        mcps.insert(2016, DMatrix::from_vec(2,3,vec![1.0,20.0,3.0,40.0,5.0,60.0]));
+        mcps.insert(2017, DMatrix::from_vec(2,3,vec![10.0,20.0,30.0,40.0,50.0,60.0]));
+
+        let builder = || ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps.clone(),
+        ).min_trade_filter(MinTradeFilter { value: 10.0 });
+
+        match builder().try_build() {
+            Err(Error::DegenerateYear { year }) => assert_eq!(year, 2016),
+            _ => panic!("expected degenerate year"),
+        }
+
+        let ps = builder().build();
+        assert!(ps.rcas_by_year.get(&2016).is_none());
+        assert!(ps.excluded_countries().get(&2016).is_none());
+        assert!(ps.rcas_by_year.get(&2017).is_some());
+    }
+
+    #[test]
+    fn test_try_build() {
+        let ps = test_builder()
+            .rca_cutoff(Some(1.0))
+            .try_build();
+
+        assert!(ps.is_ok());
+    }
+}
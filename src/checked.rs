@@ -0,0 +1,145 @@
+use nalgebra::{DMatrix, DVector};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use crate::{Complexity, Density, Error, Proximity, Provenance, Rca};
+
+// results as serialized, checked against their indexes before becoming
+// the result (see `Rca`). A matrix or vector of the wrong size for its
+// indexes would index out of bounds, or silently give another name's
+// value, on the first `get`.
+
+#[derive(Deserialize)]
+pub(crate) struct RcaData {
+    country_idx: HashMap<String, usize>,
+    product_idx: HashMap<String, usize>,
+    m: DMatrix<f64>,
+    provenance: Provenance,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ProximityData {
+    product_idx: HashMap<String, usize>,
+    m: DMatrix<f64>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct DensityData {
+    country_idx: HashMap<String, usize>,
+    product_idx: HashMap<String, usize>,
+    m: DMatrix<f64>,
+    provenance: Provenance,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ComplexityData {
+    country_idx: HashMap<String, usize>,
+    product_idx: HashMap<String, usize>,
+    eci: DVector<f64>,
+    pci: DVector<f64>,
+    provenance: Provenance,
+}
+
+// an index has to number its names 0..len, each position once
+fn check_index(idx: &HashMap<String, usize>, index: &str) -> Result<(), Error> {
+    let mut seen = vec![false; idx.len()];
+    for i in idx.values() {
+        match seen.get_mut(*i) {
+            Some(seen) if !*seen => *seen = true,
+            _ => return Err(Error::IndexMismatch { index: index.into() }),
+        }
+    }
+    Ok(())
+}
+
+fn check_matrix(
+    country_idx: &HashMap<String, usize>,
+    product_idx: &HashMap<String, usize>,
+    m: &DMatrix<f64>,
+    ) -> Result<(), Error>
+{
+    check_index(country_idx, "country")?;
+    check_index(product_idx, "product")?;
+
+    let expected = (country_idx.len(), product_idx.len());
+    if m.shape() != expected {
+        return Err(Error::ShapeMismatch { expected, found: m.shape() });
+    }
+    Ok(())
+}
+
+fn check_len(idx: &HashMap<String, usize>, v: &DVector<f64>) -> Result<(), Error> {
+    if v.len() != idx.len() {
+        return Err(Error::LengthMismatch { expected: idx.len(), found: v.len() });
+    }
+    Ok(())
+}
+
+impl TryFrom<RcaData> for Rca {
+    type Error = Error;
+
+    fn try_from(data: RcaData) -> Result<Self, Error> {
+        check_matrix(&data.country_idx, &data.product_idx, &data.m)?;
+
+        Ok(Rca {
+            country_idx: Arc::new(data.country_idx),
+            product_idx: Arc::new(data.product_idx),
+            m: data.m,
+            provenance: data.provenance,
+        })
+    }
+}
+
+impl TryFrom<ProximityData> for Proximity {
+    type Error = Error;
+
+    fn try_from(data: ProximityData) -> Result<Self, Error> {
+        check_index(&data.product_idx, "product")?;
+
+        let expected = (data.product_idx.len(), data.product_idx.len());
+        if data.m.shape() != expected {
+            return Err(Error::ShapeMismatch { expected, found: data.m.shape() });
+        }
+
+        Ok(Proximity {
+            product_idx: Arc::new(data.product_idx),
+            m: data.m,
+        })
+    }
+}
+
+impl TryFrom<DensityData> for Density {
+    type Error = Error;
+
+    fn try_from(data: DensityData) -> Result<Self, Error> {
+        check_matrix(&data.country_idx, &data.product_idx, &data.m)?;
+
+        Ok(Density {
+            country_idx: Arc::new(data.country_idx),
+            product_idx: Arc::new(data.product_idx),
+            m: data.m,
+            provenance: data.provenance,
+        })
+    }
+}
+
+impl TryFrom<ComplexityData> for Complexity {
+    type Error = Error;
+
+    fn try_from(data: ComplexityData) -> Result<Self, Error> {
+        check_index(&data.country_idx, "country")?;
+        check_index(&data.product_idx, "product")?;
+        check_len(&data.country_idx, &data.eci)?;
+        check_len(&data.product_idx, &data.pci)?;
+
+        Ok(Complexity {
+            country_idx: Arc::new(data.country_idx),
+            product_idx: Arc::new(data.product_idx),
+            eci: data.eci,
+            pci: data.pci,
+            provenance: data.provenance,
+        })
+    }
+}
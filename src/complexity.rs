@@ -1,4 +1,53 @@
-use nalgebra::{DMatrix, convert};
+use nalgebra::{DMatrix, DVector, convert};
+use std::cmp::Ordering;
+
+use crate::stats::pearson;
+use crate::Error;
+
+// country reflections in `complexity`. Products get one more, on
+// the last pass (from simoes)
+const COUNTRY_REFLECTIONS: usize = 18;
+
+// the method of reflections on a binary rca: each item is (kc, kp) after
+// one more pass, both from the previous pass's values. Never ends.
+struct Reflections<'a> {
+    rca: &'a DMatrix<f64>,
+    kc0: DVector<f64>,
+    kp0: DVector<f64>,
+    kc: DVector<f64>,
+    kp: DVector<f64>,
+}
+
+impl<'a> Reflections<'a> {
+    fn new(rca: &'a DMatrix<f64>) -> Self {
+        // k product
+        let kp0 = rca.row_sum_tr();
+        // k country
+        let kc0 = rca.column_sum();
+
+        Reflections {
+            rca,
+            kc: kc0.clone(),
+            kp: kp0.clone(),
+            kc0,
+            kp0,
+        }
+    }
+}
+
+impl<'a> Iterator for Reflections<'a> {
+    type Item = (DVector<f64>, DVector<f64>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // each depends on the other's value at the beginning of the pass
+        let kp = convert((self.rca.transpose() * &self.kc).component_div(&self.kp0));
+        let kc = (self.rca * &self.kp).component_div(&self.kc0);
+        self.kp = kp;
+        self.kc = kc;
+
+        Some((self.kc.clone(), self.kp.clone()))
+    }
+}
 
 // rca input is matrix of rca, where
 // - col indexes are product
@@ -10,28 +59,14 @@ pub fn complexity(rca: &DMatrix<f64>) -> (DMatrix<f64>, DMatrix<f64>) {
     // dot is just multiplication, not dot product.
     // mul/div is componentwise, not sweeping or otherwise
 
-    // k product
     let kp0 = rca.row_sum_tr();
-    let mut kp = kp0.clone();
-
-    // k country
     let kc0 = rca.column_sum();
-    let mut kc = kc0.clone();
 
     // (from simoes, it loops 10 times but I don't know why.
     // On last pass, does additional mult by kp)
-    for i in 0..19 {
-        // temps needed because the calculations in the next step
-        // modify kc and kp, but depend on their value at the beginning
-        // of the loop pass
-        let kc_temp = kc.clone();
-        let kp_temp = kp.clone();
-
-        kp = convert((rca.transpose() * &kc_temp).component_div(&kp0));
-        if i < 18 {
-            kc = (rca * &kp_temp).component_div(&kc0);
-        }
-    }
+    let mut reflections = Reflections::new(rca);
+    let (kc, _) = reflections.nth(COUNTRY_REFLECTIONS - 1).expect("reflections never end");
+    let (_, kp) = reflections.next().expect("reflections never end");
     println!("kp0: {}", kp0);
     println!("kc0: {}", kc0);
     println!("kp: {}", kp);
@@ -56,6 +91,196 @@ pub fn complexity(rca: &DMatrix<f64>) -> (DMatrix<f64>, DMatrix<f64>) {
     (convert(geo_complexity), convert(prod_complexity))
 }
 
+/// Value-weighted complexity, from each country's export shares
+/// instead of binary rca. Rows of `m` (export values, countries x
+/// products) are divided by the country's total exports, so each sums
+/// to 1.0 and magnitude isn't thrown away.
+///
+/// Reflections stall on shares (every country starts with the same
+/// total), so this uses the eigenvector form: eci is the eigenvector of
+/// the second largest eigenvalue of
+///
+/// M(c, c') = sum_p(s_cp * s_c'p / s_p), where s_p = sum_c(s_cp)
+///
+/// and pci is one reflection from it. Both are standardized like
+/// `complexity`, but the sign is arbitrary (see `orient`), and they're
+/// not directly comparable to eci and pci from binary rca.
+///
+/// Everything is NaN if a country has no exports, or there are fewer
+/// than two countries. Products no one exports are NaN.
+pub fn complexity_weighted(m: &DMatrix<f64>) -> (DMatrix<f64>, DMatrix<f64>) {
+    let totals = m.column_sum();
+    let shares = DMatrix::from_fn(m.nrows(), m.ncols(), |i, j| m[(i, j)] / totals[i]);
+
+    if m.nrows() < 2 || shares.iter().any(|x| !x.is_finite()) {
+        return (
+            DMatrix::from_element(m.nrows(), 1, std::f64::NAN),
+            DMatrix::from_element(m.ncols(), 1, std::f64::NAN),
+        );
+    }
+
+    let kp0 = shares.row_sum_tr();
+
+    // products no one exports add nothing to M
+    let weighted = DMatrix::from_fn(m.nrows(), m.ncols(), |i, j| {
+        if kp0[j] == 0.0 { 0.0 } else { shares[(i, j)] / kp0[j] }
+    });
+
+    // symmetric, since rows of shares sum to 1.0. The largest
+    // eigenvalue is 1.0, for a constant vector
+    let eigen = (&weighted * shares.transpose()).symmetric_eigen();
+
+    let mut order: Vec<usize> = (0..eigen.eigenvalues.len()).collect();
+    order.sort_by(|a, b| {
+        eigen.eigenvalues[*b].partial_cmp(&eigen.eigenvalues[*a]).unwrap_or(Ordering::Equal)
+    });
+
+    let mut kc = DMatrix::from_iterator(m.nrows(), 1, eigen.eigenvectors.column(order[1]).iter().cloned());
+    let mut kp = (shares.transpose() * &kc).component_div(&DMatrix::from_iterator(m.ncols(), 1, kp0.iter().cloned()));
+
+    let kc_mean = mean(&kc);
+    let kc_std = std(&kc, None);
+    kc.apply(|x| (x - kc_mean) / kc_std);
+
+    let kp_mean = mean(&kp);
+    let kp_std = std(&kp, None);
+    kp.apply(|x| (x - kp_mean) / kp_std);
+
+    (kc, kp)
+}
+
+/// The method of reflections doesn't fix the sign of eci and pci, so it
+/// can flip between years. This flips both (pci is tied to eci) if eci is
+/// negatively correlated with `reference`, one value per country, e.g.
+/// diversity or last year's eci. They're left as is if the correlation
+/// is undefined.
+///
+/// Returns `Error::LengthMismatch` unless there's one reference value
+/// per country.
+pub fn orient(eci: &mut DMatrix<f64>, pci: &mut DMatrix<f64>, reference: &[f64]) -> Result<(), Error> {
+    if eci.len() != reference.len() {
+        return Err(Error::LengthMismatch { expected: eci.len(), found: reference.len() });
+    }
+
+    if pearson(eci.as_slice(), reference) < 0.0 {
+        eci.apply(|x| -x);
+        pci.apply(|x| -x);
+    }
+    Ok(())
+}
+
+// complexity, oriented so eci increases with diversity
+pub(crate) fn complexity_by_diversity(rca: &DMatrix<f64>) -> (DMatrix<f64>, DMatrix<f64>) {
+    let (mut eci, mut pci) = complexity(rca);
+    let diversity = rca.column_sum();
+
+    orient(&mut eci, &mut pci, diversity.as_slice())
+        .expect("one diversity per country");
+
+    (eci, pci)
+}
+
+/// eci as the average pci of the products a country exports, with pci
+/// supplied rather than calculated, e.g. held fixed from a reference year.
+///
+/// rca input is the binary rca, where
+/// - col indexes are product
+/// - row indexes are countries
+///
+/// The result is in pci units and not standardized, so that it stays
+/// comparable across years calculated against the same pci. Countries
+/// that export nothing are NaN.
+pub fn eci_from_pci(rca: &DMatrix<f64>, pci: &DVector<f64>) -> DVector<f64> {
+    let kc0 = rca.column_sum();
+
+    (rca * pci).component_div(&kc0)
+}
+
+// largest change in standardized eci between the last two country
+// reflections of the same parity in `complexity`, to tell whether its
+// fixed number of passes was enough. NaN if either is undefined.
+pub(crate) fn reflection_change(rca: &DMatrix<f64>) -> f64 {
+    let mut reflections = Reflections::new(rca);
+    let (prev, _) = reflections.nth(COUNTRY_REFLECTIONS - 3).expect("reflections never end");
+    let (kc, _) = reflections.nth(1).expect("reflections never end");
+
+    let standardize = |v: &DVector<f64>| {
+        let mut m = DMatrix::from_iterator(v.len(), 1, v.iter().cloned());
+        let m_mean = mean(&m);
+        let m_std = std(&m, None);
+        m.apply(|x| (x - m_mean) / m_std);
+        m
+    };
+
+    let diffs: Vec<f64> = standardize(&kc).iter()
+        .zip(standardize(&prev).iter())
+        .map(|(x, y)| (x - y).abs())
+        .collect();
+
+    if diffs.iter().any(|x| x.is_nan()) {
+        std::f64::NAN
+    } else {
+        diffs.into_iter().fold(0.0, f64::max)
+    }
+}
+
+/// k_c,1, the first reflection for countries: the average ubiquity of
+/// the products each country has binary rca in. Countries that export
+/// nothing are NaN.
+///
+/// rca input is the binary rca, countries x products.
+pub fn avg_ubiquity(rca: &DMatrix<f64>) -> DVector<f64> {
+    let kc0 = rca.column_sum();
+    let kp0 = rca.row_sum_tr();
+
+    (rca * kp0).component_div(&kc0)
+}
+
+/// k_p,1, the first reflection for products: the average diversity of
+/// the countries with binary rca in each product. Products no country
+/// exports are NaN.
+///
+/// rca input is the binary rca, countries x products.
+pub fn avg_diversity(rca: &DMatrix<f64>) -> DVector<f64> {
+    let kc0 = rca.column_sum();
+    let kp0 = rca.row_sum_tr();
+
+    rca.tr_mul(&kc0).component_div(&kp0)
+}
+
+/// The country x country matrix whose eigenvectors the method of
+/// reflections converges to:
+///
+/// M~(c, c') = sum_p(M_cp * M_c'p / k_p,0) / k_c,0
+///
+/// from the binary rca `M` (countries x products), with diversity
+/// k_c,0 and ubiquity k_p,0. Two passes of reflections are
+/// k_c,n+2 = M~ * k_c,n, so eci is the (standardized) eigenvector of
+/// the second largest eigenvalue; the largest is 1.0, for a constant
+/// vector.
+///
+/// Rows and columns are in the rca's country order. Each row sums to
+/// 1.0 (a country's transitions, through a product it exports, to the
+/// countries exporting that product), so it's row stochastic but not
+/// symmetric. Products no country exports add nothing, and the rows of
+/// countries that export nothing are NaN.
+pub fn reflection_matrix(rca: &DMatrix<f64>) -> DMatrix<f64> {
+    let kc0 = rca.column_sum();
+    let kp0 = rca.row_sum();
+
+    let weighted = DMatrix::from_fn(rca.nrows(), rca.ncols(), |c, p| {
+        if kp0[p] == 0.0 { 0.0 } else { rca[(c, p)] / kp0[p] }
+    });
+
+    let mut res = weighted * rca.transpose();
+    for c in 0..res.nrows() {
+        let mut row = res.row_mut(c);
+        row.apply(|x| x / kc0[c]);
+    }
+
+    res
+}
+
 // only for <U1, Dynamic> vectors
 fn mean(m: &DMatrix<f64>) -> f64 {
     assert!(m.ncols() == 1);
@@ -102,6 +327,38 @@ mod tests {
     use super::*;
     use crate::rca;
 
+    #[test]
+    fn test_reflection_matrix() {
+        // d exports nothing, and no one exports 04
+        let rca = DMatrix::from_row_slice(4,4,&[
+            1.0, 1.0, 1.0, 0.0,
+            1.0, 0.0, 0.0, 0.0,
+            1.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0,
+        ]);
+
+        let res = reflection_matrix(&rca);
+        assert_eq!(res.shape(), (4, 4));
+
+        // a and b share only 01, with ubiquity 3
+        assert!((res[(0, 1)] - 1.0 / 9.0).abs() < 1e-12);
+        assert!((res[(0, 0)] - 11.0 / 18.0).abs() < 1e-12);
+        assert_eq!(res[(0, 3)], 0.0);
+        for c in 0..3 {
+            assert!((res.row(c).sum() - 1.0).abs() < 1e-12);
+        }
+        assert!(res.row(3).iter().all(|x| x.is_nan()));
+
+        // two passes of reflections
+        let rca = rca.slice((0, 0), (3, 3)).into_owned();
+        let kc0 = rca.column_sum();
+        let kc2 = (&rca * avg_diversity(&rca)).component_div(&kc0);
+        let res = reflection_matrix(&rca) * kc0;
+        for (x, y) in kc2.iter().zip(res.iter()) {
+            assert!((x - y).abs() < 1e-12);
+        }
+    }
+
     #[test]
     fn test_std_ddof0() {
         let m = DMatrix::from_vec(3,1,vec![1.0, 3.0, 5.0]);
@@ -124,6 +381,114 @@ mod tests {
         assert_eq!(std_dev, 0.0006021919193416322);
     }
 
+    #[test]
+    fn test_reflection_change() {
+        // two blocks, which settle after one pass
+        let rca = DMatrix::from_row_slice(3,3,&[
+            0.0, 1.0, 1.0,
+            1.0, 0.0, 0.0,
+            1.0, 0.0, 0.0,
+        ]);
+        assert_eq!(reflection_change(&rca), 0.0);
+
+        let rca = DMatrix::from_row_slice(5,5,&[
+            1.0, 0.0, 0.0, 1.0, 0.0,
+            1.0, 1.0, 0.0, 0.0, 1.0,
+            0.0, 1.0, 1.0, 0.0, 1.0,
+            0.0, 0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 1.0, 1.0,
+        ]);
+        let change = reflection_change(&rca);
+        assert!((change - 0.058234489991149885).abs() < 1e-9);
+
+        let rca = DMatrix::from_row_slice(2,2,&[1.0, 0.0, 0.0, 0.0]);
+        assert!(reflection_change(&rca).is_nan());
+    }
+
+    #[test]
+    fn test_eci_from_pci() {
+        let rca = DMatrix::from_vec(2,3,vec![1.0,0.0,1.0,1.0,0.0,1.0]);
+        let pci = DVector::from_vec(vec![1.0, -1.0, 3.0]);
+
+        let eci = eci_from_pci(&rca, &pci);
+
+        assert_eq!(eci, DVector::from_vec(vec![0.0, 1.0]));
+    }
+
+    #[test]
+    fn test_avg_ubiquity_diversity() {
+        // d exports nothing, and no one exports 04
+        let rca = DMatrix::from_row_slice(4,4,&[
+            1.0, 1.0, 1.0, 0.0,
+            1.0, 0.0, 0.0, 0.0,
+            1.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0,
+        ]);
+
+        // ubiquities are [3, 2, 1, 0]
+        let kc1 = avg_ubiquity(&rca);
+        assert_eq!(kc1.rows(0, 3).into_owned(), DVector::from_vec(vec![2.0, 3.0, 2.5]));
+        assert!(kc1[3].is_nan());
+
+        // diversities are [3, 1, 2, 0]
+        let kp1 = avg_diversity(&rca);
+        assert_eq!(kp1.rows(0, 3).into_owned(), DVector::from_vec(vec![2.0, 2.5, 3.0]));
+        assert!(kp1[3].is_nan());
+    }
+
+    #[test]
+    fn test_orient() {
+        let mut eci = DMatrix::from_vec(3,1,vec![1.0, 0.0, -1.0]);
+        let mut pci = DMatrix::from_vec(2,1,vec![0.5, -0.5]);
+
+        orient(&mut eci, &mut pci, &[1.0, 2.0, 3.0]).unwrap();
+        assert_eq!(eci.as_slice(), &[-1.0, 0.0, 1.0]);
+        assert_eq!(pci.as_slice(), &[-0.5, 0.5]);
+
+        // already oriented
+        orient(&mut eci, &mut pci, &[1.0, 2.0, 3.0]).unwrap();
+        assert_eq!(eci.as_slice(), &[-1.0, 0.0, 1.0]);
+
+        // no variance in the reference, so left as is
+        orient(&mut eci, &mut pci, &[1.0, 1.0, 1.0]).unwrap();
+        assert_eq!(eci.as_slice(), &[-1.0, 0.0, 1.0]);
+
+        match orient(&mut eci, &mut pci, &[1.0, 2.0]) {
+            Err(Error::LengthMismatch { expected, found }) => assert_eq!((expected, found), (3, 2)),
+            _ => panic!("expected length mismatch"),
+        }
+        assert_eq!(eci.as_slice(), &[-1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_complexity_weighted() {
+        let m = DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]);
+
+        let (mut eci, mut pci) = complexity_weighted(&m);
+        orient(&mut eci, &mut pci, &[3.0, 2.0, 1.0]).unwrap();
+
+        // same as reflections seeded with diversity and ubiquity
+        let expected_eci = [1.1270864734947468, -0.3461563444358045, -0.7809301290589424];
+        let expected_pci = [-1.1114308624103872, 0.28453084727521216, 0.826900015135175];
+        assert!(eci.iter().zip(expected_eci.iter()).all(|(x, y)| (x - y).abs() < 1e-9));
+        assert!(pci.iter().zip(expected_pci.iter()).all(|(x, y)| (x - y).abs() < 1e-9));
+
+        // only shares matter, so scaling a country's exports changes nothing
+        let mut scaled = m.clone();
+        for x in scaled.row_mut(0).iter_mut() {
+            *x *= 10.0;
+        }
+        let (mut eci_scaled, mut pci_scaled) = complexity_weighted(&scaled);
+        orient(&mut eci_scaled, &mut pci_scaled, &[3.0, 2.0, 1.0]).unwrap();
+
+        assert!((eci - eci_scaled).iter().all(|x| x.abs() < 1e-9));
+        assert!((pci - pci_scaled).iter().all(|x| x.abs() < 1e-9));
+
+        // a country with no exports
+        let (eci, _) = complexity_weighted(&DMatrix::from_vec(2,2,vec![1.0,0.0,2.0,0.0]));
+        assert!(eci.iter().all(|x| x.is_nan()));
+    }
+
     #[test]
     fn test_complexity() {
         println!("columns: product, rows: country");
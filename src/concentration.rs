@@ -0,0 +1,182 @@
+use nalgebra::{DMatrix, DVector, RowDVector};
+use std::collections::HashMap;
+
+use crate::mcp::lookup;
+use crate::{year_or_warn, Error, ProductSpace};
+
+/// Herfindahl-Hirschman index of each country's exports across
+/// products: the sum of the squares of its export shares,
+///
+/// hhi(c) = sum_p (m_cp / sum_p(m_cp))^2
+///
+/// which runs from 1/products, for exports spread evenly over every
+/// product, to 1.0, for exports of only one product. One value per row
+/// (country). Countries with no exports are NaN.
+pub fn export_concentration(m: &DMatrix<f64>) -> DVector<f64> {
+    let totals = m.column_sum();
+
+    DVector::from_fn(m.nrows(), |c, _| {
+        m.row(c).iter()
+            .map(|x| (x / totals[c]).powi(2))
+            .sum()
+    })
+}
+
+/// Finger-Kreinin export similarity of two countries' exports (rows
+/// of an mcp), the overlap of their export shares:
+///
+/// esi(a, b) = sum_p min(m_ap / sum_p(m_ap), m_bp / sum_p(m_bp))
+///
+/// Each row is normalized by its own total, so the size of the
+/// economies doesn't matter. 1.0 for identical export baskets, 0.0 for
+/// no product in common. NaN if either has no exports.
+pub fn export_similarity(a: &RowDVector<f64>, b: &RowDVector<f64>) -> f64 {
+    let total_a = a.sum();
+    let total_b = b.sum();
+
+    if total_a == 0.0 || total_b == 0.0 {
+        return std::f64::NAN;
+    }
+
+    a.iter().zip(b.iter())
+        .map(|(x, y)| (x / total_a).min(y / total_b))
+        .sum()
+}
+
+impl ProductSpace {
+    /// export concentration (the Herfindahl index, see
+    /// `export_concentration`) of each country, from the raw mcps summed
+    /// over years. Years not found are skipped.
+    ///
+    /// Returns `Error::McpsDropped` if the mcps were dropped, or
+    /// `Error::MissingYears` if no year is found.
+    pub fn export_concentration(
+        &self,
+        years: &[u32],
+        ) -> Result<HashMap<String, f64>, Error>
+    {
+        let total = self.summed_mcps(years)?;
+        let hhi = export_concentration(&total);
+
+        Ok(self.country_idx.iter()
+            .map(|(country, i)| (country.clone(), hhi[*i]))
+            .collect())
+    }
+
+    /// export similarity (Finger-Kreinin, see `export_similarity`) of
+    /// two countries, from the raw mcps summed over years, so each
+    /// year's exports count by value. Years not found are skipped.
+    ///
+    /// None if either country is unknown, the mcps were dropped, or no
+    /// year is found.
+    pub fn export_similarity(
+        &self,
+        country_a: &str,
+        country_b: &str,
+        years: &[u32],
+        ) -> Option<f64>
+    {
+        let row_a = lookup(&self.country_idx, country_a, "country").ok()?;
+        let row_b = lookup(&self.country_idx, country_b, "country").ok()?;
+        let mcps = self.mcps().ok()?;
+
+        let mut found = false;
+        let mut a = RowDVector::zeros(self.product_idx.len());
+        let mut b = RowDVector::zeros(self.product_idx.len());
+        for mcp in years.iter().filter_map(|y| year_or_warn(mcps, *y)) {
+            found = true;
+            a += mcp.row(row_a);
+            b += mcp.row(row_b);
+        }
+
+        if found {
+            Some(export_similarity(&a, &b))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_concentration() {
+        // a: one product, b: even over four, c: 3/4 and 1/4, d: nothing
+        let m = DMatrix::from_row_slice(4,4,&[
+            5.0, 0.0, 0.0, 0.0,
+            1.0, 1.0, 1.0, 1.0,
+            3.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0,
+        ]);
+
+        let res = export_concentration(&m);
+
+        assert_eq!(res[0], 1.0);
+        assert_eq!(res[1], 0.25);
+        assert_eq!(res[2], 0.625);
+        assert!(res[3].is_nan());
+    }
+
+    #[test]
+    fn test_export_similarity() {
+        let a = RowDVector::from_vec(vec![2.0, 2.0, 0.0]);
+        let b = RowDVector::from_vec(vec![1.0, 0.0, 3.0]);
+
+        assert_eq!(export_similarity(&a, &a), 1.0);
+        // shares [.5, .5, 0] and [.25, 0, .75]
+        assert_eq!(export_similarity(&a, &b), 0.25);
+        assert_eq!(export_similarity(&a, &(&a * 10.0)), 1.0);
+        assert!(export_similarity(&a, &RowDVector::zeros(3)).is_nan());
+    }
+
+    #[test]
+    fn test_ps_export_concentration() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(2,2,vec![1.0,3.0,2.0,2.0]));
+        mcps.insert(2017, DMatrix::from_vec(2,2,vec![3.0,1.0,2.0,2.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+
+        // a: 1/3 and 2/3
+        let res = ps.export_concentration(&[2016]).unwrap();
+        assert!((res["a"] - 5.0 / 9.0).abs() < 1e-12);
+        assert_eq!(res["b"], 0.6 * 0.6 + 0.4 * 0.4);
+
+        // summed, a exports 4 of each
+        let res = ps.export_concentration(&[2016, 2017, 2099]).unwrap();
+        assert_eq!(res["a"], 0.5);
+
+        assert!(ps.export_concentration(&[2099]).is_err());
+    }
+
+    #[test]
+    fn test_ps_export_similarity() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(2,2,vec![1.0,3.0,2.0,2.0]));
+        mcps.insert(2017, DMatrix::from_vec(2,2,vec![3.0,1.0,2.0,2.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+
+        // shares: a [1/3, 2/3], b [.6, .4]
+        let res = ps.export_similarity("a", "b", &[2016]).unwrap();
+        assert!((res - (1.0 / 3.0 + 0.4)).abs() < 1e-12);
+
+        // summed, a has [.5, .5] and b [.5, .5]
+        assert_eq!(ps.export_similarity("a", "b", &[2016, 2017, 2099]), Some(1.0));
+
+        assert_eq!(ps.export_similarity("a", "zz", &[2016]), None);
+        assert_eq!(ps.export_similarity("a", "b", &[2099]), None);
+    }
+}
@@ -1,4 +1,21 @@
-use nalgebra::DMatrix;
+use nalgebra::{DMatrix, RowDVector};
+
+use crate::ProximityTransform;
+
+/// Adjustments to proximity before density is calculated, for
+/// `ProductSpace::density_with_options`. The default is proximity as
+/// calculated.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DensityOptions {
+    /// proximities below this are zeroed, to cut noise from products
+    /// that only coincide by chance
+    pub min_proximity: Option<f64>,
+    /// every proximity left by the `min_proximity` floor is transformed;
+    /// see `ProximityTransform`. The floor is judged on proximity as
+    /// calculated, floored links stay at 0.0, and the diagonal is not
+    /// transformed. Unfloored zeros are transformed like any other value
+    pub transform: Option<ProximityTransform>,
+}
 
 // rca input is matrix of rca, where
 // - col indexes are product
@@ -22,6 +39,62 @@ pub fn density(rca: &DMatrix<f64>, proximity: &DMatrix<f64>) -> DMatrix<f64> {
     density_numerator.component_div(&density_denominator)
 }
 
+/// density for a single country, from its row of the rca.
+/// Same as one row of `density`, without calculating the others.
+pub fn density_row(rca_row: &RowDVector<f64>, proximity: &DMatrix<f64>) -> RowDVector<f64> {
+    let density_numerator = rca_row * proximity;
+
+    // sum of proximities for each product
+    let density_denominator = proximity.row_sum();
+
+    density_numerator.component_div(&density_denominator)
+}
+
+/// Same as `density`, but with chunks of country rows calculated in
+/// parallel. Each chunk goes through `density`, so the output is
+/// identical.
+///
+/// Runs on rayon's global pool (one thread per core, unless
+/// configured otherwise), with one chunk per thread. Where the caller
+/// is already parallel (e.g. a server calculating density for many
+/// requests at once), use `density_par_in` to scope each call to a
+/// smaller pool.
+#[cfg(feature = "rayon")]
+pub fn density_par(rca: &DMatrix<f64>, proximity: &DMatrix<f64>) -> DMatrix<f64> {
+    use rayon::prelude::*;
+
+    let nrows = rca.nrows();
+    let chunk_size = (nrows / rayon::current_num_threads()).max(1);
+    let starts: Vec<usize> = (0..nrows).step_by(chunk_size).collect();
+
+    let chunks: Vec<DMatrix<f64>> = starts.par_iter()
+        .map(|start| {
+            let len = chunk_size.min(nrows - start);
+            density(&rca.rows(*start, len).into_owned(), proximity)
+        })
+        .collect();
+
+    let mut res = DMatrix::zeros(nrows, proximity.ncols());
+    for (start, chunk) in starts.iter().zip(chunks) {
+        res.rows_mut(*start, chunk.nrows()).copy_from(&chunk);
+    }
+
+    res
+}
+
+/// `density_par` on the given pool instead of the global one. Rows are
+/// chunked by the pool's thread count, so a pool of one thread
+/// calculates the whole matrix as one chunk.
+#[cfg(feature = "rayon")]
+pub fn density_par_in(
+    rca: &DMatrix<f64>,
+    proximity: &DMatrix<f64>,
+    pool: &rayon::ThreadPool,
+    ) -> DMatrix<f64>
+{
+    pool.install(|| density_par(rca, proximity))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -59,6 +132,45 @@ mod tests {
         assert_eq!(density, expected);
     }
 
+    #[test]
+    fn test_density_row() {
+        let m = DMatrix::from_vec(2,3,vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let rca = rca(&m);
+        let proximity = proximity(&rca);
+
+        let expected = density(&rca, &proximity);
+
+        for i in 0..2 {
+            let row = density_row(&rca.row(i).into_owned(), &proximity);
+            for j in 0..3 {
+                assert!((row[j] - expected[(i,j)]).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_density_par() {
+        let m = DMatrix::from_fn(17, 5, |i, j| ((i * 7 + j * 3) % 11) as f64 + 1.0);
+        let rca = rca(&m);
+        let proximity = proximity(&rca);
+
+        assert_eq!(density_par(&rca, &proximity), density(&rca, &proximity));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_density_par_in() {
+        let m = DMatrix::from_fn(17, 5, |i, j| ((i * 7 + j * 3) % 11) as f64 + 1.0);
+        let rca = rca(&m);
+        let proximity = proximity(&rca);
+
+        for threads in &[1, 3] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(*threads).build().unwrap();
+            assert_eq!(density_par_in(&rca, &proximity, &pool), density(&rca, &proximity));
+        }
+    }
+
     #[test]
     fn test_density_0_1() {
         println!("columns: product, rows: country");
@@ -0,0 +1,89 @@
+use crate::complexity::{complexity_by_diversity, reflection_change};
+use crate::stats::pearson;
+use crate::{Error, ProductSpace};
+
+/// `reflection_change` below which the reflections count as converged
+pub const REFLECTION_TOLERANCE: f64 = 1e-4;
+
+/// Sanity checks on complexity. See `ProductSpace::complexity_diagnostics`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics {
+    /// Pearson correlation of pci with ubiquity, expected to be
+    /// negative: complex products are exported by fewer countries
+    pub pci_ubiquity: f64,
+    /// Pearson correlation of eci with diversity. eci is oriented to
+    /// increase with diversity (see `complexity`), so this is never
+    /// negative, but a value near zero is suspect
+    pub eci_diversity: f64,
+    /// largest change in standardized eci between the last two
+    /// comparable reflections. NaN if eci is undefined
+    pub reflection_change: f64,
+    /// true if `reflection_change` is below `REFLECTION_TOLERANCE`
+    pub converged: bool,
+}
+
+impl ProductSpace {
+    /// Diagnostics for `complexity` with the same years and cutoff
+    /// (binary rca with the fair share default of 1.0 if no cutoff).
+    /// Correlations are NaN where undefined, e.g. every product with the
+    /// same ubiquity.
+    ///
+    /// Returns `Error::MissingYears` if no year is found.
+    pub fn complexity_diagnostics(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<Diagnostics, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+
+        let (eci, pci) = complexity_by_diversity(&rca);
+        let diversity = rca.column_sum();
+        let ubiquity = rca.row_sum_tr();
+
+        let reflection_change = reflection_change(&rca);
+
+        Ok(Diagnostics {
+            pci_ubiquity: pearson(pci.as_slice(), ubiquity.as_slice()),
+            eci_diversity: pearson(eci.as_slice(), diversity.as_slice()),
+            reflection_change,
+            converged: reflection_change < REFLECTION_TOLERANCE,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::DMatrix;
+    use std::collections::HashMap;
+    use super::*;
+
+    #[test]
+    fn test_complexity_diagnostics() {
+        let mut mcps = HashMap::new();
+        // binary rows: a [1,0,0,1,0], b [1,1,0,0,1], c [0,1,1,0,1],
+        // d [0,0,1,0,0], e [0,0,0,1,1]
+        mcps.insert(2016, DMatrix::from_row_slice(5,5,&[
+            3.0, 1.0, 1.0, 2.0, 0.0,
+            2.0, 2.0, 0.0, 1.0, 1.0,
+            0.0, 3.0, 2.0, 0.0, 1.0,
+            1.0, 1.0, 4.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 3.0, 2.0,
+        ]));
+
+        let ps = ProductSpace::new(
+            ["a", "b", "c", "d", "e"].iter().enumerate().map(|(i, c)| (c.to_string(), i)).collect(),
+            ["01", "02", "03", "04", "05"].iter().enumerate().map(|(i, p)| (p.to_string(), i)).collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let res = ps.complexity_diagnostics(&[2016], Some(1.0)).unwrap();
+        assert!((res.eci_diversity - 0.41808502570684436).abs() < 1e-9);
+        assert!((res.pci_ubiquity - 0.04183512109102455).abs() < 1e-9);
+        assert!((res.reflection_change - 0.058234489991149885).abs() < 1e-9);
+        assert!(!res.converged);
+
+        assert!(ps.complexity_diagnostics(&[2099], Some(1.0)).is_err());
+    }
+}
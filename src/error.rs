@@ -1,15 +1,55 @@
 use std::error;
 use std::fmt;
+use std::io;
+use std::num::ParseFloatError;
 
 #[derive(Debug)]
 pub enum Error {
     MissingIndex { member: String, index: String },
+    NonUniformProductCodes { product: String, expected_len: usize },
+    InvalidRollupDigits { digits: usize, code_len: usize },
+    Io(io::Error),
+    MissingColumn { column: String },
+    MissingCell { row: usize, column: String },
+    ParseValue { row: usize, column: String, value: String },
+    MissingYears { years: Vec<u32> },
+    DegenerateYear { year: u32 },
+    NonFinite { year: u32, matrix: String },
+    McpsDropped,
+    IndexMismatch { index: String },
+    InvalidCutoff { cutoff: f64 },
+    ShapeMismatch { expected: (usize, usize), found: (usize, usize) },
+    LengthMismatch { expected: usize, found: usize },
+    NegativeValue { year: u32, country: String, product: String },
+    UnsupportedValueKind { method: String },
+    ParseFloat(ParseFloatError),
+    #[cfg(feature = "csv")]
+    Csv(csv::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::MissingIndex {ref member, ref index} => write!(f, "MissingIndex error: {} not in {}", member, index),
+            Error::NonUniformProductCodes {ref product, ref expected_len} => write!(f, "NonUniformProductCodes error: {} is not {} characters long", product, expected_len),
+            Error::InvalidRollupDigits {ref digits, ref code_len} => write!(f, "InvalidRollupDigits error: cannot roll up {} character codes to {} digits", code_len, digits),
+            Error::Io(ref err) => write!(f, "Io error: {}", err),
+            Error::MissingColumn {ref column} => write!(f, "MissingColumn error: no {} column in header", column),
+            Error::MissingCell {ref row, ref column} => write!(f, "MissingCell error: row {} has no {} value", row, column),
+            Error::ParseValue {ref row, ref column, ref value} => write!(f, "ParseValue error: could not parse {:?} in {} at row {}", value, column, row),
+            Error::MissingYears {ref years} => write!(f, "MissingYears error: none of {:?} found", years),
+            Error::DegenerateYear {ref year} => write!(f, "DegenerateYear error: {} has fewer than two exporting countries or exported products", year),
+            Error::NonFinite {ref year, ref matrix} => write!(f, "NonFinite error: {} for {} has NaN or inf values", matrix, year),
+            Error::McpsDropped => write!(f, "McpsDropped error: raw mcps were dropped with drop_mcps, or the product space was built from rca"),
+            Error::IndexMismatch {ref index} => write!(f, "IndexMismatch error: {} indexes do not match", index),
+            Error::InvalidCutoff {ref cutoff} => write!(f, "InvalidCutoff error: rca cutoff {} is not finite and at least 0", cutoff),
+            Error::ShapeMismatch {ref expected, ref found} => write!(f, "ShapeMismatch error: expected {:?} matrix, found {:?}", expected, found),
+            Error::LengthMismatch {ref expected, ref found} => write!(f, "LengthMismatch error: expected {} values, found {}", expected, found),
+            Error::NegativeValue {ref year, ref country, ref product} => write!(f, "NegativeValue error: {} has a negative value for {} in {}", country, product, year),
+            Error::UnsupportedValueKind {ref method} => write!(f, "UnsupportedValueKind error: {} needs export values, not shares", method),
+            Error::ParseFloat(ref err) => write!(f, "ParseFloat error: {}", err),
+            #[cfg(feature = "csv")]
+            Error::Csv(ref err) => write!(f, "Csv error: {}", err),
         }
     }
 }
@@ -18,12 +58,76 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::MissingIndex { .. } => "Missing Index",
+            Error::NonUniformProductCodes { .. } => "Non-uniform Product Codes",
+            Error::InvalidRollupDigits { .. } => "Invalid Rollup Digits",
+            Error::Io(_) => "Io",
+            Error::MissingColumn { .. } => "Missing Column",
+            Error::MissingCell { .. } => "Missing Cell",
+            Error::ParseValue { .. } => "Parse Value",
+            Error::MissingYears { .. } => "Missing Years",
+            Error::DegenerateYear { .. } => "Degenerate Year",
+            Error::NonFinite { .. } => "Non-finite",
+            Error::McpsDropped => "Mcps Dropped",
+            Error::IndexMismatch { .. } => "Index Mismatch",
+            Error::InvalidCutoff { .. } => "Invalid Cutoff",
+            Error::ShapeMismatch { .. } => "Shape Mismatch",
+            Error::LengthMismatch { .. } => "Length Mismatch",
+            Error::NegativeValue { .. } => "Negative Value",
+            Error::UnsupportedValueKind { .. } => "Unsupported Value Kind",
+            Error::ParseFloat(_) => "Parse Float",
+            #[cfg(feature = "csv")]
+            Error::Csv(_) => "Csv",
         }
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
+            Error::Io(ref err) => Some(err),
+            Error::ParseFloat(ref err) => Some(err),
+            #[cfg(feature = "csv")]
+            Error::Csv(ref err) => Some(err),
             _ => None
         }
     }
 }
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// For parsing values outside of ingestion. Values parsed by `ingest`
+/// are `Error::ParseValue` instead, with the row and column.
+impl From<ParseFloatError> for Error {
+    fn from(err: ParseFloatError) -> Self {
+        Error::ParseFloat(err)
+    }
+}
+
+/// With the `csv` feature, for reading mcps with the csv crate
+#[cfg(feature = "csv")]
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        Error::Csv(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as StdError;
+    use super::*;
+
+    #[test]
+    fn test_source() {
+        let err: Error = io::Error::new(io::ErrorKind::Other, "disk").into();
+        assert_eq!(err.source().unwrap().to_string(), "disk");
+
+        let parse_err = "x".parse::<f64>().unwrap_err();
+        let err: Error = parse_err.clone().into();
+        assert_eq!(err.source().unwrap().to_string(), parse_err.to_string());
+        assert_eq!(err.to_string(), format!("ParseFloat error: {}", parse_err));
+
+        assert!(Error::McpsDropped.source().is_none());
+    }
+}
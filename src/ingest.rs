@@ -0,0 +1,614 @@
+use nalgebra::DMatrix;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Read};
+#[cfg(feature = "flate2")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "flate2")]
+use std::fs::File;
+#[cfg(feature = "flate2")]
+use std::path::Path;
+
+use crate::{Error, ProductSpaceBuilder};
+
+// how many rows between progress reports while reading
+const PROGRESS_INTERVAL: usize = 10_000;
+
+/// One exports value for a country and product in a year
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub country: String,
+    pub product: String,
+    pub year: u32,
+    pub value: f64,
+}
+
+/// Header names to read from a tsv, and how to treat the value cells.
+/// Defaults to the names in the oec `year_origin_hs92_4.tsv` file,
+/// with `NULL` as the only null value.
+#[derive(Debug, Clone)]
+pub struct Columns {
+    pub country: String,
+    pub product: String,
+    pub year: String,
+    pub value: String,
+
+    /// value cells matching one of these exactly are skipped,
+    /// e.g. `""`, `"NA"`, `"-"`
+    pub nulls: Vec<String>,
+    /// what to do with value cells that aren't null and don't parse.
+    /// "NaN", "inf" and the like parse as f64, but aren't an export
+    /// value, so they count as unparseable here too
+    pub unparseable: ParsePolicy,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self {
+            country: "origin".into(),
+            product: "hs92".into(),
+            year: "year".into(),
+            value: "export_val".into(),
+            nulls: vec!["NULL".into()],
+            unparseable: ParsePolicy::default(),
+        }
+    }
+}
+
+/// How to handle a value that can't be parsed as a number.
+/// Defaults to `Error`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParsePolicy {
+    /// stop with `Error::ParseValue`, which has the row number
+    Error,
+    /// skip the row, like a null
+    Skip,
+}
+
+impl Default for ParsePolicy {
+    fn default() -> Self {
+        ParsePolicy::Error
+    }
+}
+
+/// Header names to look for in `from_csv_auto`, for each column.
+/// Matching is case-insensitive, and the first alias found in the header
+/// wins, so put preferred names first.
+///
+/// Defaults cover the oec, baci (`t`, `i`, `k`, `v`) and comtrade
+/// style headers. For odd headers, add to (or replace) the lists.
+#[derive(Debug, Clone)]
+pub struct HeaderAliases {
+    pub country: Vec<String>,
+    pub product: Vec<String>,
+    pub year: Vec<String>,
+    pub value: Vec<String>,
+}
+
+impl Default for HeaderAliases {
+    fn default() -> Self {
+        let strings = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+
+        Self {
+            country: strings(&["origin", "iso3", "country", "country_code", "exporter", "reporter", "reporter_iso", "i"]),
+            product: strings(&["hs92", "hs96", "hs02", "hs07", "hs12", "hs17", "sitc", "product", "product_code", "commodity_code", "cmd_code", "k"]),
+            year: strings(&["year", "period", "t"]),
+            value: strings(&["export_val", "export_value", "value", "trade_value", "primary_value", "val", "v"]),
+        }
+    }
+}
+
+// in order of preference when counts are equal
+const DELIMITERS: [char; 4] = ['\t', ',', ';', '|'];
+
+impl HeaderAliases {
+    /// Guesses the delimiter as the one appearing most in the header,
+    /// and finds each column's header name. `Error::MissingColumn` (with
+    /// `country`, `product`, `year` or `value`) if no alias matches.
+    ///
+    /// Other `Columns` settings are the defaults.
+    pub fn detect(&self, header: &str) -> Result<(char, Columns), Error> {
+        let delimiter = DELIMITERS.iter()
+            .cloned()
+            .rev()
+            .max_by_key(|d| header.matches(*d).count())
+            .unwrap_or('\t');
+
+        let cells: Vec<_> = header.split(delimiter).collect();
+
+        let find = |aliases: &[String], column: &str| {
+            aliases.iter()
+                .find_map(|alias| {
+                    cells.iter().find(|cell| cell.trim().eq_ignore_ascii_case(alias))
+                })
+                .map(|cell| cell.to_string())
+                .ok_or_else(|| Error::MissingColumn { column: column.into() })
+        };
+
+        let columns = Columns {
+            country: find(&self.country, "country")?,
+            product: find(&self.product, "product")?,
+            year: find(&self.year, "year")?,
+            value: find(&self.value, "value")?,
+            ..Columns::default()
+        };
+
+        Ok((delimiter, columns))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IngestPhase {
+    /// reading records, `processed` is rows read
+    FirstPass,
+    /// filling the mcp matrices, `processed` is rows placed
+    SecondPass,
+    /// `processed` is years done
+    BuildingRca,
+    /// `processed` is years done
+    BuildingProximity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IngestProgress {
+    pub phase: IngestPhase,
+    pub processed: usize,
+}
+
+pub type ProgressFn = Box<dyn FnMut(IngestProgress)>;
+
+pub(crate) fn report(progress: &mut Option<ProgressFn>, phase: IngestPhase, processed: usize) {
+    if let Some(f) = progress {
+        f(IngestProgress { phase, processed });
+    }
+}
+
+impl ProductSpaceBuilder {
+    /// Builds the country and product indexes and the mcp for each year
+    /// from records. Values for duplicate records are summed.
+    ///
+    /// The progress callback, if any, is kept by the builder and also
+    /// reports on building rca and proximity.
+    pub fn from_records<I>(
+        records: I,
+        progress: Option<ProgressFn>,
+        ) -> ProductSpaceBuilder
+        where I: IntoIterator<Item=Record>
+    {
+        ingest(records.into_iter().map(Ok), progress)
+            .expect("logic error, records are infallible")
+    }
+
+    /// Reads a tab-separated file with a header row, using `columns`
+    /// to find the country, product, year and value cells.
+    ///
+    /// Rows where the value is one of `columns.nulls` are skipped, and
+    /// unparseable values are skipped or errors according to
+    /// `columns.unparseable`. Row numbers in errors count the header as
+    /// row 1.
+    ///
+    /// The reader is only read through once (records are buffered for
+    /// the second pass), so it doesn't need to be seekable and can be a
+    /// decompressing stream.
+    pub fn from_tsv_reader<R: Read>(
+        rdr: R,
+        columns: &Columns,
+        progress: Option<ProgressFn>,
+        ) -> Result<ProductSpaceBuilder, Error>
+    {
+        let mut lines = BufReader::new(rdr).lines();
+
+        let header = match lines.next() {
+            Some(header) => header?,
+            None => String::new(),
+        };
+
+        from_lines(lines, &header, '\t', columns, progress)
+    }
+
+    /// Like `from_tsv_reader`, but the delimiter (tab, comma, semicolon
+    /// or pipe) is guessed from the header, and the columns are found by
+    /// `aliases`. See `HeaderAliases::detect`.
+    ///
+    /// Cells are split on the delimiter, so quoted cells containing the
+    /// delimiter aren't supported.
+    pub fn from_csv_auto<R: Read>(
+        rdr: R,
+        aliases: &HeaderAliases,
+        progress: Option<ProgressFn>,
+        ) -> Result<ProductSpaceBuilder, Error>
+    {
+        let mut lines = BufReader::new(rdr).lines();
+
+        let header = match lines.next() {
+            Some(header) => header?,
+            None => String::new(),
+        };
+        let (delimiter, columns) = aliases.detect(&header)?;
+
+        from_lines(lines, &header, delimiter, &columns, progress)
+    }
+
+    /// Reads a gzipped tsv file, e.g. as the oec data ships, without
+    /// decompressing to disk. A gz stream can't be rewound, so this
+    /// relies on `from_tsv_reader` only reading through once.
+    #[cfg(feature = "flate2")]
+    pub fn from_gzip_tsv<P: AsRef<Path>>(
+        path: P,
+        columns: &Columns,
+        progress: Option<ProgressFn>,
+        ) -> Result<ProductSpaceBuilder, Error>
+    {
+        let f = File::open(path)?;
+
+        Self::from_tsv_reader(GzDecoder::new(f), columns, progress)
+    }
+}
+
+// reads the rows after the header
+fn from_lines<L>(
+    lines: L,
+    header: &str,
+    delimiter: char,
+    columns: &Columns,
+    progress: Option<ProgressFn>,
+    ) -> Result<ProductSpaceBuilder, Error>
+    where L: Iterator<Item=io::Result<String>>
+{
+    let header: Vec<_> = header.split(delimiter).collect();
+
+    let position = |column: &str| {
+        header.iter()
+            .position(|h| *h == column)
+            .ok_or_else(|| Error::MissingColumn { column: column.into() })
+    };
+    let country_col = position(&columns.country)?;
+    let product_col = position(&columns.product)?;
+    let year_col = position(&columns.year)?;
+    let value_col = position(&columns.value)?;
+
+    let records = lines.enumerate()
+        .filter_map(|(i, line)| {
+            let row = i + 2;
+
+            let parse = || -> Result<Option<Record>, Error> {
+                let line = line?;
+                let cells: Vec<_> = line.split(delimiter).collect();
+
+                let cell = |idx: usize, column: &str| {
+                    cells.get(idx)
+                        .cloned()
+                        .ok_or_else(|| Error::MissingCell { row, column: column.into() })
+                };
+
+                let value = cell(value_col, &columns.value)?;
+                if columns.nulls.iter().any(|null| null == value) {
+                    return Ok(None);
+                }
+                let value = match (value.parse::<f64>(), columns.unparseable) {
+                    (Ok(value), _) if value.is_finite() => value,
+                    (_, ParsePolicy::Skip) => return Ok(None),
+                    (_, ParsePolicy::Error) => {
+                        return Err(Error::ParseValue { row, column: columns.value.clone(), value: value.into() });
+                    },
+                };
+
+                let year = cell(year_col, &columns.year)?;
+                let year = year.parse::<u32>()
+                    .map_err(|_| Error::ParseValue { row, column: columns.year.clone(), value: year.into() })?;
+
+                Ok(Some(Record {
+                    country: cell(country_col, &columns.country)?.to_owned(),
+                    product: cell(product_col, &columns.product)?.to_owned(),
+                    year,
+                    value,
+                }))
+            };
+
+            parse().transpose()
+        });
+
+    ingest(records, progress)
+}
+
+// Records can't be assumed sorted, and the matrix sizes aren't known
+// until all countries and products are seen, so records are buffered in
+// the first pass and placed into matrices in the second.
+fn ingest<I>(
+    records: I,
+    mut progress: Option<ProgressFn>,
+    ) -> Result<ProductSpaceBuilder, Error>
+    where I: Iterator<Item=Result<Record, Error>>
+{
+    let mut country_set = HashSet::new();
+    let mut product_set = HashSet::new();
+    let mut year_set = HashSet::new();
+    let mut rows = vec![];
+
+    // first pass
+    for record in records {
+        let record = record?;
+
+        country_set.insert(record.country.clone());
+        product_set.insert(record.product.clone());
+        year_set.insert(record.year);
+        rows.push(record);
+
+        if rows.len() % PROGRESS_INTERVAL == 0 {
+            report(&mut progress, IngestPhase::FirstPass, rows.len());
+        }
+    }
+    report(&mut progress, IngestPhase::FirstPass, rows.len());
+
+    let mut mcps: HashMap<u32,_> = year_set.into_iter()
+        .map(|y| (y, DMatrix::zeros(country_set.len(), product_set.len())))
+        .collect();
+
+    // indexes are assigned in sorted order, so they don't depend on hash
+    // order and are the same across runs
+    let country_idx = sorted_index(country_set);
+    let product_idx = sorted_index(product_set);
+
+    // second pass
+    for (i, row) in rows.iter().enumerate() {
+        let mcp = mcps.get_mut(&row.year)
+            .expect("logic error, year must be in");
+
+        let matrix_row_idx = country_idx[&row.country];
+        let matrix_col_idx = product_idx[&row.product];
+
+        mcp[(matrix_row_idx, matrix_col_idx)] += row.value;
+
+        if (i + 1) % PROGRESS_INTERVAL == 0 {
+            report(&mut progress, IngestPhase::SecondPass, i + 1);
+        }
+    }
+    report(&mut progress, IngestPhase::SecondPass, rows.len());
+
+    let builder = ProductSpaceBuilder::new(country_idx, product_idx, mcps);
+
+    match progress {
+        Some(progress) => Ok(builder.progress(progress)),
+        None => Ok(builder),
+    }
+}
+
+fn sorted_index(set: HashSet<String>) -> HashMap<String, usize> {
+    let mut members: Vec<_> = set.into_iter().collect();
+    members.sort();
+
+    members.into_iter()
+        .enumerate()
+        .map(|(v,k)| (k,v))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mcp;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const TSV: &str = "year\torigin\ths92\texport_val\n\
+        2017\ta\t01\t1\n\
+        2017\tb\t01\t2\n\
+        2017\ta\t02\t3\n\
+        2017\tb\t02\t4\n\
+        2017\ta\t03\t5\n\
+        2017\tb\t03\tNULL\n\
+        2017\tb\t03\t6\n";
+
+    #[test]
+    fn test_from_records() {
+        let records = vec![
+            Record { country: "a".into(), product: "01".into(), year: 2017, value: 1.0 },
+            Record { country: "b".into(), product: "01".into(), year: 2017, value: 2.0 },
+            Record { country: "a".into(), product: "02".into(), year: 2017, value: 3.0 },
+            Record { country: "b".into(), product: "02".into(), year: 2017, value: 4.0 },
+            Record { country: "a".into(), product: "03".into(), year: 2017, value: 5.0 },
+            Record { country: "b".into(), product: "03".into(), year: 2017, value: 6.0 },
+        ];
+
+        let ps = ProductSpaceBuilder::from_records(records, None).build();
+        let rca = ps.rca(&[2017], None).unwrap();
+
+        assert_eq!(rca.get("a", "01").unwrap(), 0.7777777777777778);
+        assert_eq!(rca.get("b", "03").unwrap(), 0.9545454545454545);
+    }
+
+    #[test]
+    fn test_from_tsv_reader() {
+        let ps = ProductSpaceBuilder::from_tsv_reader(TSV.as_bytes(), &Columns::default(), None)
+            .unwrap()
+            .build();
+        let rca = ps.rca(&[2017], None).unwrap();
+
+        assert_eq!(rca.get("a", "01").unwrap(), 0.7777777777777778);
+        assert_eq!(rca.get("b", "03").unwrap(), 0.9545454545454545);
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn test_from_gzip_tsv() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join("product_space_test_from_gzip_tsv.tsv.gz");
+
+        let mut enc = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        enc.write_all(TSV.as_bytes()).unwrap();
+        enc.finish().unwrap();
+
+        let ps = ProductSpaceBuilder::from_gzip_tsv(&path, &Columns::default(), None)
+            .unwrap()
+            .build();
+        let rca = ps.rca(&[2017], None).unwrap();
+
+        assert_eq!(rca.get("a", "01").unwrap(), 0.7777777777777778);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sorted_indexes() {
+        let tsv = "year\torigin\ths92\texport_val\n\
+            2017\tzwe\t02\t1\n\
+            2017\tago\t03\t2\n\
+            2017\tbra\t01\t3\n";
+
+        let ps = ProductSpaceBuilder::from_tsv_reader(tsv.as_bytes(), &Columns::default(), None)
+            .unwrap()
+            .build();
+
+        assert_eq!(ps.country_idx["ago"], 0);
+        assert_eq!(ps.country_idx["bra"], 1);
+        assert_eq!(ps.country_idx["zwe"], 2);
+        assert_eq!(ps.product_idx["01"], 0);
+        assert_eq!(ps.product_idx["03"], 2);
+    }
+
+    #[test]
+    fn test_from_tsv_reader_errors() {
+        let tsv = "year\torigin\ths92\n2017\ta\t01\n";
+        match ProductSpaceBuilder::from_tsv_reader(tsv.as_bytes(), &Columns::default(), None) {
+            Err(Error::MissingColumn { column }) => assert_eq!(column, "export_val"),
+            _ => panic!("expected missing column"),
+        }
+
+        let tsv = "year\torigin\ths92\texport_val\n2017\ta\t01\t1\n2017\tb\t01\tx\n";
+        match ProductSpaceBuilder::from_tsv_reader(tsv.as_bytes(), &Columns::default(), None) {
+            Err(Error::ParseValue { row, value, .. }) => {
+                assert_eq!(row, 3);
+                assert_eq!(value, "x");
+            },
+            _ => panic!("expected parse error"),
+        }
+    }
+
+    #[test]
+    fn test_nulls_and_unparseable() {
+        let tsv = "year\torigin\ths92\texport_val\n\
+            2017\ta\t01\t1\n\
+            2017\tb\t01\t2\n\
+            2017\ta\t02\t3\n\
+            2017\tb\t02\t4\n\
+            2017\ta\t03\t5\n\
+            2017\tb\t03\t6\n\
+            2017\ta\t01\tNA\n\
+            2017\ta\t01\t\n\
+            2017\ta\t01\t-\n\
+            2017\tb\t01\t1..0\n\
+            2017\tb\t02\tNaN\n\
+            2017\tb\t03\tinf\n";
+
+        let columns = Columns {
+            nulls: vec!["".into(), "NA".into(), "-".into()],
+            ..Columns::default()
+        };
+        match ProductSpaceBuilder::from_tsv_reader(tsv.as_bytes(), &columns, None) {
+            Err(Error::ParseValue { row, value, .. }) => {
+                assert_eq!(row, 11);
+                assert_eq!(value, "1..0");
+            },
+            _ => panic!("expected parse error"),
+        }
+
+        let columns = Columns {
+            unparseable: ParsePolicy::Skip,
+            ..columns
+        };
+        let ps = ProductSpaceBuilder::from_tsv_reader(tsv.as_bytes(), &columns, None)
+            .unwrap()
+            .build();
+        let rca = ps.rca(&[2017], None).unwrap();
+        assert!(rca.matrix().iter().all(|x| x.is_finite()));
+
+        assert_eq!(rca.get("a", "01").unwrap(), 0.7777777777777778);
+        assert_eq!(rca.get("b", "03").unwrap(), 0.9545454545454545);
+
+        // NULL is no longer a null value, and can't be parsed
+        let columns = Columns { nulls: vec![], ..Columns::default() };
+        assert!(ProductSpaceBuilder::from_tsv_reader(TSV.as_bytes(), &columns, None).is_err());
+
+        // NaN and inf parse as f64, but are rejected like any other
+        // unparseable value
+        for value in &["NaN", "inf", "-inf"] {
+            let tsv = format!("year\torigin\ths92\texport_val\n2017\ta\t01\t{}\n", value);
+            match ProductSpaceBuilder::from_tsv_reader(tsv.as_bytes(), &Columns::default(), None) {
+                Err(Error::ParseValue { row, value: found, .. }) => {
+                    assert_eq!(row, 2);
+                    assert_eq!(found, *value);
+                },
+                _ => panic!("expected parse error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        let aliases = HeaderAliases::default();
+
+        let (delimiter, columns) = aliases.detect("year\torigin\ths92\texport_val").unwrap();
+        assert_eq!(delimiter, '\t');
+        assert_eq!(columns.country, "origin");
+
+        let (delimiter, columns) = aliases.detect("t,i,j,k,v,q").unwrap();
+        assert_eq!(delimiter, ',');
+        assert_eq!(columns.country, "i");
+        assert_eq!(columns.product, "k");
+        assert_eq!(columns.year, "t");
+        assert_eq!(columns.value, "v");
+
+        let (delimiter, columns) = aliases.detect("Year;ISO3;Product_Code;Trade_Value").unwrap();
+        assert_eq!(delimiter, ';');
+        assert_eq!(columns.country, "ISO3");
+        assert_eq!(columns.value, "Trade_Value");
+
+        match aliases.detect("year,place,hs92,value") {
+            Err(Error::MissingColumn { column }) => assert_eq!(column, "country"),
+            _ => panic!("expected missing column"),
+        }
+
+        let mut aliases = HeaderAliases::default();
+        aliases.country.push("place".into());
+        assert_eq!(aliases.detect("year,place,hs92,value").unwrap().1.country, "place");
+    }
+
+    #[test]
+    fn test_from_csv_auto() {
+        let csv = TSV.replace('\t', ",").replace("origin", "iso3").replace("export_val", "value");
+
+        let ps = ProductSpaceBuilder::from_csv_auto(csv.as_bytes(), &HeaderAliases::default(), None)
+            .unwrap()
+            .build();
+        let rca = ps.rca(&[2017], None).unwrap();
+
+        assert_eq!(rca.get("a", "01").unwrap(), 0.7777777777777778);
+        assert_eq!(rca.get("b", "03").unwrap(), 0.9545454545454545);
+    }
+
+    #[test]
+    fn test_progress() {
+        let phases = Rc::new(RefCell::new(vec![]));
+        let phases_cb = phases.clone();
+
+        let progress: ProgressFn = Box::new(move |p: IngestProgress| {
+            phases_cb.borrow_mut().push((p.phase, p.processed));
+        });
+
+        ProductSpaceBuilder::from_tsv_reader(TSV.as_bytes(), &Columns::default(), Some(progress))
+            .unwrap()
+            .build();
+
+        assert_eq!(
+            *phases.borrow(),
+            vec![
+                (IngestPhase::FirstPass, 6),
+                (IngestPhase::SecondPass, 6),
+                (IngestPhase::BuildingRca, 1),
+                (IngestPhase::BuildingProximity, 1),
+            ]
+        );
+    }
+}
@@ -1,34 +1,132 @@
-use nalgebra::DMatrix;
+use log::warn;
+use nalgebra::{DMatrix, DVector};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 mod mcp;
 pub use mcp::Mcp;
+use mcp::{lookup, names_by_index};
 
 mod rca;
 pub use rca::{
     apply_fair_share,
+    apply_fair_share_cols,
     apply_fair_share_into,
+    apply_fair_share_into_with,
+    apply_fair_share_with,
     apply_rca,
     fair_share,
+    fair_share_with,
+    is_binary,
     rca,
+    rca_leave_one_out,
+    rca_relative_to,
+    rca_relative_to_with,
+    rca_vs_reference,
+    rca_weighted,
+    rca_with,
+    validate_cutoff,
+    world_share,
+    Comparison,
+    RcaVariant,
+    ValueKind,
+    LOG_RCA_FLOOR,
 };
 
 mod proximity;
-pub use proximity::proximity;
+pub use proximity::{
+    coexport_counts,
+    cosine_proximity,
+    proximity,
+    ProximityBasis,
+    ProximityMethod,
+    ProximityTransform,
+};
 
 mod density;
-pub use density::density;
+pub use density::{density, density_row, DensityOptions};
+#[cfg(feature = "rayon")]
+pub use density::{density_par, density_par_in};
+
+mod sparse;
+pub use sparse::{density_sparse, SparseProximity};
 
 mod distance;
 pub use distance::distance;
 
 mod complexity;
-pub use complexity::complexity;
+pub use complexity::{avg_diversity, avg_ubiquity, complexity, complexity_weighted, eci_from_pci, orient, reflection_matrix};
 
 mod error;
 pub use error::Error;
 
-mod smooth;
+mod builder;
+pub use builder::{MinTradeFilter, NegativePolicy, ProductSpaceBuilder, SanitizePolicy};
+
+mod ingest;
+pub use ingest::{
+    Columns,
+    HeaderAliases,
+    IngestPhase,
+    IngestProgress,
+    ParsePolicy,
+    ProgressFn,
+    Record,
+};
+
+pub mod smooth;
+
+mod stats;
+pub use stats::rank_correlation;
+
+mod network;
+pub use network::{atlas_network, maximum_spanning_tree};
+
+mod analysis;
+pub use analysis::YearAnalysis;
+
+mod diagnostics;
+pub use diagnostics::{Diagnostics, REFLECTION_TOLERANCE};
+
+mod transition;
+pub use transition::MIN_BASE_RCA;
+
+mod memory;
+pub use memory::{MatrixMemory, MemoryReport};
+
+mod bootstrap;
+pub use bootstrap::{RankStats, Resample};
+
+mod set;
+pub use set::ProductSpaceSet;
+
+mod align;
+pub use align::AlignMode;
+
+mod validate;
+pub use validate::{near_duplicates, CoverageReport, IndexReport};
+
+mod outlook;
+pub use outlook::complexity_outlook_gain;
+
+mod concentration;
+pub use concentration::export_concentration;
+
+mod single_year;
+
+mod totals;
+pub use totals::Totals;
+
+mod provenance;
+pub use provenance::{Provenance, Smoothing, YearUsage};
+use provenance::smoothing_by_len;
+
+#[cfg(feature = "json")]
+mod snapshot;
+
+#[cfg(feature = "json")]
+mod checked;
 
 // Currently just country and product.
 // May make this more general in the future
@@ -40,39 +138,128 @@ mod smooth;
 //
 // TODO tests for density and proximity
 //
-// TODO separate module for smoothing fns, and then let user choose which
-// smoothing so use (rca binary, averaging, etc) over an already-calculated
-// set of matrixies.
+// TODO let user choose which smoothing to use (rca binary, averaging,
+// etc). `smooth` can already be used on an already-calculated set of
+// matrixes.
+//
+/// There are two rca cutoffs in play:
+///
+/// - the construction cutoff, given to `new` or the builder. This makes
+///   the cached binary rca, which every year's proximity is built on.
+///   It can only be changed with `rebuild_cutoff`.
+/// - the per-call cutoff, given to `rca`, `density`, etc. This only
+///   affects the rca used in that call, so `density(years, Some(1.5))`
+///   on a product space built with a cutoff of 1.0 uses rca >= 1.5
+///   with proximity from rca >= 1.0.
+///
+/// Both cutoffs use the builder's `Comparison` (`>=` by default), and
+/// are given as Balassa rca whatever the `RcaVariant`.
 pub struct ProductSpace {
-    country_idx: HashMap<String, usize>,
-    product_idx: HashMap<String, usize>,
+    country_idx: Arc<HashMap<String, usize>>,
+    product_idx: Arc<HashMap<String, usize>>,
+
+    rca_cutoff: Option<f64>,
+    comparison: Comparison,
+    proximity_basis: ProximityBasis,
+    proximity_method: ProximityMethod,
+    value_kind: ValueKind,
+    rca_variant: RcaVariant,
+    min_trade_filter: Option<MinTradeFilter>,
+    // built with `ProductSpaceBuilder::adjustment`
+    adjusted: bool,
+    negative_policy: NegativePolicy,
+    compute_proximity: bool,
+    sparse_proximity: Option<f64>,
+    interpolate_missing_years: bool,
+    leave_one_out: bool,
+    rca_cap: Option<f64>,
+    sanitize: SanitizePolicy,
+    excluded_countries: HashMap<u32, Vec<String>>,
 
-    #[allow(dead_code)]
-    mcps:                HashMap<u32, DMatrix<f64>>,
+    // None after `drop_mcps`
+    mcps:                Option<HashMap<u32, DMatrix<f64>>>,
     rcas_by_year:        HashMap<u32, DMatrix<f64>>,
     rcas_cutoff_by_year: HashMap<u32, DMatrix<f64>>,
     proximities_by_year: HashMap<u32, DMatrix<f64>>,
+    // instead of `proximities_by_year`, with `sparse_proximity`
+    sparse_proximities_by_year: HashMap<u32, SparseProximity>,
+
+    // multi-year `rca_cutoff`, by sorted years, at most
+    // `CUTOFF_CACHE_CAPACITY`. Cleared on `rebuild_cutoff`
+    cutoff_products: Mutex<HashMap<Vec<u32>, DMatrix<f64>>>,
 }
 
+/// The most year sets `ProductSpace::rca_cutoff` keeps results for
+pub const CUTOFF_CACHE_CAPACITY: usize = 16;
+
 impl ProductSpace {
-    /// if years not found, either returns None or silently skips
+    /// if years not found, either returns None or skips (logged at warn)
     /// for aggregating, will either
     /// for cutoff, rca(t) = 1 if rca(t-1) > cutoff and rca(t-2) > cutoff...
     /// - otherwise just average
+    ///
+    /// None for an invalid cutoff (see `validate_cutoff`), as for every
+    /// method taking a cutoff and returning an Option. Methods returning
+    /// a Result return `Error::InvalidCutoff`; see `try_rca`.
     pub fn rca(
         &self,
         years: &[u32],
         cutoff: Option<f64>,
         ) -> Option<Rca>
     {
-        self.rca_matrix(years, cutoff)
-            .map(|m| {
-                Rca {
-                    country_idx: self.country_idx.clone(),
-                    product_idx: self.product_idx.clone(),
-                    m,
-                }
-            })
+        self.rca_capped(years, cutoff, None)
+    }
+
+    /// `rca`, returning `Error::InvalidCutoff` for an invalid cutoff and
+    /// `Error::MissingYears` if no year is found
+    pub fn try_rca(
+        &self,
+        years: &[u32],
+        cutoff: Option<f64>,
+        ) -> Result<Rca, Error>
+    {
+        self.try_rca_capped(years, cutoff, None)
+    }
+
+    /// `rca`, with values above `rca_cap` clamped to it, after averaging
+    /// (see also `ProductSpaceBuilder::rca_cap`, which caps each year
+    /// before anything else). Like cutoffs, it's given as Balassa rca and
+    /// must be valid as a cutoff. It only tames outliers if there's no
+    /// cutoff; binary rca is unchanged by a cap of at least 1.0.
+    pub fn rca_capped(
+        &self,
+        years: &[u32],
+        cutoff: Option<f64>,
+        rca_cap: Option<f64>,
+        ) -> Option<Rca>
+    {
+        self.try_rca_capped(years, cutoff, rca_cap).ok()
+    }
+
+    fn try_rca_capped(
+        &self,
+        years: &[u32],
+        cutoff: Option<f64>,
+        rca_cap: Option<f64>,
+        ) -> Result<Rca, Error>
+    {
+        validate_cutoff(rca_cap)?;
+
+        let mut m = self.try_rca_matrix(years, cutoff)?;
+        if let Some(cap) = rca_cap {
+            let cap = self.rca_variant.apply(cap);
+            m.apply(|x| if x > cap { cap } else { x });
+        }
+
+        let mut provenance = self.rca_provenance(years, cutoff);
+        provenance.rca_cap = rca_cap;
+
+        Ok(Rca {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            m,
+            provenance,
+        })
     }
 
     fn rca_matrix(
@@ -81,37 +268,66 @@ impl ProductSpace {
         cutoff: Option<f64>,
         ) -> Option<DMatrix<f64>>
     {
+        self.try_rca_matrix(years, cutoff).ok()
+    }
+
+    // every method taking a cutoff gets its rca here or from
+    // `try_binary_rca_matrix`, which validate it
+    fn try_rca_matrix(
+        &self,
+        years: &[u32],
+        cutoff: Option<f64>,
+        ) -> Result<DMatrix<f64>, Error>
+    {
+        validate_cutoff(cutoff)?;
+        let missing = || Error::MissingYears { years: years.to_vec() };
+
+        // cached rca is in the variant's terms, see `RcaVariant`
+        let cutoff = cutoff.map(|c| self.rca_variant.apply(c));
+
         if years.len() > 1 {
+            // ones for the product of binary rcas, zeros for the sum
             let init_matrix = DMatrix::from_element(
                 self.country_idx.len(),
                 self.product_idx.len(),
-                1.0,
+                if cutoff.is_some() { 1.0 } else { 0.0 },
             );
 
             // for cutoff, rca(t) = 1 if rca(t-1) > cutoff and rca(t-2) > cutoff...
             //
             // else just avg the rca
+            let mut found = 0;
             let mut res = years.iter()
-                // silently removes missing years
-                .filter_map(|y| self.rcas_by_year.get(y))
+                // removes missing years, unless interpolated
+                .filter_map(|y| self.window_rca(years, *y))
+                .inspect(|_| found += 1)
                 .fold(init_matrix, |mut z, rca| {
-                    let mut rca_matrix = rca.clone();
-                    if cutoff.is_some() {
-                        apply_fair_share_into(&mut rca_matrix, &mut z, cutoff);
+                    if let Some(cutoff) = cutoff {
+                        // the product of binary rcas, zeroing wherever this
+                        // year doesn't pass rather than taking a binary copy
+                        for (acc, x) in z.iter_mut().zip(rca.iter()) {
+                            if !self.comparison.passes(*x, cutoff) {
+                                *acc = 0.0;
+                            }
+                        }
                     } else {
-                        // just average as default?
-                        // do the sum part here, divide at end
-                        z += rca_matrix;
+                        // do the sum part here, divide at end. Added
+                        // straight from the cached rca, no copy
+                        z += &*rca;
                     }
                     z
                 });
 
+            if found == 0 {
+                return Err(missing());
+            }
+
             // avg if no cutoff
             if cutoff.is_none() {
                 res.apply(|x| x / years.len() as f64)
             }
 
-            Some(res)
+            Ok(res)
         } else if years.len() == 1 {
             // no extra allocation for mcp
             years.get(0)
@@ -119,16 +335,235 @@ impl ProductSpace {
                 .map(|rca| {
                     let mut rca_matrix = rca.clone();
                     if cutoff.is_some() {
-                        apply_fair_share(&mut rca_matrix, cutoff);
+                        apply_fair_share_with(&mut rca_matrix, cutoff, self.comparison);
                     }
                     rca_matrix
                 })
+                .ok_or_else(missing)
         } else {
-            None
+            Err(missing())
+        }
+    }
+
+    // a year's cached rca when aggregating over `years`. A missing year
+    // is skipped, or with `interpolate_missing_years` is interpolated
+    // from the nearest years on either side that are both requested in
+    // `years` and found
+    fn window_rca(&self, years: &[u32], year: u32) -> Option<Cow<DMatrix<f64>>> {
+        if !self.interpolate_missing_years {
+            return year_or_warn(&self.rcas_by_year, year).map(Cow::Borrowed);
+        }
+        if let Some(rca) = self.rcas_by_year.get(&year) {
+            return Some(Cow::Borrowed(rca));
+        }
+
+        match self.interpolation_years(years, year) {
+            Some((before, after)) => {
+                warn!("interpolating missing year {} from {} and {}", year, before, after);
+
+                let weight = f64::from(year - before) / f64::from(after - before);
+                let from = &self.rcas_by_year[&before];
+                let to = &self.rcas_by_year[&after];
+
+                Some(Cow::Owned(from + (to - from) * weight))
+            },
+            None => {
+                warn!("skipping missing year {}", year);
+                None
+            },
+        }
+    }
+
+    // the nearest years on either side of a missing `year`, out of the
+    // requested `years` that are found
+    pub(crate) fn interpolation_years(&self, years: &[u32], year: u32) -> Option<(u32, u32)> {
+        let found = || years.iter().filter(|y| self.rcas_by_year.contains_key(y));
+        let before = found().filter(|y| **y < year).max()?;
+        let after = found().filter(|y| **y > year).min()?;
+
+        Some((*before, *after))
+    }
+
+    pub fn country_index(&self) -> &HashMap<String, usize> {
+        &self.country_idx
+    }
+
+    pub fn product_index(&self) -> &HashMap<String, usize> {
+        &self.product_idx
+    }
+
+    /// the raw mcps the product space was built from, or
+    /// `Error::McpsDropped` after `drop_mcps`, or if built `from_rcas`
+    pub fn mcps(&self) -> Result<&HashMap<u32, DMatrix<f64>>, Error> {
+        self.mcps.as_ref().ok_or(Error::McpsDropped)
+    }
+
+    /// Frees the results kept for repeated calls (see `rca_cutoff`)
+    pub fn clear_cache(&self) {
+        self.cutoff_cache().clear();
+    }
+
+    // the cache only holds finished results, so one left by a panicking
+    // thread is still good
+    fn cutoff_cache(&self) -> MutexGuard<HashMap<Vec<u32>, DMatrix<f64>>> {
+        self.cutoff_products.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Frees the raw mcps, when only rca, proximity and what's derived
+    /// from them are needed. Afterwards, anything using the raw values
+    /// (`mcps`, `rollup`, `rca_relative_to`) returns `Error::McpsDropped`.
+    pub fn drop_mcps(&mut self) {
+        self.mcps = None;
+    }
+
+    /// the construction cutoff, which the cached binary rca and
+    /// proximity are built on. See `ProductSpace`
+    pub fn build_cutoff(&self) -> Option<f64> {
+        self.rca_cutoff
+    }
+
+    /// countries left out of rca by the min trade filter, by year
+    pub fn excluded_countries(&self) -> &HashMap<u32, Vec<String>> {
+        &self.excluded_countries
+    }
+
+    /// rca for each requested year separately, rather than aggregated.
+    /// Years not found are absent from the result.
+    pub fn rca_by_year(
+        &self,
+        years: &[u32],
+        cutoff: Option<f64>,
+        ) -> HashMap<u32, Rca>
+    {
+        years.iter()
+            .filter_map(|y| {
+                self.rca(&[*y], cutoff)
+                    .map(|rca| (*y, rca))
+            })
+            .collect()
+    }
+
+    /// rca with the world aggregates taken over `peer_countries` only,
+    /// for every country. See `rca_relative_to`.
+    ///
+    /// This is calculated from the mcps, so the min trade filter isn't
+    /// applied. Over several years, the rca is averaged. Years not found
+    /// are skipped, and `Error::MissingYears` is returned if none are.
+    /// The sanitize policy (including `ZeroUntraded`) is applied to each
+    /// year's rca, then the rca variant. Mcps are taken as the builder's
+    /// `ValueKind`.
+    pub fn rca_relative_to(
+        &self,
+        peer_countries: &[&str],
+        years: &[u32],
+        ) -> Result<Rca, Error>
+    {
+        let mcps = self.mcps()?;
+
+        let peer_rows = peer_countries.iter()
+            .map(|c| lookup(&self.country_idx, c, "country"))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut mean = smooth::RunningMean::new(self.country_idx.len(), self.product_idx.len());
+        for (y, mcp) in years.iter().filter_map(|y| year_or_warn(mcps, *y).map(|mcp| (y, mcp))) {
+            let mut rca = rca_relative_to_with(mcp, &peer_rows, self.value_kind);
+            if self.sanitize == SanitizePolicy::ZeroUntraded {
+                builder::zero_untraded_year(&mut rca, *y, &self.product_idx);
+            }
+            builder::sanitize(&mut rca, self.sanitize, *y, "rca")?;
+            rca.apply(|x| self.rca_variant.apply(x));
+            mean.add(&rca);
+        }
+
+        let m = mean.into_mean()
+            .ok_or_else(|| Error::MissingYears { years: years.to_vec() })?;
+
+        Ok(Rca {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            m,
+            provenance: self.provenance(years, None, smoothing_by_len(years, Smoothing::Average)),
+        })
+    }
+
+    /// each country's share of world exports of each product, from the
+    /// raw mcps (see `world_share`). Over several years, exports are
+    /// summed before the share is taken. Years not found are skipped.
+    ///
+    /// Products no one exports are NaN, or 0.0 with the `ZeroOut`
+    /// sanitize policy. Returns `Error::UnsupportedValueKind` for
+    /// `ValueKind::Shares`, which has no world totals.
+    pub fn world_share(
+        &self,
+        years: &[u32],
+        ) -> Result<WorldShare, Error>
+    {
+        if self.value_kind == ValueKind::Shares {
+            return Err(Error::UnsupportedValueKind { method: "world_share".into() });
+        }
+
+        let total = self.summed_mcps(years)?;
+
+        let mut m = world_share(&total);
+        m.apply(|x| self.sanitized(x));
+
+        Ok(WorldShare {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            m,
+        })
+    }
+
+    // raw mcps summed over years, skipping years not found
+    fn summed_mcps(&self, years: &[u32]) -> Result<DMatrix<f64>, Error> {
+        let mcps = self.mcps()?;
+
+        let mut total: Option<DMatrix<f64>> = None;
+        for mcp in years.iter().filter_map(|y| year_or_warn(mcps, *y)) {
+            match total {
+                Some(ref mut total) => *total += mcp,
+                None => total = Some(mcp.clone()),
+            }
         }
+
+        total.ok_or_else(|| Error::MissingYears { years: years.to_vec() })
+    }
+
+    /// rca for each year in the product space, in year order, calculated
+    /// as the iterator is advanced, so only one year's result needs to be
+    /// held at a time. The indexes are shared, not copied.
+    ///
+    /// Empty for an invalid cutoff (see `validate_cutoff`).
+    pub fn iter_rca(&self, cutoff: Option<f64>) -> impl Iterator<Item=(u32, Rca)> + '_ {
+        self.years().into_iter()
+            .filter_map(move |year| {
+                self.rca(&[year], cutoff)
+                    .map(|rca| (year, rca))
+            })
     }
 
-    /// for working with cutoff-on-init rca only
+    /// The binary Mcp: 1.0 where rca passes the cutoff (the fair share
+    /// default of 1.0 if none is given), else 0.0.
+    ///
+    /// For one year, that year's binary rca. Over several years, 1.0 only
+    /// where rca passes the cutoff in every year found; years not found
+    /// are skipped. None if no year is found, or the cutoff is invalid.
+    pub fn mcp_binary(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Option<Rca>
+    {
+        self.rca(years, Some(rca_cutoff.unwrap_or(1.0)))
+    }
+
+    /// for working with cutoff-on-init rca only.
+    ///
+    /// Over several years, the result is kept for the same set of years
+    /// (in any order), so a repeated call copies it instead of
+    /// multiplying every year again. Results are kept for up to
+    /// `CUTOFF_CACHE_CAPACITY` year sets; past that, an arbitrary one is
+    /// dropped. See `clear_cache`
     pub fn rca_cutoff(
         &self,
         years: &[u32],
@@ -140,6 +575,11 @@ impl ProductSpace {
                     country_idx: self.country_idx.clone(),
                     product_idx: self.product_idx.clone(),
                     m,
+                    provenance: self.provenance(
+                        years,
+                        self.rca_cutoff,
+                        smoothing_by_len(years, Smoothing::CutoffInEveryYear),
+                    ),
                 }
             })
     }
@@ -150,23 +590,37 @@ impl ProductSpace {
         ) -> Option<DMatrix<f64>>
     {
         if years.len() > 1 {
+            let mut key = years.to_vec();
+            key.sort();
+            key.dedup();
+
+            if let Some(res) = self.cutoff_cache().get(&key) {
+                return Some(res.clone());
+            }
+
             let init_matrix = DMatrix::from_element(
                 self.country_idx.len(),
                 self.product_idx.len(),
                 1.0,
             );
 
-            // for cutoff, rca(t) = 1 if rca(t-1) > cutoff and rca(t-2) > cutoff...
-            //
-            // else just avg the rca
+            // rca(t) = 1 if rca(t-1) > cutoff and rca(t-2) > cutoff...
             let res = years.iter()
-                // silently removes missing years
-                .filter_map(|y| self.rcas_by_year.get(y))
+                // removes missing years
+                .filter_map(|y| year_or_warn(&self.rcas_cutoff_by_year, *y))
                 .fold(init_matrix, |mut z, rca| {
-                    z = z.component_mul(&rca);
+                    z.component_mul_assign(rca);
                     z
                 });
 
+            let mut cache = self.cutoff_cache();
+            if cache.len() >= CUTOFF_CACHE_CAPACITY {
+                if let Some(evicted) = cache.keys().next().cloned() {
+                    cache.remove(&evicted);
+                }
+            }
+            cache.insert(key, res.clone());
+
             Some(res)
         } else if years.len() == 1 {
             // no extra allocation for mcp
@@ -178,7 +632,7 @@ impl ProductSpace {
         }
     }
 
-    /// if years not found, either returns None or silently skips
+    /// if years not found, either returns None or skips (logged at warn)
     /// for aggregating, will either
     /// for cutoff, rca(t) = 1 if rca(t-1) > cutoff and rca(t-2) > cutoff...
     /// - otherwise just average
@@ -196,165 +650,2051 @@ impl ProductSpace {
             })
     }
 
-    fn proximity_matrix(
+    /// Like `proximity`, but returns `Error::MissingYears` if no year is
+    /// found, and `Error::NonFinite` with `SanitizePolicy::ErrorOnNonFinite`
+    /// if proximity isn't computed on build and is calculated now with
+    /// NaN or inf. `proximity` returns None for those instead.
+    pub fn try_proximity(
         &self,
         years: &[u32],
-        ) -> Option<DMatrix<f64>>
+        ) -> Result<Proximity, Error>
     {
-        if years.len() > 1 {
-            let proximities = years.iter()
-                // silently removes missing years
-                // TODO what happens when no years?
-                .filter_map(|y| self.proximities_by_year.get(y));
-
-            let res = smooth::avg(proximities, self.product_idx.len(), years.len());
+        let m = self.try_proximity_matrix(years)?
+            .ok_or_else(|| Error::MissingYears { years: years.to_vec() })?;
 
-            Some(res)
-        } else if years.len() == 1 {
-            // no extra allocation for mcp
-            years.get(0)
-                .and_then(|y| self.proximities_by_year.get(y))
-                .cloned()
-        } else {
-            None
-        }
+        Ok(Proximity {
+            product_idx: self.product_idx.clone(),
+            m,
+        })
     }
 
-    pub fn density(
+    /// proximity calculated once from the rca of the whole window,
+    /// rather than averaged over per-year proximities as in `proximity`.
+    /// With `ProximityBasis::BinaryRca` the window's binary rca is used:
+    /// rca past the cutoff in every year, or the fair share cutoff of the
+    /// average rca if no cutoff is given (as in `complexity`). With
+    /// `ProximityBasis::ContinuousRca` the average rca is used.
+    ///
+    /// Pass it to `density_with_proximity` for a density whose rca and
+    /// proximity come from the same binary rca.
+    ///
+    /// Returns `Error::MissingYears` if no year is found.
+    pub fn window_proximity(
         &self,
         years: &[u32],
         rca_cutoff: Option<f64>,
-        ) -> Option<Density>
+        ) -> Result<Proximity, Error>
     {
-        self.density_matrix(years, rca_cutoff)
-            .map(|m| {
-                Density {
-                    country_idx: self.country_idx.clone(),
-                    product_idx: self.product_idx.clone(),
-                    m,
-                }
-            })
+        let rca = match self.proximity_basis {
+            ProximityBasis::BinaryRca => self.try_binary_rca_matrix(years, rca_cutoff)?,
+            ProximityBasis::ContinuousRca => {
+                // unused, but still has to be valid
+                validate_cutoff(rca_cutoff)?;
+                Cow::Owned(self.try_rca_matrix(years, None)?)
+            },
+        };
+
+        let (_, policy) = self.proximity_inputs();
+        let m = builder::year_proximity(&rca, self.proximity_method, policy, years[0])?;
+
+        Ok(Proximity {
+            product_idx: self.product_idx.clone(),
+            m,
+        })
     }
 
-    pub fn density_matrix(
+    /// product x product count of countries with binary rca in both
+    /// products, indexed by `product_index`. See `coexport_counts`
+    pub fn coexport_counts(
         &self,
         years: &[u32],
         rca_cutoff: Option<f64>,
         ) -> Option<DMatrix<f64>>
     {
-        let rca = self.rca_matrix(years, rca_cutoff);
-        let proximity = self.proximity_matrix(years);
+        self.binary_rca_matrix(years, rca_cutoff)
+            .map(|rca| coexport_counts(&rca))
+    }
 
-        if rca.is_some() && proximity.is_some() {
-            let rca = rca.unwrap();
-            let proximity = proximity.unwrap();
+    /// borrows the cached proximity for a single year, without cloning.
+    /// None if the product space was built without computing proximity,
+    /// or with sparse proximity (see `sparse_proximity_ref`).
+    pub fn proximity_ref(&self, year: u32) -> Option<&DMatrix<f64>> {
+        self.proximities_by_year.get(&year)
+    }
 
-            Some(density(&rca, &proximity))
-        } else {
-            None
+    /// borrows the cached sparse proximity for a single year. None unless
+    /// the product space was built with
+    /// `ProductSpaceBuilder::sparse_proximity`.
+    pub fn sparse_proximity_ref(&self, year: u32) -> Option<&SparseProximity> {
+        self.sparse_proximities_by_year.get(&year)
+    }
+
+    // cached proximity for a year, or calculated now if the product
+    // space was built without computing proximity (recalculated every
+    // call, deliberately not memoized: see `compute_proximity`). None if
+    // the year isn't found; `Error::NonFinite` if it's calculated now
+    // with `ErrorOnNonFinite` and isn't finite.
+    fn try_year_proximity(&self, year: u32) -> Result<Option<Cow<DMatrix<f64>>>, Error> {
+        if self.compute_proximity {
+            if self.sparse_proximity.is_some() {
+                return Ok(self.sparse_proximities_by_year.get(&year)
+                    .map(|sparse| Cow::Owned(sparse.to_dense())));
+            }
+            return Ok(self.proximities_by_year.get(&year).map(Cow::Borrowed));
+        }
+
+        let (rcas, policy) = self.proximity_inputs();
+
+        match rcas.get(&year) {
+            Some(rca) => {
+                let prox = builder::year_proximity(rca, self.proximity_method, policy, year)?;
+                Ok(Some(Cow::Owned(prox)))
+            },
+            None => Ok(None),
         }
     }
-}
 
-impl ProductSpace {
-    pub fn new(
-        country_idx: HashMap<String, usize>,
-        product_idx: HashMap<String, usize>,
-        mcps: HashMap<u32, DMatrix<f64>>,
-        rca_cutoff: Option<f64>,
-        ) -> Self
+    // rca proximity is calculated from, and the sanitize policy for
+    // proximity calculated outside of build
+    fn proximity_inputs(&self) -> (&HashMap<u32, DMatrix<f64>>, SanitizePolicy) {
+        let rcas = match self.proximity_basis {
+            ProximityBasis::BinaryRca => &self.rcas_cutoff_by_year,
+            ProximityBasis::ContinuousRca => &self.rcas_by_year,
+        };
+
+        (rcas, self.sanitize)
+    }
+
+    /// proximity among `products` only, indexed in the order given
+    /// (duplicates are ignored). Only their rca columns are used, so the
+    /// full product x product matrix isn't built; the result is the same
+    /// as that block of `proximity`. Over several years, it's averaged
+    /// as in `proximity`.
+    ///
+    /// Returns `Error::MissingIndex` for an unknown product, and
+    /// `Error::MissingYears` if no year is found.
+    pub fn proximity_subset(
+        &self,
+        products: &[&str],
+        years: &[u32],
+        ) -> Result<Proximity, Error>
     {
-        let rcas_by_year: HashMap<_,_> = mcps.iter()
-            .map(|(year, mcp)| {
-                let rca_matrix = rca(&mcp);
-                (*year, rca_matrix)
-            })
-            .collect();
+        let mut subset: Vec<&str> = Vec::with_capacity(products.len());
+        for product in products {
+            if !subset.contains(product) {
+                subset.push(*product);
+            }
+        }
 
-        let rcas_cutoff_by_year: HashMap<_,_> = mcps.iter()
-            .map(|(year, mcp)| {
-                let mut rca_matrix = rca(&mcp);
-                apply_fair_share(&mut rca_matrix, rca_cutoff);
+        let cols = subset.iter()
+            .map(|p| lookup(&self.product_idx, p, "product"))
+            .collect::<Result<Vec<_>, _>>()?;
 
-                (*year, rca_matrix)
-            })
-            .collect();
+        let (rcas, policy) = self.proximity_inputs();
 
-        let proximities_by_year: HashMap<_,_> = rcas_cutoff_by_year.iter()
-            .map(|(year, rca)| {
-                let mut prox = proximity(&rca);
-                // TODO check if this zeroing is ok
-                // This fixed the "everything is Nan issue
-                prox.apply(|x| if x.is_nan() { 0.0 } else { x });
-                (*year, prox)
-            })
-            .collect();
+        if !years.iter().any(|y| rcas.contains_key(y)) {
+            return Err(Error::MissingYears { years: years.to_vec() });
+        }
 
-        Self {
-            country_idx,
-            product_idx,
-            mcps,
-            rcas_by_year,
-            rcas_cutoff_by_year,
-            proximities_by_year,
+        let mut mean = smooth::RunningMean::new(cols.len(), cols.len());
+        for y in years {
+            match year_or_warn(rcas, *y) {
+                Some(rca) => {
+                    let rca = rca.select_columns(&cols);
+                    mean.add(&builder::year_proximity(&rca, self.proximity_method, policy, *y)?);
+                },
+                // missing years count as zeros, see `smooth::avg`
+                None => mean.add_missing(),
+            }
         }
-    }
-}
 
-// TODO put indexes in Arc to avoid copying?
-pub struct Rca {
-    country_idx: HashMap<String, usize>,
-    product_idx: HashMap<String, usize>,
-    m: DMatrix<f64>,
-}
+        let m = mean.into_mean().expect("at least one year found");
 
-impl Mcp for Rca {
-    fn matrix(&self) -> &DMatrix<f64> {
-        &self.m
-    }
-    fn country_index(&self) -> &HashMap<String, usize> {
-        &self.country_idx
+        Ok(Proximity {
+            product_idx: Arc::new(subset.iter()
+                .enumerate()
+                .map(|(i, p)| (p.to_string(), i))
+                .collect()),
+            m,
+        })
     }
-    fn product_index(&self) -> &HashMap<String, usize> {
-        &self.product_idx
+
+    // `try_proximity_matrix` for methods returning Option, with an
+    // error logged and taken as None
+    fn proximity_matrix(
+        &self,
+        years: &[u32],
+        ) -> Option<DMatrix<f64>>
+    {
+        self.try_proximity_matrix(years)
+            .unwrap_or_else(|err| {
+                warn!("{}", err);
+                None
+            })
     }
-}
 
-// TODO put indexes in Arc to avoid copying?
-// TODO figure out how this calc shown publicly.
-#[allow(dead_code)]
-pub struct Proximity {
-    product_idx: HashMap<String, usize>,
-    m: DMatrix<f64>,
-}
+    fn try_proximity_matrix(
+        &self,
+        years: &[u32],
+        ) -> Result<Option<DMatrix<f64>>, Error>
+    {
+        if years.len() > 1 {
+            // one year at a time, so proximity calculated outside of
+            // build isn't all held at once
+            let n = self.product_idx.len();
+            let mut mean = smooth::RunningMean::new(n, n);
+            let mut found = 0;
 
-// TODO put indexes in Arc to avoid copying?
-pub struct Density {
-    country_idx: HashMap<String, usize>,
-    product_idx: HashMap<String, usize>,
-    m: DMatrix<f64>,
-}
+            for y in years {
+                match self.try_year_proximity(*y)? {
+                    Some(prox) => {
+                        mean.add(&prox);
+                        found += 1;
+                    },
+                    None => {
+                        // missing years count as zeros, see `smooth::avg`
+                        warn!("skipping missing year {}", y);
+                        mean.add_missing();
+                    },
+                }
+            }
 
-impl Mcp for Density {
-    fn matrix(&self) -> &DMatrix<f64> {
-        &self.m
+            if found == 0 {
+                return Ok(None);
+            }
+            Ok(mean.into_mean())
+        } else if years.len() == 1 {
+            Ok(self.try_year_proximity(years[0])?
+                .map(|prox| prox.into_owned()))
+        } else {
+            Ok(None)
+        }
     }
-    fn country_index(&self) -> &HashMap<String, usize> {
-        &self.country_idx
+
+    /// Over several years, the two halves of density are aggregated
+    /// separately: the rca is as in `rca` (1.0 where rca passes the
+    /// cutoff in every year, or the average rca if no cutoff is given),
+    /// while proximity is the average of each year's proximity, as in
+    /// `proximity`. So a product pair that coincides in only some years
+    /// still contributes. For proximity from the window's binary rca as
+    /// well, use `window_proximity` with `density_with_proximity`.
+    ///
+    /// To floor or transform proximity first, see `density_with_options`.
+    pub fn density(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Option<Density>
+    {
+        self.density_with_options(years, rca_cutoff, DensityOptions::default())
+    }
+
+    /// `density`, returning `Error::InvalidCutoff` for an invalid cutoff,
+    /// `Error::MissingYears` if no year is found, and `Error::NonFinite`
+    /// for proximity calculated outside of build (see
+    /// `SanitizePolicy::ErrorOnNonFinite`)
+    pub fn try_density(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<Density, Error>
+    {
+        self.try_density_with_options(years, rca_cutoff, DensityOptions::default())
+    }
+
+    /// `density` with proximity adjusted first; see `DensityOptions`
+    pub fn density_with_options(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        options: DensityOptions,
+        ) -> Option<Density>
+    {
+        ok_or_warn(self.try_density_with_options(years, rca_cutoff, options))
+    }
+
+    /// `try_density` with proximity adjusted first; see `DensityOptions`
+    pub fn try_density_with_options(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        options: DensityOptions,
+        ) -> Result<Density, Error>
+    {
+        let mut provenance = self.rca_provenance(years, rca_cutoff);
+        provenance.min_proximity = options.min_proximity;
+        provenance.transform = options.transform;
+
+        Ok(Density {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            m: self.try_density_matrix_with_options(years, rca_cutoff, options)?,
+            provenance,
+        })
+    }
+
+    pub fn density_matrix(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Option<DMatrix<f64>>
+    {
+        self.density_matrix_with_options(years, rca_cutoff, DensityOptions::default())
+    }
+
+    fn try_density_matrix(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<DMatrix<f64>, Error>
+    {
+        self.try_density_matrix_with_options(years, rca_cutoff, DensityOptions::default())
+    }
+
+    pub fn density_matrix_with_options(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        options: DensityOptions,
+        ) -> Option<DMatrix<f64>>
+    {
+        ok_or_warn(self.try_density_matrix_with_options(years, rca_cutoff, options))
+    }
+
+    fn try_density_matrix_with_options(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        options: DensityOptions,
+        ) -> Result<DMatrix<f64>, Error>
+    {
+        let DensityOptions { min_proximity, transform } = options;
+
+        // one year of sparse proximity, without making it dense. A
+        // transform may not keep 0.0 at 0.0, so it needs the dense matrix
+        if years.len() == 1 && transform.is_none() {
+            if let Some(sparse) = self.sparse_proximities_by_year.get(&years[0]) {
+                let rca = self.try_rca_matrix(years, rca_cutoff)?;
+                let mut res = match min_proximity {
+                    Some(floor) => density_sparse(&rca, &sparse.with_threshold(floor)),
+                    None => density_sparse(&rca, sparse),
+                };
+                res.apply(|x| self.sanitized(x));
+                return Ok(res);
+            }
+        }
+
+        let rca = self.try_rca_matrix(years, rca_cutoff)?;
+        let mut proximity = self.try_proximity_matrix(years)?
+            .ok_or_else(|| Error::MissingYears { years: years.to_vec() })?;
+
+        // floored links stay at 0.0 whatever the transform maps 0.0 to,
+        // and the diagonal (a product's proximity to itself) isn't
+        // transformed
+        if min_proximity.is_some() || transform.is_some() {
+            let floor = min_proximity.unwrap_or(std::f64::NEG_INFINITY);
+            for i in 0..proximity.nrows() {
+                for j in 0..proximity.ncols() {
+                    let x = proximity[(i, j)];
+                    proximity[(i, j)] = if x < floor {
+                        0.0
+                    } else if i == j {
+                        x
+                    } else {
+                        transform.map_or(x, |t| t.apply(x))
+                    };
+                }
+            }
+        }
+
+        let mut res = density(&rca, &proximity);
+        res.apply(|x| self.sanitized(x));
+
+        Ok(res)
+    }
+
+    /// density from this product space's rca and a proximity from
+    /// elsewhere, e.g. a reference product space. The proximity must have
+    /// the same product index (names and positions), or
+    /// `Error::IndexMismatch` is returned.
+    pub fn density_with_proximity(
+        &self,
+        rca_years: &[u32],
+        rca_cutoff: Option<f64>,
+        external: &Proximity,
+        ) -> Result<Density, Error>
+    {
+        if *external.product_idx != *self.product_idx {
+            return Err(Error::IndexMismatch { index: "product".into() });
+        }
+
+        let rca = self.try_rca_matrix(rca_years, rca_cutoff)?;
+
+        let mut m = density(&rca, &external.m);
+        m.apply(|x| self.sanitized(x));
+
+        Ok(Density {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            m,
+            provenance: self.rca_provenance(rca_years, rca_cutoff),
+        })
+    }
+
+    /// density with rca and proximity from different windows, e.g. rca
+    /// for the latest year against proximity averaged over a longer,
+    /// more stable window. Each is aggregated as in `density`; with the
+    /// same years for both, this is `density(years, rca_cutoff)`.
+    ///
+    /// The windows don't need to overlap: proximity is taken as a
+    /// property of the product space, so rca from one period can be
+    /// weighted by proximity from another. The provenance lists the rca
+    /// years, and the proximity years as `proximity_years`.
+    ///
+    /// Returns `Error::MissingYears` with the window's years if no year
+    /// of either window is found.
+    pub fn density_windows(
+        &self,
+        rca_years: &[u32],
+        proximity_years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<Density, Error>
+    {
+        let rca = self.try_rca_matrix(rca_years, rca_cutoff)?;
+        let proximity = self.try_proximity_matrix(proximity_years)?
+            .ok_or_else(|| Error::MissingYears { years: proximity_years.to_vec() })?;
+
+        let mut m = density(&rca, &proximity);
+        m.apply(|x| self.sanitized(x));
+
+        let mut provenance = self.rca_provenance(rca_years, rca_cutoff);
+        provenance.proximity_years = Some(proximity_years.to_vec());
+
+        Ok(Density {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            m,
+            provenance,
+        })
+    }
+
+    /// all years in the product space, sorted
+    pub fn years(&self) -> Vec<u32> {
+        let mut years: Vec<_> = self.rcas_by_year.keys().cloned().collect();
+        years.sort();
+        years
+    }
+
+    /// rca of one country and product for every year, sorted by year.
+    /// With a cutoff, values are the fair share 0.0 or 1.0
+    pub fn rca_series(
+        &self,
+        country: &str,
+        product: &str,
+        cutoff: Option<f64>,
+        ) -> Result<Vec<(u32, f64)>, Error>
+    {
+        validate_cutoff(cutoff)?;
+
+        let row = lookup(&self.country_idx, country, "country")?;
+        let col = lookup(&self.product_idx, product, "product")?;
+
+        let res = self.years().into_iter()
+            .map(|year| {
+                let x = self.rcas_by_year[&year][(row, col)];
+                (year, fair_share_value(x, cutoff.map(|c| self.rca_variant.apply(c)), self.comparison))
+            })
+            .collect();
+
+        Ok(res)
+    }
+
+    /// density of one country and product for every year, sorted by year.
+    /// Only the one density cell is calculated for each year. The year's
+    /// proximity is borrowed when cached as a dense matrix; with
+    /// `sparse_proximity`, or without `compute_proximity`, the full
+    /// proximity is built for each year.
+    pub fn density_series(
+        &self,
+        country: &str,
+        product: &str,
+        rca_cutoff: Option<f64>,
+        ) -> Result<Vec<(u32, f64)>, Error>
+    {
+        validate_cutoff(rca_cutoff)?;
+        let rca_cutoff = rca_cutoff.map(|c| self.rca_variant.apply(c));
+
+        let row = lookup(&self.country_idx, country, "country")?;
+        let col = lookup(&self.product_idx, product, "product")?;
+
+        let res = self.years().into_iter()
+            .filter_map(|year| {
+                let rca = &self.rcas_by_year[&year];
+                let proximity = match self.try_year_proximity(year) {
+                    Ok(proximity) => proximity?,
+                    Err(err) => return Some(Err(err)),
+                };
+
+                let mut numerator = 0.0;
+                let mut denominator = 0.0;
+                for k in 0..rca.ncols() {
+                    let phi = proximity[(k, col)];
+                    numerator += fair_share_value(rca[(row, k)], rca_cutoff, self.comparison) * phi;
+                    denominator += phi;
+                }
+
+                Some(Ok((year, self.sanitized(numerator / denominator))))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(res)
+    }
+
+    /// density for a country if it lost its advantage in `products`:
+    /// they're zeroed in the country's binary rca before density is
+    /// recalculated. Density is returned for all products.
+    pub fn density_without(
+        &self,
+        country: &str,
+        products: &[&str],
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<HashMap<String, f64>, Error>
+    {
+        let row = lookup(&self.country_idx, country, "country")?;
+        let cols = products.iter()
+            .map(|p| lookup(&self.product_idx, p, "product"))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+        let proximity = self.try_proximity_matrix(years)?
+            .ok_or_else(|| Error::MissingYears { years: years.to_vec() })?;
+
+        let mut rca_row = rca.row(row).into_owned();
+        for col in cols {
+            rca_row[col] = 0.0;
+        }
+
+        let res = density_row(&rca_row, &proximity);
+
+        Ok(self.product_idx.iter()
+            .map(|(product, idx)| (product.clone(), self.sanitized(res[*idx])))
+            .collect())
+    }
+
+    /// A diversification path for a country: starting from its binary
+    /// rca, the highest density product it doesn't have yet is added,
+    /// density is recalculated, and so on for up to `steps` products.
+    /// Returns the products in the order added, each with its density
+    /// when it was added. Proximity is held fixed.
+    ///
+    /// This is greedy, taking the best next step each time, not the
+    /// path that does best over all the steps. Ties go by product
+    /// name. Stops early once no product left has a defined density.
+    pub fn diversification_path(
+        &self,
+        country: &str,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        steps: usize,
+        ) -> Result<Vec<(String, f64)>, Error>
+    {
+        let row = lookup(&self.country_idx, country, "country")?;
+
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+        let proximity = self.try_proximity_matrix(years)?
+            .ok_or_else(|| Error::MissingYears { years: years.to_vec() })?;
+
+        let products = names_by_index(&self.product_idx);
+        let mut rca_row = rca.row(row).into_owned();
+        let mut res = Vec::new();
+
+        for _ in 0..steps {
+            let density = density_row(&rca_row, &proximity);
+
+            let next = (0..rca_row.len())
+                .filter(|col| rca_row[*col] == 0.0 && !density[*col].is_nan())
+                .min_by(|p, q| stats::cmp_ranked((products[*p], density[*p]), (products[*q], density[*q])));
+
+            match next {
+                Some(col) => {
+                    rca_row[col] = 1.0;
+                    res.push((products[col].to_owned(), self.sanitized(density[col])));
+                },
+                None => break,
+            }
+        }
+
+        Ok(res)
+    }
+
+    // density (which isn't cached) with the `ZeroOut` policy.
+    // `ZeroUntraded` is rca only, see `SanitizePolicy`
+    fn sanitized(&self, x: f64) -> f64 {
+        if self.sanitize == SanitizePolicy::ZeroOut && !x.is_finite() {
+            0.0
+        } else {
+            x
+        }
+    }
+
+    /// rca with a cutoff, or the fair share default of 1.0 if no cutoff
+    /// is given. Borrowed from the cache for one year at the
+    /// construction cutoff.
+    fn binary_rca_matrix(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Option<Cow<DMatrix<f64>>>
+    {
+        self.try_binary_rca_matrix(years, rca_cutoff).ok()
+    }
+
+    fn try_binary_rca_matrix(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<Cow<DMatrix<f64>>, Error>
+    {
+        // the cached binary rca, for one year at the construction cutoff
+        // (which was validated on build)
+        if years.len() == 1 && rca_cutoff.unwrap_or(1.0) == self.rca_cutoff.unwrap_or(1.0) {
+            return self.rcas_cutoff_by_year.get(&years[0]).map(Cow::Borrowed)
+                .ok_or_else(|| Error::MissingYears { years: years.to_vec() });
+        }
+
+        let mut rca = self.try_rca_matrix(years, rca_cutoff)?;
+        if rca_cutoff.is_none() {
+            apply_fair_share_with(&mut rca, Some(builder::binary_cutoff(self.rca_variant, None)), self.comparison);
+        }
+        Ok(Cow::Owned(rca))
+    }
+
+    /// complexity is calculated from the binary rca. If no cutoff is
+    /// given, the default fair share cutoff of 1.0 is applied.
+    ///
+    /// eci is oriented to increase with diversity (see `orient`), so the
+    /// sign is consistent across years.
+    pub fn complexity(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Option<Complexity>
+    {
+        self.try_complexity(years, rca_cutoff).ok()
+    }
+
+    /// `complexity`, returning `Error::InvalidCutoff` for an invalid
+    /// cutoff and `Error::MissingYears` if no year is found
+    pub fn try_complexity(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<Complexity, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+        let (eci, pci) = complexity::complexity_by_diversity(&rca);
+
+        Ok(Complexity {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            eci: eci.column(0).into_owned(),
+            pci: pci.column(0).into_owned(),
+            provenance: self.binary_provenance(years, rca_cutoff),
+        })
+    }
+
+    /// value-weighted complexity from the raw mcps (see
+    /// `complexity_weighted`), summed over years. Years not found are
+    /// skipped.
+    ///
+    /// eci is oriented to increase with diversity, the number of products
+    /// a country exports at all. Not directly comparable to `complexity`.
+    pub fn complexity_weighted(
+        &self,
+        years: &[u32],
+        ) -> Result<Complexity, Error>
+    {
+        let total = self.summed_mcps(years)?;
+
+        let (mut eci, mut pci) = complexity_weighted(&total);
+        let diversity: Vec<f64> = total.row_iter()
+            .map(|row| row.iter().filter(|x| **x > 0.0).count() as f64)
+            .collect();
+        orient(&mut eci, &mut pci, &diversity)?;
+
+        Ok(Complexity {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            eci: eci.column(0).into_owned(),
+            pci: pci.column(0).into_owned(),
+            provenance: self.provenance(years, None, smoothing_by_len(years, Smoothing::Sum)),
+        })
+    }
+
+    /// Like `complexity`, but with the method of reflections restricted to
+    /// `countries` and `products` (all of them for None), e.g. to match
+    /// the country set of a published study.
+    ///
+    /// rca is still calculated against the whole world; only the cells
+    /// of the binary rca used change. Diversity and ubiquity are counted
+    /// within the restriction: a country's diversity is the number of
+    /// `products` it has binary rca in, and a product's ubiquity the
+    /// number of `countries` with binary rca in it. As in `complexity`,
+    /// a country with none of `products` (or a product with none of
+    /// `countries`) makes every value NaN, so should be left out.
+    ///
+    /// The result is indexed by only the countries and products used.
+    /// Returns `Error::MissingIndex` for an unknown name,
+    /// `Error::InvalidCutoff`, or `Error::MissingYears`.
+    pub fn complexity_over(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        countries: Option<&[&str]>,
+        products: Option<&[&str]>,
+        ) -> Result<Complexity, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+
+        let (country_idx, rows) = restricted_index(&self.country_idx, countries, "country")?;
+        let (product_idx, cols) = restricted_index(&self.product_idx, products, "product")?;
+
+        let rca = DMatrix::from_fn(rows.len(), cols.len(), |i, j| rca[(rows[i], cols[j])]);
+        let (eci, pci) = complexity::complexity_by_diversity(&rca);
+
+        Ok(Complexity {
+            country_idx,
+            product_idx,
+            eci: eci.column(0).into_owned(),
+            pci: pci.column(0).into_owned(),
+            provenance: self.binary_provenance(years, rca_cutoff),
+        })
+    }
+
+    /// Like `complexity`, but eci is oriented to correlate positively with
+    /// `reference` (e.g. last year's eci) instead of diversity, as in
+    /// `orient`. Only countries in `reference` are used for the
+    /// correlation.
+    pub fn complexity_oriented(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        reference: &HashMap<String, f64>,
+        ) -> Result<Complexity, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+        let (mut eci, mut pci) = complexity::complexity_by_diversity(&rca);
+
+        // countries not in `reference` take the mean of those that are,
+        // which leaves them out of the covariance, so the sign of the
+        // correlation is the same as over those countries only
+        let given: Vec<f64> = self.country_idx.keys()
+            .filter_map(|country| reference.get(country).cloned())
+            .collect();
+        let mean = given.iter().sum::<f64>() / given.len() as f64;
+        let mut reference_values = vec![mean; self.country_idx.len()];
+        for (country, idx) in self.country_idx.iter() {
+            if let Some(x) = reference.get(country) {
+                reference_values[*idx] = *x;
+            }
+        }
+        orient(&mut eci, &mut pci, &reference_values)?;
+
+        Ok(Complexity {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            eci: eci.column(0).into_owned(),
+            pci: pci.column(0).into_owned(),
+            provenance: self.binary_provenance(years, rca_cutoff),
+        })
+    }
+
+    /// Average pci of the products each country has binary rca in, with
+    /// pci from `complexity` for the same years and cutoff.
+    ///
+    /// Unlike eci, which comes out of the method of reflections and is
+    /// standardized, this is a plain average in pci units, so it's easy
+    /// to explain and to trace back to products. Eci is the better
+    /// choice for comparing with published rankings. Countries that
+    /// export nothing are NaN.
+    pub fn avg_export_complexity(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<HashMap<String, f64>, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+
+        let (_, pci) = complexity::complexity_by_diversity(&rca);
+        let res = eci_from_pci(&rca, &pci.column(0).into_owned());
+
+        Ok(self.country_idx.iter()
+            .map(|(country, idx)| (country.clone(), res[*idx]))
+            .collect())
+    }
+
+    /// k_c,1 for each country, the average ubiquity of the products it
+    /// has binary rca in (see `avg_ubiquity`). Countries that export
+    /// nothing are NaN.
+    pub fn avg_ubiquity_of_exports(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<HashMap<String, f64>, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+        let res = avg_ubiquity(&rca);
+
+        Ok(self.country_idx.iter()
+            .map(|(country, idx)| (country.clone(), res[*idx]))
+            .collect())
+    }
+
+    /// k_p,1 for each product, the average diversity of the countries
+    /// with binary rca in it (see `avg_diversity`). Products no country
+    /// exports are NaN.
+    pub fn avg_diversity_of_exporters(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<HashMap<String, f64>, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+        let res = avg_diversity(&rca);
+
+        Ok(self.product_idx.iter()
+            .map(|(product, idx)| (product.clone(), res[*idx]))
+            .collect())
+    }
+
+    /// The country x country matrix behind the method of reflections, from
+    /// the binary rca as in `complexity` (see `reflection_matrix`). Rows
+    /// and columns are indexed by `country_index`, and each row sums to
+    /// 1.0, except for countries that export nothing, which are NaN.
+    pub fn reflection_matrix(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<DMatrix<f64>, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+
+        Ok(reflection_matrix(&rca))
+    }
+
+    /// eci as the average of a supplied pci over the products each
+    /// country exports (see `eci_from_pci`), so pci can be held fixed
+    /// across years. Every product must be in `pci`.
+    pub fn eci_with_pci(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        pci: &HashMap<String, f64>,
+        ) -> Result<HashMap<String, f64>, Error>
+    {
+        let rca = self.try_binary_rca_matrix(years, rca_cutoff)?;
+
+        let mut pci_vector = DVector::zeros(self.product_idx.len());
+        for (product, idx) in self.product_idx.iter() {
+            pci_vector[*idx] = *pci.get(product)
+                .ok_or_else(|| Error::MissingIndex { member: product.clone(), index: "pci".into() })?;
+        }
+
+        let eci = eci_from_pci(&rca, &pci_vector);
+
+        Ok(self.country_idx.iter()
+            .map(|(country, idx)| (country.clone(), eci[*idx]))
+            .collect())
+    }
+}
+
+impl ProductSpace {
+    pub fn new(
+        country_idx: HashMap<String, usize>,
+        product_idx: HashMap<String, usize>,
+        mcps: HashMap<u32, DMatrix<f64>>,
+        rca_cutoff: Option<f64>,
+        ) -> Self
+    {
+        ProductSpaceBuilder::new(country_idx, product_idx, mcps)
+            .rca_cutoff(rca_cutoff)
+            .build()
+    }
+
+    /// Like `new`, but returns an error (see
+    /// `ProductSpaceBuilder::try_build`) instead of panicking or leaving
+    /// out degenerate years.
+    pub fn try_new(
+        country_idx: HashMap<String, usize>,
+        product_idx: HashMap<String, usize>,
+        mcps: HashMap<u32, DMatrix<f64>>,
+        rca_cutoff: Option<f64>,
+        ) -> Result<Self, Error>
+    {
+        ProductSpaceBuilder::new(country_idx, product_idx, mcps)
+            .rca_cutoff(rca_cutoff)
+            .try_build()
+    }
+
+    pub fn builder(
+        country_idx: HashMap<String, usize>,
+        product_idx: HashMap<String, usize>,
+        mcps: HashMap<u32, DMatrix<f64>>,
+        ) -> ProductSpaceBuilder
+    {
+        ProductSpaceBuilder::new(country_idx, product_idx, mcps)
+    }
+}
+
+// index of only `names` (all of `index` for None), numbered in the
+// order of `index`, with each one's position in `index`
+fn restricted_index(
+    index: &Arc<HashMap<String, usize>>,
+    names: Option<&[&str]>,
+    index_name: &str,
+    ) -> Result<(Arc<HashMap<String, usize>>, Vec<usize>), Error>
+{
+    let names = match names {
+        Some(names) => names,
+        None => return Ok((index.clone(), (0..index.len()).collect())),
+    };
+
+    let mut positions = names.iter()
+        .map(|name| lookup(index, name, index_name))
+        .collect::<Result<Vec<_>, _>>()?;
+    positions.sort();
+    positions.dedup();
+
+    let all = names_by_index(index);
+    let restricted = positions.iter()
+        .enumerate()
+        .map(|(i, pos)| (all[*pos].to_owned(), i))
+        .collect();
+
+    Ok((Arc::new(restricted), positions))
+}
+
+// fair share for a single value, when there's a cutoff
+fn fair_share_value(x: f64, cutoff: Option<f64>, comparison: Comparison) -> f64 {
+    match cutoff {
+        Some(cutoff) => if comparison.passes(x, cutoff) { 1.0 } else { 0.0 },
+        None => x,
+    }
+}
+
+impl ProductSpace {
+    /// Aggregates products up a code hierarchy (e.g. hs6 -> hs4 -> hs2)
+    /// by truncating each product code to its first `digits` characters.
+    ///
+    /// Raw mcp values are summed into the parent code, and rca and
+    /// proximity are recalculated, using the same settings as this
+    /// product space was built with. That holds for `ValueKind::Shares`
+    /// too: a country's shares of its exports summed over products are
+    /// its share of the parent.
+    ///
+    /// All product codes must be the same length. Errors from rebuilding
+    /// (as from `build`) are returned rather than panicking; degenerate
+    /// years are left out, as in `build`.
+    pub fn rollup(&self, digits: usize) -> Result<ProductSpace, Error> {
+        let mcps = self.mcps()?;
+
+        let code_len = self.product_idx.keys()
+            .next()
+            .map(|p| p.chars().count())
+            .unwrap_or(0);
+
+        if let Some(product) = self.product_idx.keys().find(|p| p.chars().count() != code_len) {
+            return Err(Error::NonUniformProductCodes {
+                product: product.clone(),
+                expected_len: code_len,
+            });
+        }
+
+        if digits == 0 || digits > code_len {
+            return Err(Error::InvalidRollupDigits { digits, code_len });
+        }
+
+        let mut parent_codes: Vec<String> = self.product_idx.keys()
+            .map(|p| p.chars().take(digits).collect())
+            .collect();
+        parent_codes.sort();
+        parent_codes.dedup();
+
+        let rollup_idx: HashMap<String, usize> = parent_codes.into_iter()
+            .enumerate()
+            .map(|(v,k)| (k,v))
+            .collect();
+
+        // old column idx -> new column idx
+        let col_mapping: Vec<(usize, usize)> = self.product_idx.iter()
+            .map(|(p, old_idx)| {
+                let parent: String = p.chars().take(digits).collect();
+                (*old_idx, rollup_idx[&parent])
+            })
+            .collect();
+
+        let mcps = mcps.iter()
+            .map(|(year, mcp)| {
+                let mut rolled = DMatrix::zeros(mcp.nrows(), rollup_idx.len());
+
+                for (old_idx, new_idx) in &col_mapping {
+                    for i in 0..mcp.nrows() {
+                        rolled[(i, *new_idx)] += mcp[(i, *old_idx)];
+                    }
+                }
+
+                (*year, rolled)
+            })
+            .collect();
+
+        self.rebuilder((*self.country_idx).clone(), rollup_idx, mcps).build_inner(false)
+    }
+}
+
+// an Option method's result: None for any error, logging the errors
+// that aren't logged where they happen (missing years are, as skipped)
+fn ok_or_warn<T>(res: Result<T, Error>) -> Option<T> {
+    match res {
+        Ok(x) => Some(x),
+        Err(err @ Error::NonFinite { .. }) => {
+            warn!("{}", err);
+            None
+        },
+        Err(_) => None,
+    }
+}
+
+// year lookup when aggregating over years, logging years skipped
+fn year_or_warn<T>(by_year: &HashMap<u32, T>, year: u32) -> Option<&T> {
+    let res = by_year.get(&year);
+    if res.is_none() {
+        warn!("skipping missing year {}", year);
+    }
+    res
+}
+
+/// rca by country and product, from `ProductSpace::rca`.
+///
+/// With the `json` feature, results serialize on their own as their
+/// name indexes plus matrix, so one can be cached or sent elsewhere and
+/// queried with `get` after deserializing. A deserialized result has its
+/// own copy of the indexes rather than sharing them. serde_json writes
+/// NaN and inf as null, which doesn't read back into f64, so use a
+/// format that keeps them or sanitize first (see `SanitizePolicy`).
+///
+/// Deserializing checks the indexes number their names 0..len
+/// (`Error::IndexMismatch`) and match the matrix shape
+/// (`Error::ShapeMismatch`, or `Error::LengthMismatch` for the
+/// `Complexity` vectors).
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(try_from = "checked::RcaData"))]
+pub struct Rca {
+    country_idx: Arc<HashMap<String, usize>>,
+    product_idx: Arc<HashMap<String, usize>>,
+    m: DMatrix<f64>,
+    provenance: Provenance,
+}
+
+impl Rca {
+    /// how this was calculated
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+}
+
+impl Mcp for Rca {
+    fn matrix(&self) -> &DMatrix<f64> {
+        &self.m
+    }
+    fn country_index(&self) -> &HashMap<String, usize> {
+        &self.country_idx
+    }
+    fn product_index(&self) -> &HashMap<String, usize> {
+        &self.product_idx
+    }
+}
+
+// TODO figure out how this calc shown publicly.
+/// Serializable with the `json` feature, as for `Rca`
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(try_from = "checked::ProximityData"))]
+pub struct Proximity {
+    product_idx: Arc<HashMap<String, usize>>,
+    m: DMatrix<f64>,
+}
+
+impl Proximity {
+    /// maximum spanning tree edges, by product name.
+    /// See `maximum_spanning_tree`
+    pub fn mst(&self) -> Vec<(String, String, f64)> {
+        let products = names_by_index(&self.product_idx);
+
+        maximum_spanning_tree(&self.m).into_iter()
+            .map(|(p, q, phi)| (products[p].to_owned(), products[q].to_owned(), phi))
+            .collect()
+    }
+
+    /// maximum spanning tree plus links above `threshold`, by product
+    /// name. See `atlas_network`
+    pub fn atlas_network(&self, threshold: f64) -> Vec<(String, String, f64)> {
+        let products = names_by_index(&self.product_idx);
+
+        atlas_network(&self.m, threshold).into_iter()
+            .map(|(p, q, phi)| (products[p].to_owned(), products[q].to_owned(), phi))
+            .collect()
+    }
+}
+
+/// Serializable with the `json` feature, as for `Rca`
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(try_from = "checked::DensityData"))]
+pub struct Density {
+    country_idx: Arc<HashMap<String, usize>>,
+    product_idx: Arc<HashMap<String, usize>>,
+    m: DMatrix<f64>,
+    provenance: Provenance,
+}
+
+impl Density {
+    /// how this was calculated
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+}
+
+impl Mcp for Density {
+    fn matrix(&self) -> &DMatrix<f64> {
+        &self.m
+    }
+    fn country_index(&self) -> &HashMap<String, usize> {
+        &self.country_idx
+    }
+    fn product_index(&self) -> &HashMap<String, usize> {
+        &self.product_idx
+    }
+}
+
+/// Each country's share of world exports of each product
+pub struct WorldShare {
+    country_idx: Arc<HashMap<String, usize>>,
+    product_idx: Arc<HashMap<String, usize>>,
+    m: DMatrix<f64>,
+}
+
+impl Mcp for WorldShare {
+    fn matrix(&self) -> &DMatrix<f64> {
+        &self.m
+    }
+    fn country_index(&self) -> &HashMap<String, usize> {
+        &self.country_idx
+    }
+    fn product_index(&self) -> &HashMap<String, usize> {
+        &self.product_idx
+    }
+}
+
+/// Country complexity (eci) and product complexity (pci), addressable
+/// by name through the same indexes as `Rca` and `Density`.
+/// Serializable with the `json` feature, as for `Rca`
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(try_from = "checked::ComplexityData"))]
+pub struct Complexity {
+    country_idx: Arc<HashMap<String, usize>>,
+    product_idx: Arc<HashMap<String, usize>>,
+    eci: DVector<f64>,
+    pci: DVector<f64>,
+    provenance: Provenance,
+}
+
+impl Complexity {
+    /// how this was calculated
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+
+    pub fn eci(&self, country: &str) -> Result<f64, Error> {
+        let idx = self.country_idx.get(country)
+            .ok_or_else(|| Error::MissingIndex { member: country.into(), index: "country".into() })?;
+
+        Ok(self.eci[*idx])
+    }
+
+    pub fn pci(&self, product: &str) -> Result<f64, Error> {
+        let idx = self.product_idx.get(product)
+            .ok_or_else(|| Error::MissingIndex { member: product.into(), index: "product".into() })?;
+
+        Ok(self.pci[*idx])
+    }
+
+    pub fn eci_vector(&self) -> &DVector<f64> {
+        &self.eci
+    }
+
+    pub fn pci_vector(&self) -> &DVector<f64> {
+        &self.pci
+    }
+
+    pub fn country_index(&self) -> &HashMap<String, usize> {
+        &self.country_idx
+    }
+
+    pub fn product_index(&self) -> &HashMap<String, usize> {
+        &self.product_idx
+    }
+
+    /// eci and pci as one flat table, e.g. for a dataframe: countries
+    /// then products, each ranked from 1 by value descending (ties by
+    /// name, NaN last), in rank order.
+    pub fn complexity_records(&self) -> Vec<ComplexityRecord> {
+        let rank = |idx: &HashMap<String, usize>, values: &DVector<f64>, kind: EntityKind| {
+            let mut ranked: Vec<_> = idx.iter()
+                .map(|(entity, i)| (entity.clone(), values[*i]))
+                .collect();
+            stats::sort_ranked(&mut ranked);
+
+            ranked.into_iter()
+                .enumerate()
+                .map(move |(i, (entity, value))| ComplexityRecord { entity, kind, value, rank: i + 1 })
+        };
+
+        rank(&self.country_idx, &self.eci, EntityKind::Country)
+            .chain(rank(&self.product_idx, &self.pci, EntityKind::Product))
+            .collect()
+    }
+}
+
+/// Which index a `ComplexityRecord` is from
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub enum EntityKind {
+    Country,
+    Product,
+}
+
+/// One row of `Complexity::complexity_records`: eci for a country, or
+/// pci for a product
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct ComplexityRecord {
+    pub entity: String,
+    pub kind: EntityKind,
+    pub value: f64,
+    pub rank: usize,
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use super::*;
+
+    #[test]
+    fn test_ps_interface() {
+        let vals = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(0.0),
+        );
+
+        let rca = ps.rca(&[2017], None).unwrap();
+
+        let expected = DMatrix::from_vec(2,3,vec![0.7777777777777778,1.1666666666666667,1.0,1.0,1.0606060606060606,0.9545454545454545]);
+
+        assert_eq!(rca.m, expected);
+
+        let val = rca.get("a", "01").unwrap();
+        assert_eq!(val, 0.7777777777777778);
+
+        let vals = rca.get_country("b").unwrap();
+        assert_eq!(vals, vec![1.1666666666666667, 1.0, 0.9545454545454545]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_result_serde() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            ["a", "b", "c", "d"].iter().enumerate().map(|(i, c)| (c.to_string(), i)).collect(),
+            ["01", "02", "03"].iter().enumerate().map(|(i, p)| (p.to_string(), i)).collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let rca = ps.rca(&[2017], None).unwrap();
+        let json = serde_json::to_string(&rca).unwrap();
+        let res: Rca = serde_json::from_str(&json).unwrap();
+        assert_eq!(res.get("c", "03").unwrap(), rca.get("c", "03").unwrap());
+        assert_eq!(res.m, rca.m);
+        assert_eq!(res.provenance(), rca.provenance());
+
+        let density = ps.density(&[2017], Some(1.0)).unwrap();
+        let res: Density = serde_json::from_str(&serde_json::to_string(&density).unwrap()).unwrap();
+        assert_eq!(res.get("b", "01").unwrap(), density.get("b", "01").unwrap());
+
+        let proximity = ps.proximity(&[2017]).unwrap();
+        let res: Proximity = serde_json::from_str(&serde_json::to_string(&proximity).unwrap()).unwrap();
+        assert_eq!(res.m, proximity.m);
+        assert_eq!(res.mst(), proximity.mst());
+
+        let complexity = ps.complexity(&[2017], Some(1.0)).unwrap();
+        let res: Complexity = serde_json::from_str(&serde_json::to_string(&complexity).unwrap()).unwrap();
+        assert_eq!(res.eci("d").unwrap(), complexity.eci("d").unwrap());
+        assert_eq!(res.pci_vector(), complexity.pci_vector());
+
+        // indexes that don't match the matrix are rejected
+        let mut value = serde_json::to_value(&rca).unwrap();
+        value["country_idx"].as_object_mut().unwrap().remove("d");
+        assert!(serde_json::from_value::<Rca>(value).is_err());
+
+        let mut value = serde_json::to_value(&proximity).unwrap();
+        value["product_idx"]["03"] = serde_json::json!(0);
+        assert!(serde_json::from_value::<Proximity>(value).is_err());
+
+        let mut value = serde_json::to_value(&complexity).unwrap();
+        value["product_idx"].as_object_mut().unwrap().remove("03");
+        assert!(serde_json::from_value::<Complexity>(value).is_err());
+    }
+
+    #[test]
+    fn test_ps_rca_by_year() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(2,3,vec![6.0,5.0,4.0,3.0,2.0,1.0]));
+        mcps.insert(2017, DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let rcas = ps.rca_by_year(&[2016, 2017, 2099], None);
+
+        assert_eq!(rcas.len(), 2);
+        assert!(rcas.get(&2099).is_none());
+        assert_eq!(rcas[&2016].m, ps.rca(&[2016], None).unwrap().m);
+        assert_eq!(rcas[&2017].m, ps.rca(&[2017], None).unwrap().m);
+    }
+
+    #[test]
+    fn test_ps_series() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]));
+        mcps.insert(2016, DMatrix::from_vec(2,3,vec![6.0,5.0,4.0,3.0,2.0,1.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let series = ps.rca_series("b", "03", None).unwrap();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0], (2016, ps.rca(&[2016], None).unwrap().get("b", "03").unwrap()));
+        assert_eq!(series[1], (2017, 0.9545454545454545));
+
+        let series = ps.rca_series("b", "03", Some(1.0)).unwrap();
+        assert_eq!(series[1], (2017, 0.0));
+
+        let series = ps.density_series("a", "02", Some(1.0)).unwrap();
+        for (year, x) in series {
+            let expected = ps.density(&[year], Some(1.0)).unwrap().get("a", "02").unwrap();
+            assert!((x - expected).abs() < 1e-12);
+        }
+
+        assert!(ps.rca_series("zzz", "03", None).is_err());
+    }
+
+    #[test]
+    fn test_ps_proximity_ref() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        assert_eq!(ps.proximity_ref(2017), Some(&ps.proximity(&[2017]).unwrap().m));
+        assert!(ps.proximity_ref(2099).is_none());
+    }
+
+    #[test]
+    fn test_ps_density_with_proximity() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // 2016 rca with 2017 proximity
+        let external = ps.proximity(&[2017]).unwrap();
+        let res = ps.density_with_proximity(&[2016], Some(1.0), &external).unwrap();
+        let expected = density(&ps.rca(&[2016], Some(1.0)).unwrap().m, &external.m);
+        assert_eq!(res.m, expected);
+
+        // same products, different positions
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+        let other = ProductSpace::new(
+            ps.country_index().clone(),
+            [("02".to_string(),0usize), ("01".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+        match ps.density_with_proximity(&[2016], Some(1.0), &other.proximity(&[2017]).unwrap()) {
+            Err(Error::IndexMismatch { index }) => assert_eq!(index, "product"),
+            _ => panic!("expected index mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_ps_world_share() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(2,2,vec![1.0,3.0,2.0,2.0]));
+        mcps.insert(2017, DMatrix::from_vec(2,2,vec![3.0,1.0,2.0,2.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+
+        let share = ps.world_share(&[2016]).unwrap();
+        assert_eq!(share.get("a", "01").unwrap(), 0.25);
+        assert_eq!(share.get("b", "02").unwrap(), 0.5);
+
+        let share = ps.world_share(&[2016, 2017, 2099]).unwrap();
+        assert_eq!(share.get("a", "01").unwrap(), 0.5);
+
+        assert!(ps.world_share(&[2099]).is_err());
+
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(2,2,vec![0.25,0.75,0.75,0.25]));
+        let ps = ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1)].iter().cloned().collect(),
+            mcps,
+        )
+            .value_kind(ValueKind::Shares)
+            .build();
+        match ps.world_share(&[2016]) {
+            Err(Error::UnsupportedValueKind { method }) => assert_eq!(method, "world_share"),
+            _ => panic!("expected unsupported value kind"),
+        }
+        // rca relative to every country is the cached rca from shares
+        assert_eq!(ps.rca_relative_to(&["a", "b"], &[2016]).unwrap().matrix(), ps.rca(&[2016], None).unwrap().matrix());
+    }
+
+    #[test]
+    fn test_ps_complexity_weighted() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+
+        let res = ps.complexity_weighted(&[2017]).unwrap();
+        let (eci, _) = complexity_weighted(&DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        // a exports the most products
+        assert!(res.eci("a").unwrap() > res.eci("b").unwrap());
+        assert!((res.eci("a").unwrap().abs() - eci[0].abs()).abs() < 1e-12);
+
+        let summed = DMatrix::from_vec(3,3,vec![10.0,9.0,10.0,4.0,3.0,2.0,1.0,3.0,3.0]);
+        let (eci, _) = complexity_weighted(&summed);
+        let res = ps.complexity_weighted(&[2016, 2017, 2099]).unwrap();
+        assert!((res.eci("b").unwrap().abs() - eci[1].abs()).abs() < 1e-12);
+
+        assert!(ps.complexity_weighted(&[2099]).is_err());
+    }
+
+    #[test]
+    fn test_ps_mcp_binary() {
+        let mut mcps = HashMap::new();
+        // binary rows: a [1,0,0], b [0,1,1], c [0,1,1]
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        // binary rows: a [0,1,1], b [1,0,0], c [1,0,0]
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+
+        let res = ps.mcp_binary(&[2017], None).unwrap();
+        assert_eq!(res.matrix(), &DMatrix::from_vec(3,3,vec![0.0,1.0,1.0,1.0,0.0,0.0,1.0,0.0,0.0]));
+        assert_eq!(res.get("a", "02").unwrap(), 1.0);
+
+        // no product is kept by any country in both years
+        let res = ps.mcp_binary(&[2016, 2017, 2099], Some(1.0)).unwrap();
+        assert_eq!(res.matrix(), &DMatrix::zeros(3,3));
+
+        // a missing year is skipped
+        let res = ps.mcp_binary(&[2017, 2099], None).unwrap();
+        assert_eq!(res.get("b", "01").unwrap(), 1.0);
+
+        assert!(ps.mcp_binary(&[2099], None).is_none());
+        assert!(ps.mcp_binary(&[2098, 2099], None).is_none());
+    }
+
+    #[test]
+    fn test_ps_proximity_subset() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let full = ps.proximity_matrix(&[2016, 2017, 2099]).unwrap();
+        let subset = ps.proximity_subset(&["03", "01", "03"], &[2016, 2017, 2099]).unwrap();
+
+        assert_eq!(subset.m.shape(), (2,2));
+        assert_eq!(subset.product_idx["03"], 0);
+        assert_eq!(subset.m[(0,1)], full[(2,0)]);
+        assert_eq!(subset.m[(0,0)], full[(2,2)]);
+        assert_eq!(subset.m, subset.m.transpose());
+
+        assert!(ps.proximity_subset(&["01", "zz"], &[2017]).is_err());
+        assert!(ps.proximity_subset(&["01"], &[2099]).is_err());
+    }
+
+    #[test]
+    fn test_ps_density_min_proximity() {
+        let mut mcps = HashMap::new();
+        // binary rows: a [1,0,1], b [0,1,0], c [0,0,1], d [1,1,0]
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2), ("d".to_string(),3)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // proximity between different products is 0.5 or 0.0
+        let full = ps.density(&[2017], Some(1.0)).unwrap();
+        assert!((full.get("a", "02").unwrap() - 1.0 / 3.0).abs() < 1e-12);
+
+        // with only the diagonal left, density is the binary rca
+        let res = ps.density_with_options(&[2017], Some(1.0), DensityOptions { min_proximity: Some(0.6), ..Default::default() }).unwrap();
+        assert_eq!(res.get("a", "02").unwrap(), 0.0);
+        assert_eq!(res.matrix(), &ps.rca_matrix(&[2017], Some(1.0)).unwrap());
+
+        // a floor below every proximity changes nothing
+        let res = ps.density_with_options(&[2017], Some(1.0), DensityOptions { min_proximity: Some(0.1), ..Default::default() }).unwrap();
+        assert_eq!(res.matrix(), full.matrix());
+    }
+
+    #[test]
+    fn test_ps_complexity_over() {
+        let mut mcps = HashMap::new();
+        // binary rows: a [1,0,1], b [0,1,0], c [0,0,1], d [1,1,0]
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2), ("d".to_string(),3)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let full = ps.complexity(&[2017], Some(1.0)).unwrap();
+        let res = ps.complexity_over(&[2017], Some(1.0), None, None).unwrap();
+        assert_eq!(res.eci_vector(), full.eci_vector());
+        assert_eq!(res.pci_vector(), full.pci_vector());
+
+        // reflections over the rows for a, b, d only, in index order
+        let res = ps.complexity_over(&[2017], Some(1.0), Some(&["d", "a", "b"]), None).unwrap();
+        let binary = ps.binary_rca_matrix(&[2017], Some(1.0)).unwrap();
+        let (eci, pci) = complexity::complexity_by_diversity(&binary.select_rows(&[0, 1, 3]));
+        assert_eq!(res.eci("a").unwrap(), eci[(0, 0)]);
+        assert_eq!(res.eci("d").unwrap(), eci[(2, 0)]);
+        assert_eq!(res.pci("03").unwrap(), pci[(2, 0)]);
+        assert!(res.eci("c").is_err());
+
+        let res = ps.complexity_over(&[2017], Some(1.0), None, Some(&["02", "03"])).unwrap();
+        assert!(res.pci("01").is_err());
+        assert_eq!(res.eci_vector().len(), 4);
+        assert_eq!(res.pci_vector().len(), 2);
+
+        assert!(ps.complexity_over(&[2017], Some(1.0), Some(&["zz"]), None).is_err());
+        assert!(ps.complexity_over(&[2099], Some(1.0), None, None).is_err());
+    }
+
+    #[test]
+    fn test_ps_avg_ubiquity_diversity() {
+        let mut mcps = HashMap::new();
+        // binary rows: a [1,0,1], b [0,1,0], c [0,0,1], d [1,1,0]
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2), ("d".to_string(),3)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // every product has ubiquity 2
+        let kc1 = ps.avg_ubiquity_of_exports(&[2017], Some(1.0)).unwrap();
+        assert_eq!(kc1.len(), 4);
+        assert!(kc1.values().all(|x| *x == 2.0));
+
+        // 02 is exported by b (diversity 1) and d (diversity 2)
+        let kp1 = ps.avg_diversity_of_exporters(&[2017], Some(1.0)).unwrap();
+        assert_eq!(kp1["01"], 2.0);
+        assert_eq!(kp1["02"], 1.5);
+        assert_eq!(kp1["03"], 1.5);
+
+        assert!(ps.avg_ubiquity_of_exports(&[2099], Some(1.0)).is_err());
+        assert!(ps.avg_diversity_of_exporters(&[2017], Some(-1.0)).is_err());
+    }
+
+    #[test]
+    fn test_ps_reflection_matrix() {
+        let mut mcps = HashMap::new();
+        // binary rows: a [1,0,1], b [0,1,0], c [0,0,1], d [1,1,0]
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2), ("d".to_string(),3)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let res = ps.reflection_matrix(&[2017], Some(1.0)).unwrap();
+        assert_eq!(res, reflection_matrix(&ps.binary_rca_matrix(&[2017], Some(1.0)).unwrap()));
+
+        // every product has ubiquity 2, so b moves to itself and d
+        assert_eq!(res.row(1).iter().cloned().collect::<Vec<_>>(), vec![0.0, 0.5, 0.0, 0.5]);
+
+        assert!(ps.reflection_matrix(&[2099], Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_ps_rca_average() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+
+        let expected = (&ps.rcas_by_year[&2016] + &ps.rcas_by_year[&2017]) / 2.0;
+        assert_eq!(ps.rca_matrix(&[2016, 2017], None).unwrap(), expected);
+
+        // a missing year counts as zeros
+        let expected = (&ps.rcas_by_year[&2016] + &ps.rcas_by_year[&2017]) / 3.0;
+        assert_eq!(ps.rca_matrix(&[2016, 2017, 2099], None).unwrap(), expected);
+
+        // with a cutoff, binary rca in every year
+        let expected = fair_share(&ps.rcas_by_year[&2016], Some(1.0))
+            .component_mul(&fair_share(&ps.rcas_by_year[&2017], Some(1.0)));
+        assert_eq!(ps.rca_matrix(&[2016, 2017], Some(1.0)).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_ps_density_transform() {
+        let mut mcps = HashMap::new();
+        // binary rows: a [1,0,1], b [0,1,0], c [0,0,1], d [1,1,0]
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2), ("d".to_string(),3)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let full = ps.density(&[2017], Some(1.0)).unwrap();
+        let res = ps.density_with_options(&[2017], Some(1.0), DensityOptions { transform: Some(ProximityTransform::Power(1.0)), ..Default::default() }).unwrap();
+        assert_eq!(res.matrix(), full.matrix());
+
+        // 0.5 proximities are squared: 0.25 / (0.25 + 1.0)
+        let res = ps.density_with_options(&[2017], Some(1.0), DensityOptions { transform: Some(ProximityTransform::Power(2.0)), ..Default::default() }).unwrap();
+        assert_eq!(res.get("a", "02").unwrap(), 0.2);
+
+        // the floor applies to the proximity as calculated
+        let res = ps.density_with_options(&[2017], Some(1.0), DensityOptions { min_proximity: Some(0.6), transform: Some(ProximityTransform::Power(2.0)) }).unwrap();
+        assert_eq!(res.matrix(), &ps.rca_matrix(&[2017], Some(1.0)).unwrap());
+
+        // a sigmoid maps 0.0 above 0.0, but floored links stay at 0.0 and
+        // the diagonal stays at 1.0
+        let sigmoid = ProximityTransform::Sigmoid { midpoint: 0.5, steepness: 10.0 };
+        let res = ps.density_with_options(&[2017], Some(1.0), DensityOptions { min_proximity: Some(0.6), transform: Some(sigmoid) }).unwrap();
+        assert_eq!(res.matrix(), &ps.rca_matrix(&[2017], Some(1.0)).unwrap());
+
+        // without a floor, the 0.0 between 02 and 03 is transformed too.
+        // a: 02 links to 01 (sigmoid(0.5) = 0.5) and 03 (sigmoid(0.0))
+        let at_zero = sigmoid.apply(0.0);
+        let res = ps.density_with_options(&[2017], Some(1.0), DensityOptions { transform: Some(sigmoid), ..Default::default() }).unwrap();
+        let expected = (0.5 + at_zero) / (0.5 + 1.0 + at_zero);
+        assert!((res.get("a", "02").unwrap() - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_ps_density_window() {
+        let mut mcps = HashMap::new();
+        // binary rows: a [1,0,1], b [0,1,0], c [0,0,1], d [1,1,0]
+        mcps.insert(2016, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+        // binary rows: a [1,0,0], b [0,1,1], c [0,0,1], d [1,1,0]
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,1.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2), ("d".to_string(),3)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // rca in both years: a [1,0,0], b [0,1,0], c [0,0,1], d [1,1,0],
+        // against proximity averaged over the years:
+        // [[1, .5, .25], [.5, 1, .25], [.25, .25, 1]]
+        let res = ps.density(&[2016, 2017], Some(1.0)).unwrap();
+        assert_eq!(res.get("a", "02").unwrap(), 0.5 / 1.75);
+        assert!((res.get("b", "03").unwrap() - 0.25 / 1.5).abs() < 1e-12);
+        assert!((res.get("d", "03").unwrap() - 0.5 / 1.5).abs() < 1e-12);
+        assert_eq!(
+            res.matrix(),
+            &density(&ps.rca_matrix(&[2016, 2017], Some(1.0)).unwrap(), &ps.proximity_matrix(&[2016, 2017]).unwrap()),
+        );
+
+        // proximity from the same binary rca:
+        // [[1, .5, 0], [.5, 1, 0], [0, 0, 1]]
+        let window = ps.window_proximity(&[2016, 2017], Some(1.0)).unwrap();
+        assert_eq!(window.m[(0, 2)], 0.0);
+        let res = ps.density_with_proximity(&[2016, 2017], Some(1.0), &window).unwrap();
+        assert_eq!(res.get("a", "02").unwrap(), 0.5 / 1.5);
+        assert_eq!(res.get("d", "03").unwrap(), 0.0);
+
+        // one year, same as proximity
+        let window = ps.window_proximity(&[2016], Some(1.0)).unwrap();
+        assert_eq!(window.m, ps.proximity_matrix(&[2016]).unwrap());
+
+        assert!(ps.window_proximity(&[2099], Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_ps_interpolate_missing_years() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2015, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let builder = || ProductSpace::builder(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps.clone(),
+        );
+        let skipped = builder().build();
+        let ps = builder().interpolate_missing_years(true).build();
+
+        let rca_2015 = &ps.rcas_by_year[&2015];
+        let rca_2017 = &ps.rcas_by_year[&2017];
+
+        // 2016 is the midpoint, so the average is of 2015 and 2017
+        let res = ps.rca_matrix(&[2015, 2016, 2017], None).unwrap();
+        let expected = (rca_2015 + rca_2017) / 2.0;
+        for (x, y) in res.iter().zip(expected.iter()) {
+            assert!((x - y).abs() < 1e-12);
+        }
+
+        let res = skipped.rca_matrix(&[2015, 2016, 2017], None).unwrap();
+        assert_eq!(res, (rca_2015 + rca_2017) / 3.0);
+
+        // endpoints aren't filled
+        assert_eq!(ps.rca_matrix(&[2015, 2016], None), skipped.rca_matrix(&[2015, 2016], None));
+
+        // neighbors have to be requested: 2017 is found but not asked for
+        assert_eq!(ps.rca_matrix(&[2015, 2016, 2018], None), skipped.rca_matrix(&[2015, 2016, 2018], None));
+        assert_eq!(ps.rca_matrix(&[2016, 2017, 2018], Some(1.0)), skipped.rca_matrix(&[2016, 2017, 2018], Some(1.0)));
     }
-    fn product_index(&self) -> &HashMap<String, usize> {
-        &self.product_idx
+
+    #[test]
+    fn test_ps_density_windows() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,1.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2), ("d".to_string(),3)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let res = ps.density_windows(&[2016, 2017], &[2016, 2017], Some(1.0)).unwrap();
+        assert_eq!(res.matrix(), ps.density(&[2016, 2017], Some(1.0)).unwrap().matrix());
+
+        // 2017 rca against 2016 proximity
+        let res = ps.density_windows(&[2017], &[2016], Some(1.0)).unwrap();
+        let expected = density(&ps.rca_matrix(&[2017], Some(1.0)).unwrap(), &ps.proximity_matrix(&[2016]).unwrap());
+        assert_eq!(res.matrix(), &expected);
+        assert_eq!(res.provenance().years, vec![2017]);
+
+        match ps.density_windows(&[2017], &[2098, 2099], Some(1.0)) {
+            Err(Error::MissingYears { years }) => assert_eq!(years, vec![2098, 2099]),
+            _ => panic!("expected missing proximity years"),
+        }
+        assert!(ps.density_windows(&[2099], &[2016], Some(1.0)).is_err());
     }
-}
 
+    #[test]
+    fn test_ps_binary_rca_cached() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
 
-#[cfg(test)]
-mod test {
-    use std::collections::HashMap;
-    use super::*;
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+
+        // cached for the construction cutoff, the default of 1.0
+        let uncached = fair_share(&ps.rcas_by_year[&2017], Some(1.0));
+        assert_eq!(*ps.binary_rca_matrix(&[2017], None).unwrap(), uncached);
+        assert_eq!(*ps.binary_rca_matrix(&[2017], Some(1.0)).unwrap(), uncached);
+        match ps.binary_rca_matrix(&[2017], None) {
+            Some(Cow::Borrowed(_)) => (),
+            _ => panic!("cached binary rca should be borrowed"),
+        }
+
+        let uncached = fair_share(&ps.rcas_by_year[&2017], Some(0.5));
+        assert_eq!(*ps.binary_rca_matrix(&[2017], Some(0.5)).unwrap(), uncached);
+
+        assert!(ps.binary_rca_matrix(&[2099], None).is_none());
+        assert!(ps.binary_rca_matrix(&[2017], Some(-1.0)).is_none());
+    }
 
     #[test]
-    fn test_ps_interface() {
+    fn test_complexity_records() {
+        let complexity = Complexity {
+            country_idx: Arc::new([("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect()),
+            product_idx: Arc::new([("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect()),
+            eci: DVector::from_vec(vec![-1.0, 1.0]),
+            pci: DVector::from_vec(vec![0.5, std::f64::NAN, 0.5]),
+            provenance: Provenance::default(),
+        };
+
+        let records = complexity.complexity_records();
+        assert_eq!(records.len(), 5);
+
+        assert_eq!(records[0], ComplexityRecord { entity: "b".into(), kind: EntityKind::Country, value: 1.0, rank: 1 });
+        assert_eq!(records[1], ComplexityRecord { entity: "a".into(), kind: EntityKind::Country, value: -1.0, rank: 2 });
+
+        let products: Vec<_> = records[2..].iter().map(|r| (r.entity.as_str(), r.kind, r.rank)).collect();
+        assert_eq!(products, vec![
+            ("01", EntityKind::Product, 1),
+            ("03", EntityKind::Product, 2),
+            ("02", EntityKind::Product, 3),
+        ]);
+        assert!(records[4].value.is_nan());
+    }
+
+    #[test]
+    fn test_ps_rca_cutoff_cached() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2015, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,2.0,0.0]));
+
+        let mut ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let uncached = ps.rcas_cutoff_by_year[&2015].component_mul(&ps.rcas_cutoff_by_year[&2017]);
+
+        let first = ps.rca_cutoff_matrix(&[2015, 2017]).unwrap();
+        let second = ps.rca_cutoff_matrix(&[2017, 2015, 2017]).unwrap();
+        assert_eq!(first, uncached);
+        assert_eq!(second, uncached);
+        assert_eq!(ps.cutoff_cache().len(), 1);
+
+        ps.clear_cache();
+        assert!(ps.cutoff_cache().is_empty());
+        assert_eq!(ps.rca_cutoff_matrix(&[2015, 2017]).unwrap(), uncached);
+
+        // a new cutoff isn't answered from the cache
+        ps.rebuild_cutoff(Some(0.5)).unwrap();
+        let uncached = ps.rcas_cutoff_by_year[&2015].component_mul(&ps.rcas_cutoff_by_year[&2017]);
+        assert_eq!(ps.rca_cutoff_matrix(&[2015, 2017]).unwrap(), uncached);
+
+        // the cache is bounded; missing years still make distinct keys
+        for year in 2100..2100 + 2 * CUTOFF_CACHE_CAPACITY as u32 {
+            ps.rca_cutoff_matrix(&[2015, 2017, year]).unwrap();
+        }
+        assert_eq!(ps.cutoff_cache().len(), CUTOFF_CACHE_CAPACITY);
+
+        // a poisoned cache is still used
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = ps.cutoff_products.lock().unwrap();
+            panic!("poisoning the cache");
+        }));
+        assert!(ps.cutoff_products.is_poisoned());
+        assert_eq!(ps.rca_cutoff_matrix(&[2015, 2017]).unwrap(), uncached);
+    }
+
+    #[test]
+    fn test_ps_iter_rca() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,2,vec![1.0,3.0,2.0,2.0]));
+        mcps.insert(2016, DMatrix::from_vec(2,2,vec![3.0,1.0,2.0,2.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+
+        let years: Vec<_> = ps.iter_rca(Some(1.0)).map(|(year, _)| year).collect();
+        assert_eq!(years, ps.years());
+        assert_eq!(years, vec![2016, 2017]);
+
+        for (year, rca) in ps.iter_rca(None) {
+            assert_eq!(rca.matrix(), ps.rca(&[year], None).unwrap().matrix());
+            assert!(Arc::ptr_eq(&rca.country_idx, &ps.country_idx));
+        }
+
+        assert_eq!(ps.iter_rca(Some(-1.0)).count(), 0);
+    }
+
+    #[test]
+    fn test_ps_try_methods() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,2,vec![1.0,3.0,2.0,2.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+
+        assert_eq!(ps.try_rca(&[2017], Some(1.0)).unwrap().matrix(), ps.rca(&[2017], Some(1.0)).unwrap().matrix());
+        assert_eq!(ps.try_density(&[2017], None).unwrap().matrix(), ps.density(&[2017], None).unwrap().matrix());
+        assert!(ps.try_complexity(&[2017], None).is_ok());
+
+        match ps.try_rca(&[2017], Some(-1.0)) {
+            Err(Error::InvalidCutoff { cutoff }) => assert_eq!(cutoff, -1.0),
+            _ => panic!("expected invalid cutoff"),
+        }
+        match ps.try_density(&[2017], Some(std::f64::NAN)) {
+            Err(Error::InvalidCutoff { .. }) => (),
+            _ => panic!("expected invalid cutoff"),
+        }
+        match ps.try_complexity(&[2017], Some(-1.0)) {
+            Err(Error::InvalidCutoff { .. }) => (),
+            _ => panic!("expected invalid cutoff"),
+        }
+        match ps.try_rca_capped(&[2017], None, Some(-1.0)) {
+            Err(Error::InvalidCutoff { .. }) => (),
+            _ => panic!("expected invalid cap"),
+        }
+        match ps.try_density(&[2099], None) {
+            Err(Error::MissingYears { years }) => assert_eq!(years, vec![2099]),
+            _ => panic!("expected missing years"),
+        }
+        assert!(ps.try_rca(&[], None).is_err());
+
+        // several years, none found: an error, not ones or zeros
+        for cutoff in &[None, Some(1.0)] {
+            match ps.try_rca(&[2098, 2099], *cutoff) {
+                Err(Error::MissingYears { years }) => assert_eq!(years, vec![2098, 2099]),
+                _ => panic!("expected missing years"),
+            }
+            assert!(ps.try_density(&[2098, 2099], *cutoff).is_err());
+            assert!(ps.try_complexity(&[2098, 2099], *cutoff).is_err());
+            assert!(ps.rca(&[2098, 2099], *cutoff).is_none());
+        }
+        assert!(ps.proximity(&[2098, 2099]).is_none());
+    }
+
+    #[test]
+    fn test_ps_drop_mcps() {
+        let vals = DMatrix::from_vec(2,2,vec![1.0,2.0,3.0,4.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals.clone());
+
+        let mut ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("0101".to_string(),0usize), ("0102".to_string(),1)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+        assert_eq!(ps.mcps().unwrap()[&2017], vals);
+
+        ps.drop_mcps();
+
+        match ps.mcps() {
+            Err(Error::McpsDropped) => (),
+            _ => panic!("expected mcps dropped"),
+        }
+        match ps.rollup(2) {
+            Err(Error::McpsDropped) => (),
+            _ => panic!("expected mcps dropped"),
+        }
+        assert!(ps.rca(&[2017], None).is_some());
+        assert_eq!(ps.memory_report().mcps.total(), 0);
+    }
+
+    #[test]
+    fn test_ps_build_cutoff() {
+        let vals = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let mut ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+        assert_eq!(ps.build_cutoff(), Some(1.0));
+
+        ps.rebuild_cutoff(None).unwrap();
+        assert_eq!(ps.build_cutoff(), None);
+    }
+
+    #[test]
+    fn test_ps_rca_relative_to() {
         let vals = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
         let mut mcps = HashMap::new();
         mcps.insert(2017, vals);
@@ -363,19 +2703,285 @@ mod test {
             [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
             [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
             mcps,
-            Some(0.0),
+            None,
         );
 
-        let rca = ps.rca(&[2017], None).unwrap();
+        let res = ps.rca_relative_to(&["b"], &[2017]).unwrap();
+        assert_eq!(res.get("a", "01").unwrap(), 0.6666666666666666);
+        assert_eq!(res.get("b", "01").unwrap(), 1.0);
 
-        let expected = DMatrix::from_vec(2,3,vec![0.7777777777777778,1.1666666666666667,1.0,1.0,1.0606060606060606,0.9545454545454545]);
+        let res = ps.rca_relative_to(&["a", "b"], &[2017]).unwrap();
+        assert_eq!(res.matrix(), ps.rca(&[2017], None).unwrap().matrix());
 
-        assert_eq!(rca.m, expected);
+        assert!(ps.rca_relative_to(&["zz"], &[2017]).is_err());
+        assert!(ps.rca_relative_to(&["a"], &[2099]).is_err());
 
-        let val = rca.get("a", "01").unwrap();
-        assert_eq!(val, 0.7777777777777778);
+        // 02 is untraded, and zeroed as on build
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,3,vec![1.0,2.0,0.0,0.0,5.0,6.0]));
+        let ps = ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+        )
+            .sanitize(SanitizePolicy::ZeroUntraded)
+            .build();
 
-        let vals = rca.get_country("b").unwrap();
-        assert_eq!(vals, vec![1.1666666666666667, 1.0, 0.9545454545454545]);
+        let res = ps.rca_relative_to(&["a", "b"], &[2017]).unwrap();
+        assert_eq!(res.get("a", "02").unwrap(), 0.0);
+        assert_eq!(res.matrix(), ps.rca(&[2017], None).unwrap().matrix());
+    }
+
+    #[test]
+    fn test_ps_coexport_counts() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // binary rows: a [0,1,1], b [1,1,0]
+        let counts = ps.coexport_counts(&[2017], Some(1.0)).unwrap();
+        let p = ps.product_index();
+
+        assert_eq!(counts[(p["02"], p["02"])], 2.0);
+        assert_eq!(counts[(p["01"], p["02"])], 1.0);
+        assert_eq!(counts[(p["01"], p["03"])], 0.0);
+    }
+
+    #[test]
+    fn test_ps_try_new() {
+        let vals = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::try_new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps.clone(),
+            Some(1.0),
+        );
+
+        assert!(ps.is_ok());
+
+        // only a exports in 2016
+        mcps.insert(2016, DMatrix::from_vec(2,3,vec![1.0,0.0,3.0,0.0,5.0,0.0]));
+        let ps = ProductSpace::try_new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+        match ps {
+            Err(Error::DegenerateYear { year }) => assert_eq!(year, 2016),
+            _ => panic!("expected degenerate year"),
+        }
+
+        match ProductSpace::try_new(HashMap::new(), HashMap::new(), HashMap::new(), Some(std::f64::NAN)) {
+            Err(Error::InvalidCutoff { .. }) => (),
+            _ => panic!("expected invalid cutoff"),
+        }
+    }
+
+    #[test]
+    fn test_ps_rollup() {
+        // rows: [1,2,3], [4,5,6]
+        let vals = DMatrix::from_vec(2,3,vec![1.0,4.0,2.0,5.0,3.0,6.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("0101".to_string(),0usize), ("0102".to_string(),1), ("0201".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let rolled = ps.rollup(2).unwrap();
+
+        // rows: [3,3], [9,6]
+        let expected = rca(&DMatrix::from_vec(2,2,vec![3.0,9.0,3.0,6.0]));
+        let res = rolled.rca(&[2017], None).unwrap();
+
+        assert_eq!(res.get("a", "01").unwrap(), expected[(0,0)]);
+        assert_eq!(res.get("b", "02").unwrap(), expected[(1,1)]);
+        assert!(res.get("a", "0101").is_err());
+
+        assert!(ps.rollup(0).is_err());
+        assert!(ps.rollup(5).is_err());
+    }
+
+    #[test]
+    fn test_ps_rollup_non_uniform() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,2,vec![1.0,2.0,3.0,4.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("0101".to_string(),0usize), ("01".to_string(),1)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        assert!(ps.rollup(2).is_err());
+    }
+
+    #[test]
+    fn test_ps_eci_with_pci() {
+        let vals = DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let mut pci: HashMap<String, f64> = [("01".to_string(), -1.0), ("02".to_string(), 1.0)].iter().cloned().collect();
+        assert!(ps.eci_with_pci(&[2017], Some(1.0), &pci).is_err());
+
+        // binary rows: a [0,1,1], b [1,0,0], c [1,0,0]
+        pci.insert("03".to_string(), 2.0);
+        let eci = ps.eci_with_pci(&[2017], Some(1.0), &pci).unwrap();
+
+        assert_eq!(eci["a"], 1.5);
+        assert_eq!(eci["b"], -1.0);
+        assert_eq!(eci["c"], -1.0);
+
+        assert!(ps.eci_with_pci(&[2099], Some(1.0), &pci).is_err());
+    }
+
+    #[test]
+    fn test_ps_density_without() {
+        let vals = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // nothing removed is the same as density
+        let full = ps.density(&[2017], Some(1.0)).unwrap();
+        let res = ps.density_without("a", &[], &[2017], Some(1.0)).unwrap();
+        assert!((res["02"] - full.get("a", "02").unwrap()).abs() < 1e-12);
+
+        // a has binary rca [0,1,1]; without 02 and 03 it has none
+        let res = ps.density_without("a", &["02", "03"], &[2017], Some(1.0)).unwrap();
+        assert_eq!(res["01"], 0.0);
+        assert_eq!(res["02"], 0.0);
+
+        assert!(ps.density_without("a", &["99"], &[2017], Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_ps_diversification_path() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            ["a", "b", "c", "d"].iter().enumerate().map(|(i, c)| (c.to_string(), i)).collect(),
+            ["01", "02", "03"].iter().enumerate().map(|(i, p)| (p.to_string(), i)).collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // c has only 03, close to 01 but not 02. Once it has 01, 02 is
+        // the only product left.
+        let res = ps.diversification_path("c", &[2017], Some(1.0), 3).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0], ("01".to_string(), 0.25));
+        assert_eq!(res[1].0, "02");
+        assert!((res[1].1 - 1.0 / 3.0).abs() < 1e-12);
+
+        let res = ps.diversification_path("c", &[2017], Some(1.0), 1).unwrap();
+        assert_eq!(res.len(), 1);
+
+        assert!(ps.diversification_path("zz", &[2017], Some(1.0), 1).is_err());
+        assert!(ps.diversification_path("c", &[2099], Some(1.0), 1).is_err());
+    }
+
+    #[test]
+    fn test_ps_complexity_oriented() {
+        let vals = DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // a is the most diverse
+        let c = ps.complexity(&[2017], Some(1.0)).unwrap();
+        assert!(c.eci("a").unwrap() > c.eci("b").unwrap());
+
+        let reference: HashMap<String, f64> = [("a".to_string(), -1.0), ("b".to_string(), 1.0)].iter().cloned().collect();
+        let oriented = ps.complexity_oriented(&[2017], Some(1.0), &reference).unwrap();
+        assert_eq!(oriented.eci("a").unwrap(), -c.eci("a").unwrap());
+        assert_eq!(oriented.pci("01").unwrap(), -c.pci("01").unwrap());
+
+        let reference: HashMap<String, f64> = [("a".to_string(), 1.0), ("b".to_string(), -1.0)].iter().cloned().collect();
+        let oriented = ps.complexity_oriented(&[2017], Some(1.0), &reference).unwrap();
+        assert_eq!(oriented.eci_vector(), c.eci_vector());
+    }
+
+    #[test]
+    fn test_ps_avg_export_complexity() {
+        let vals = DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let res = ps.avg_export_complexity(&[2017], Some(1.0)).unwrap();
+        let c = ps.complexity(&[2017], Some(1.0)).unwrap();
+
+        // binary rows: a [0,1,1], b [1,0,0], c [1,0,0]
+        assert_eq!(res["a"], (c.pci("02").unwrap() + c.pci("03").unwrap()) / 2.0);
+        assert_eq!(res["b"], c.pci("01").unwrap());
+        assert!(ps.avg_export_complexity(&[2099], Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_ps_complexity() {
+        // rows: [5,3,1], [8,1,0], [9,0,0]
+        let vals = DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals.clone());
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let c = ps.complexity(&[2017], Some(1.0)).unwrap();
+
+        let (expected_eci, expected_pci) = complexity(&fair_share(&rca(&vals), Some(1.0)));
+
+        assert_eq!(c.eci_vector().as_slice(), expected_eci.as_slice());
+        assert_eq!(c.pci_vector().as_slice(), expected_pci.as_slice());
+
+        assert_eq!(c.eci("b").unwrap(), expected_eci[1]);
+        assert_eq!(c.pci("03").unwrap(), expected_pci[2]);
+        assert!(c.eci("zzz").is_err());
     }
 }
@@ -1,5 +1,7 @@
 use nalgebra::DMatrix;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::io::Write;
 
 use crate::Error;
 
@@ -25,6 +27,160 @@ pub trait Mcp {
             country,
         )
     }
+
+    /// cells for many (country, product) pairs, None where either name
+    /// is unknown. Each distinct name is looked up once, so this is
+    /// cheaper than a `get` per pair when names repeat.
+    fn get_many(&self, pairs: &[(&str, &str)]) -> Vec<Option<f64>> {
+        let mut rows: HashMap<&str, Option<usize>> = HashMap::new();
+        let mut cols: HashMap<&str, Option<usize>> = HashMap::new();
+        let m = self.matrix();
+
+        pairs.iter()
+            .map(|(country, product)| {
+                let row = *rows.entry(*country)
+                    .or_insert_with(|| self.country_index().get(*country).cloned());
+                let col = *cols.entry(*product)
+                    .or_insert_with(|| self.product_index().get(*product).cloned());
+
+                match (row, col) {
+                    (Some(row), Some(col)) => Some(m[(row, col)]),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// a country's row, by product name
+    fn get_country_map(&self, country: &str) -> Result<HashMap<String, f64>, Error> {
+        let row = lookup(self.country_index(), country, "country")?;
+
+        Ok(self.product_index().iter()
+            .map(|(product, col)| (product.clone(), self.matrix()[(row, *col)]))
+            .collect())
+    }
+
+    /// a product's column, by country name
+    fn get_product_map(&self, product: &str) -> Result<HashMap<String, f64>, Error> {
+        let col = lookup(self.product_index(), product, "product")?;
+
+        Ok(self.country_index().iter()
+            .map(|(country, row)| (country.clone(), self.matrix()[(*row, col)]))
+            .collect())
+    }
+
+    /// the block for `countries` x `products`, in the order given
+    /// rather than index order. `Error::MissingIndex` for an unknown name.
+    fn submatrix(&self, countries: &[&str], products: &[&str]) -> Result<DMatrix<f64>, Error> {
+        let rows = countries.iter()
+            .map(|c| lookup(self.country_index(), c, "country"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let cols = products.iter()
+            .map(|p| lookup(self.product_index(), p, "product"))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let m = self.matrix();
+        Ok(DMatrix::from_fn(rows.len(), cols.len(), |i, j| m[(rows[i], cols[j])]))
+    }
+
+    /// (countries, products)
+    fn dims(&self) -> (usize, usize) {
+        self.matrix().shape()
+    }
+
+    /// count of nonzero cells
+    fn nnz(&self) -> usize {
+        self.matrix().iter().filter(|x| **x != 0.0).count()
+    }
+
+    /// (min, max) of all cells, ignoring NaN.
+    /// (inf, -inf) if there are no non-NaN cells.
+    fn value_range(&self) -> (f64, f64) {
+        self.matrix().iter()
+            .fold((std::f64::INFINITY, std::f64::NEG_INFINITY), |(min, max), x| {
+                (min.min(*x), max.max(*x))
+            })
+    }
+
+    /// Writes a header of product names, then one row per country.
+    /// Rows are written straight to `w` one at a time, so the output is
+    /// never held in memory; wrap `w` in a `BufWriter` if needed.
+    ///
+    /// Names containing the delimiter, a quote or a line break are
+    /// quoted, with quotes doubled (as in RFC 4180).
+    fn write_delimited(&self, w: &mut dyn Write, delimiter: char) -> Result<(), Error> {
+        write_delimited(
+            &self.matrix(),
+            &self.country_index(),
+            &self.product_index(),
+            w,
+            delimiter,
+        )
+    }
+
+    fn write_csv(&self, w: &mut dyn Write) -> Result<(), Error> {
+        self.write_delimited(w, ',')
+    }
+
+    fn write_tsv(&self, w: &mut dyn Write) -> Result<(), Error> {
+        self.write_delimited(w, '\t')
+    }
+}
+
+pub(crate) fn lookup(
+    index: &HashMap<String, usize>,
+    member: &str,
+    index_name: &str,
+    ) -> Result<usize, Error>
+{
+    index.get(member)
+        .cloned()
+        .ok_or_else(|| Error::MissingIndex { member: member.into(), index: index_name.into() })
+}
+
+/// names ordered by their index
+pub(crate) fn names_by_index(index: &HashMap<String, usize>) -> Vec<&str> {
+    let mut names = vec![""; index.len()];
+    for (name, i) in index {
+        names[*i] = name.as_str();
+    }
+    names
+}
+
+fn write_delimited(
+    m: &DMatrix<f64>,
+    country_index: &HashMap<String, usize>,
+    product_index: &HashMap<String, usize>,
+    w: &mut dyn Write,
+    delimiter: char,
+    ) -> Result<(), Error>
+{
+    write!(w, "country")?;
+    for product in names_by_index(product_index) {
+        write!(w, "{}{}", delimiter, quoted(product, delimiter))?;
+    }
+    writeln!(w)?;
+
+    for (i, country) in names_by_index(country_index).into_iter().enumerate() {
+        write!(w, "{}", quoted(country, delimiter))?;
+        for x in m.row(i).iter() {
+            write!(w, "{}{}", delimiter, x)?;
+        }
+        writeln!(w)?;
+    }
+
+    w.flush()?;
+
+    Ok(())
+}
+
+// a name as a delimited field
+fn quoted(name: &str, delimiter: char) -> Cow<str> {
+    if name.contains(|c: char| c == delimiter || c == '"' || c == '\n' || c == '\r') {
+        Cow::Owned(format!("\"{}\"", name.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(name)
+    }
 }
 
 // TODO: put in util module?
@@ -65,3 +221,137 @@ fn get_by_country(
 
     Ok(matrix_row.iter().cloned().collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{Provenance, Rca};
+
+    #[test]
+    fn test_stats() {
+        let rca = Rca {
+            country_idx: Arc::new([("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect()),
+            product_idx: Arc::new([("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect()),
+            m: DMatrix::from_vec(2,3,vec![0.0,2.0,std::f64::NAN,4.0,0.0,-1.5]),
+            provenance: Provenance::default(),
+        };
+
+        assert_eq!(rca.dims(), (2, 3));
+        // NaN is nonzero
+        assert_eq!(rca.nnz(), 4);
+        assert_eq!(rca.value_range(), (-1.5, 4.0));
+    }
+
+    #[test]
+    fn test_maps() {
+        let rca = Rca {
+            country_idx: Arc::new([("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect()),
+            product_idx: Arc::new([("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect()),
+            m: DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]),
+            provenance: Provenance::default(),
+        };
+
+        let country = rca.get_country_map("b").unwrap();
+        assert_eq!(country.len(), 3);
+        assert_eq!(country["01"], 2.0);
+        assert_eq!(country["03"], 6.0);
+
+        let product = rca.get_product_map("02").unwrap();
+        assert_eq!(product.len(), 2);
+        assert_eq!(product["a"], 3.0);
+        assert_eq!(product["b"], 4.0);
+
+        assert!(rca.get_country_map("zz").is_err());
+        assert!(rca.get_product_map("zz").is_err());
+    }
+
+    #[test]
+    fn test_get_many() {
+        let rca = Rca {
+            country_idx: Arc::new([("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect()),
+            product_idx: Arc::new([("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect()),
+            m: DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]),
+            provenance: Provenance::default(),
+        };
+
+        let res = rca.get_many(&[("a", "01"), ("b", "03"), ("zz", "01"), ("a", "zz"), ("b", "01")]);
+        assert_eq!(res, vec![Some(1.0), Some(6.0), None, None, Some(2.0)]);
+
+        for (country, product) in &[("a", "02"), ("b", "02")] {
+            assert_eq!(rca.get_many(&[(*country, *product)])[0], rca.get(country, product).ok());
+        }
+        assert!(rca.get_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_submatrix() {
+        let rca = Rca {
+            country_idx: Arc::new([("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect()),
+            product_idx: Arc::new([("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect()),
+            m: DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]),
+            provenance: Provenance::default(),
+        };
+
+        let res = rca.submatrix(&["b", "a"], &["03", "01"]).unwrap();
+        assert_eq!(res, DMatrix::from_vec(2,2,vec![6.0,5.0,2.0,1.0]));
+
+        match rca.submatrix(&["a"], &["01", "zz"]) {
+            Err(Error::MissingIndex { member, index }) => {
+                assert_eq!(member, "zz");
+                assert_eq!(index, "product");
+            },
+            _ => panic!("expected missing index"),
+        }
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let rca = Rca {
+            country_idx: Arc::new([("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect()),
+            product_idx: Arc::new([("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect()),
+            m: DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.5]),
+            provenance: Provenance::default(),
+        };
+
+        let mut out: Vec<u8> = vec![];
+        rca.write_csv(&mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "country,01,02,03");
+        assert_eq!(lines[1], "a,1,3,5");
+
+        let last: Vec<_> = lines[2].split(',').collect();
+        assert_eq!(last[0], "b");
+        let vals: Vec<f64> = last[1..].iter().map(|x| x.parse().unwrap()).collect();
+        assert_eq!(vals, rca.get_country("b").unwrap());
+    }
+
+    #[test]
+    fn test_write_csv_quoting() {
+        let rca = Rca {
+            country_idx: Arc::new([("Korea, Rep.".to_string(),0usize), ("a \"b\"".to_string(),1)].iter().cloned().collect()),
+            product_idx: Arc::new([("01".to_string(),0usize), ("fish\tchips".to_string(),1)].iter().cloned().collect()),
+            m: DMatrix::from_vec(2,2,vec![1.0,2.0,3.0,4.0]),
+            provenance: Provenance::default(),
+        };
+
+        let mut out: Vec<u8> = vec![];
+        rca.write_csv(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "country,01,fish\tchips");
+        assert_eq!(lines[1], "\"Korea, Rep.\",1,3");
+        assert_eq!(lines[2], "\"a \"\"b\"\"\",2,4");
+
+        let mut out: Vec<u8> = vec![];
+        rca.write_tsv(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "country\t01\t\"fish\tchips\"");
+        assert_eq!(lines[1], "Korea, Rep.\t1\t3");
+    }
+}
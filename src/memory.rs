@@ -0,0 +1,113 @@
+use nalgebra::DMatrix;
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+
+use crate::{ProductSpace, SparseProximity};
+
+/// Estimated bytes of matrix data held by a `ProductSpace`, by cached
+/// map and by year. Only the f64 values are counted, not the map or
+/// matrix overhead, or the indexes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryReport {
+    pub mcps: MatrixMemory,
+    pub rcas: MatrixMemory,
+    pub rcas_cutoff: MatrixMemory,
+    pub proximities: MatrixMemory,
+}
+
+impl MemoryReport {
+    /// bytes across all maps
+    pub fn total(&self) -> usize {
+        self.mcps.total()
+            + self.rcas.total()
+            + self.rcas_cutoff.total()
+            + self.proximities.total()
+    }
+}
+
+/// bytes for each year's matrix in one map
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixMemory {
+    pub by_year: BTreeMap<u32, usize>,
+}
+
+impl MatrixMemory {
+    fn new(ms: &HashMap<u32, DMatrix<f64>>) -> Self {
+        let by_year = ms.iter()
+            .map(|(year, m)| (*year, m.len() * mem::size_of::<f64>()))
+            .collect();
+
+        Self { by_year }
+    }
+
+    // an index and an f64 per entry kept
+    fn sparse(ms: &HashMap<u32, SparseProximity>) -> Self {
+        let by_year = ms.iter()
+            .map(|(year, m)| (*year, m.nnz() * (mem::size_of::<usize>() + mem::size_of::<f64>())))
+            .collect();
+
+        Self { by_year }
+    }
+
+    /// bytes across all years
+    pub fn total(&self) -> usize {
+        self.by_year.values().sum()
+    }
+}
+
+impl ProductSpace {
+    /// Estimated memory used by the cached matrixes. Mcps are 0 after
+    /// `drop_mcps`. Proximity is
+    /// products x products per year, so it's usually the largest, unless
+    /// it's sparse.
+    pub fn memory_report(&self) -> MemoryReport {
+        MemoryReport {
+            mcps: self.mcps.as_ref()
+                .map(MatrixMemory::new)
+                .unwrap_or_else(|| MatrixMemory { by_year: BTreeMap::new() }),
+            rcas: MatrixMemory::new(&self.rcas_by_year),
+            rcas_cutoff: MatrixMemory::new(&self.rcas_cutoff_by_year),
+            proximities: if self.sparse_proximity.is_some() {
+                MatrixMemory::sparse(&self.sparse_proximities_by_year)
+            } else {
+                MatrixMemory::new(&self.proximities_by_year)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_report() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]));
+        mcps.insert(2017, DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let report = ps.memory_report();
+
+        assert_eq!(report.mcps.by_year[&2017], 6 * 8);
+        assert_eq!(report.rcas.total(), 2 * 6 * 8);
+        assert_eq!(report.proximities.by_year[&2016], 9 * 8);
+        assert_eq!(report.total(), 3 * 2 * 6 * 8 + 2 * 9 * 8);
+
+        let ps = ProductSpace::builder(
+            ps.country_index().clone(),
+            ps.product_index().clone(),
+            ps.mcps().unwrap().clone(),
+        )
+            .compute_proximity(false)
+            .build();
+
+        assert_eq!(ps.memory_report().proximities.total(), 0);
+    }
+}
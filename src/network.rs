@@ -0,0 +1,138 @@
+use nalgebra::DMatrix;
+use std::collections::HashSet;
+
+/// Maximum spanning tree of a symmetric proximity matrix, as
+/// (product idx, product idx, proximity) edges in the order they're
+/// added (Prim's algorithm, starting from product 0).
+///
+/// This is the backbone of the product space visualization: every
+/// product is connected through its strongest links. The diagonal is
+/// ignored, and NaN entries are treated as no link, so if the products
+/// can't all be connected the result is a spanning forest.
+pub fn maximum_spanning_tree(proximity: &DMatrix<f64>) -> Vec<(usize, usize, f64)> {
+    let n = proximity.nrows();
+
+    let mut in_tree = vec![false; n];
+    // strongest link from the tree to each product not yet in it
+    let mut best = vec![std::f64::NEG_INFINITY; n];
+    let mut parent: Vec<Option<usize>> = vec![None; n];
+
+    let mut edges = vec![];
+
+    for _ in 0..n {
+        // ties go to the lowest index
+        let next = (0..n)
+            .filter(|i| !in_tree[*i])
+            .fold(None, |acc: Option<usize>, i| {
+                match acc {
+                    Some(j) if best[j] >= best[i] => Some(j),
+                    _ => Some(i),
+                }
+            });
+
+        let v = match next {
+            Some(v) => v,
+            None => break,
+        };
+
+        in_tree[v] = true;
+        if let Some(p) = parent[v] {
+            edges.push((p, v, proximity[(p, v)]));
+        }
+
+        for u in 0..n {
+            if !in_tree[u] && proximity[(v, u)] > best[u] {
+                best[u] = proximity[(v, u)];
+                parent[u] = Some(v);
+            }
+        }
+    }
+
+    edges
+}
+
+/// The product space network as published by the Atlas: the maximum
+/// spanning tree, plus every other link with proximity above
+/// `threshold` (commonly 0.55). The tree keeps every product connected,
+/// and the threshold adds the dense clusters the tree alone would hide.
+///
+/// Edges are (product idx, product idx, proximity), tree edges first in
+/// the order of `maximum_spanning_tree`, then the rest by
+/// (lower idx, higher idx). Each link appears once.
+pub fn atlas_network(proximity: &DMatrix<f64>, threshold: f64) -> Vec<(usize, usize, f64)> {
+    let n = proximity.nrows();
+
+    let mut edges = maximum_spanning_tree(proximity);
+    let in_tree: HashSet<(usize, usize)> = edges.iter()
+        .map(|(p, q, _)| (*p.min(q), *p.max(q)))
+        .collect();
+
+    for p in 0..n {
+        for q in p+1..n {
+            let phi = proximity[(p, q)];
+            if phi > threshold && !in_tree.contains(&(p, q)) {
+                edges.push((p, q, phi));
+            }
+        }
+    }
+
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maximum_spanning_tree() {
+        let m = DMatrix::from_row_slice(4,4,&[
+            1.0, 0.9, 0.1, 0.2,
+            0.9, 1.0, 0.3, 0.8,
+            0.1, 0.3, 1.0, 0.4,
+            0.2, 0.8, 0.4, 1.0,
+        ]);
+
+        let mst = maximum_spanning_tree(&m);
+
+        assert_eq!(mst, vec![(0, 1, 0.9), (1, 3, 0.8), (3, 2, 0.4)]);
+    }
+
+    #[test]
+    fn test_maximum_spanning_tree_disconnected() {
+        let nan = std::f64::NAN;
+        let m = DMatrix::from_row_slice(3,3,&[
+            1.0, 0.5, nan,
+            0.5, 1.0, nan,
+            nan, nan, 1.0,
+        ]);
+
+        let mst = maximum_spanning_tree(&m);
+
+        assert_eq!(mst, vec![(0, 1, 0.5)]);
+    }
+
+    #[test]
+    fn test_atlas_network() {
+        let m = DMatrix::from_row_slice(4,4,&[
+            1.0, 0.9, 0.1, 0.2,
+            0.9, 1.0, 0.3, 0.8,
+            0.1, 0.3, 1.0, 0.4,
+            0.2, 0.8, 0.4, 1.0,
+        ]);
+
+        // 0.9 and 0.8 are already in the tree
+        assert_eq!(atlas_network(&m, 0.75), vec![(0, 1, 0.9), (1, 3, 0.8), (3, 2, 0.4)]);
+        assert_eq!(
+            atlas_network(&m, 0.25),
+            vec![(0, 1, 0.9), (1, 3, 0.8), (3, 2, 0.4), (1, 2, 0.3)],
+        );
+
+        // tree edges are kept whatever the threshold
+        let mst = maximum_spanning_tree(&m);
+        for threshold in &[0.0, 0.35, 0.55, 0.85, 1.0, 2.0] {
+            let network = atlas_network(&m, *threshold);
+            assert_eq!(network[..mst.len()], mst[..]);
+            assert!(network[mst.len()..].iter().all(|(_, _, phi)| *phi > *threshold));
+        }
+    }
+}
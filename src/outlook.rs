@@ -0,0 +1,114 @@
+use nalgebra::{DMatrix, DVector};
+
+use crate::complexity::complexity_by_diversity;
+use crate::{density, Density, ProductSpace};
+
+/// Complexity outlook gain, as in the Atlas of Economic Complexity
+/// (Hausmann et al. 2014), for every country and product:
+///
+/// cog(c, p) = sum_p'(phi(p, p') / sum_p''(phi(p'', p')) * (1 - m(c, p')) * pci(p'))
+///           - (1 - d(c, p)) * pci(p)
+///
+/// where m is the binary rca (countries x products), phi the proximity,
+/// and d the density. How much exporting p would bring a country closer
+/// to complex products it doesn't export yet, less what it gives up
+/// moving away from p's own complexity.
+pub fn complexity_outlook_gain(
+    rca: &DMatrix<f64>,
+    proximity: &DMatrix<f64>,
+    pci: &DVector<f64>,
+    ) -> DMatrix<f64>
+{
+    let (countries, products) = rca.shape();
+
+    // sum of proximities for each product
+    let sums = proximity.row_sum();
+    let weights = DMatrix::from_fn(products, products, |p, q| proximity[(p, q)] / sums[q]);
+
+    let unexported = DMatrix::from_fn(countries, products, |c, q| (1.0 - rca[(c, q)]) * pci[q]);
+    let gain = unexported * weights.transpose();
+
+    let d = density(rca, proximity);
+
+    DMatrix::from_fn(countries, products, |c, p| gain[(c, p)] - (1.0 - d[(c, p)]) * pci[p])
+}
+
+impl ProductSpace {
+    /// complexity outlook gain (see `complexity_outlook_gain`) from the
+    /// binary rca (the fair share default of 1.0 if there's no cutoff),
+    /// with pci from the same binary rca as in `complexity`.
+    pub fn complexity_outlook_gain(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Option<Density>
+    {
+        let rca = self.binary_rca_matrix(years, rca_cutoff)?;
+        let proximity = self.proximity_matrix(years)?;
+        let (_, pci) = complexity_by_diversity(&rca);
+
+        let mut m = complexity_outlook_gain(&rca, &proximity, &pci.column(0).into_owned());
+        m.apply(|x| self.sanitized(x));
+
+        Some(Density {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            m,
+            provenance: self.binary_provenance(years, rca_cutoff),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+    use crate::Mcp;
+
+    #[test]
+    fn test_complexity_outlook_gain() {
+        // binary rows: a [1,0,1], b [0,1,0], c [0,0,1], d [1,1,0]
+        let rca = DMatrix::from_vec(4,3,vec![1.0,0.0,0.0,1.0,0.0,1.0,0.0,1.0,1.0,0.0,1.0,0.0]);
+        let proximity = DMatrix::from_vec(3,3,vec![1.0,0.5,0.5,0.5,1.0,0.0,0.5,0.0,1.0]);
+        let pci = DVector::from_vec(vec![-1.0, 0.5, 2.0]);
+
+        let res = complexity_outlook_gain(&rca, &proximity, &pci);
+        let d = density(&rca, &proximity);
+
+        for c in 0..4 {
+            for p in 0..3 {
+                let mut expected = 0.0;
+                for q in 0..3 {
+                    let sum: f64 = (0..3).map(|k| proximity[(k, q)]).sum();
+                    expected += proximity[(p, q)] / sum * (1.0 - rca[(c, q)]) * pci[q];
+                }
+                expected -= (1.0 - d[(c, p)]) * pci[p];
+
+                assert!((res[(c, p)] - expected).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ps_complexity_outlook_gain() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2), ("d".to_string(),3)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let res = ps.complexity_outlook_gain(&[2017], Some(1.0)).unwrap();
+
+        let rca = ps.binary_rca_matrix(&[2017], Some(1.0)).unwrap();
+        let proximity = ps.proximity_matrix(&[2017]).unwrap();
+        let pci = ps.complexity(&[2017], Some(1.0)).unwrap().pci_vector().clone();
+        let expected = complexity_outlook_gain(&rca, &proximity, &pci);
+
+        assert_eq!(res.matrix(), &expected);
+        assert!(ps.complexity_outlook_gain(&[2099], Some(1.0)).is_none());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::{
+    Comparison,
+    MinTradeFilter,
+    NegativePolicy,
+    ProductSpace,
+    ProximityBasis,
+    ProximityMethod,
+    ProximityTransform,
+    Rca,
+    RcaVariant,
+    SanitizePolicy,
+    ValueKind,
+};
+
+/// How the rca behind a result was combined over its years
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub enum Smoothing {
+    /// one year, nothing combined
+    SingleYear,
+    /// rca averaged over the years
+    Average,
+    /// binary rca, 1.0 only where rca passes the cutoff in every year
+    CutoffInEveryYear,
+    /// binary rca, from the fair share default cutoff of the average rca
+    AverageThenCutoff,
+    /// mcps summed over the years before calculating
+    Sum,
+    /// the later year minus the earlier, each year on its own
+    Difference,
+}
+
+/// How a result was calculated: the crate version, the years and
+/// cutoff of the call, and the settings the product space was built
+/// with. Attached to `Rca`, `Density` and `Complexity`, so a result
+/// saved on its own can still be reproduced.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub struct Provenance {
+    pub crate_version: String,
+    /// years as requested, including any that were skipped as missing
+    pub years: Vec<u32>,
+    /// missing years were interpolated rather than skipped (see
+    /// `ProductSpaceBuilder::interpolate_missing_years`)
+    pub interpolate_missing_years: bool,
+    /// years proximity was averaged over, from
+    /// `ProductSpace::density_windows`. None where it's `years`
+    pub proximity_years: Option<Vec<u32>>,
+    /// the cutoff passed to the call
+    pub rca_cutoff: Option<f64>,
+    /// the cutoff the product space was built with, used for cached
+    /// binary rca and proximity
+    pub build_cutoff: Option<f64>,
+    /// the cap passed to the call (see `ProductSpace::rca_capped`)
+    pub rca_cap: Option<f64>,
+    /// the cap the product space was built with, applied to every
+    /// year's rca (see `ProductSpaceBuilder::rca_cap`)
+    pub build_rca_cap: Option<f64>,
+    pub comparison: Comparison,
+    pub value_kind: ValueKind,
+    pub rca_variant: RcaVariant,
+    /// rca compared each country against the rest of the world (see
+    /// `ProductSpaceBuilder::leave_one_out`)
+    pub leave_one_out: bool,
+    pub min_trade_filter: Option<MinTradeFilter>,
+    /// mcps were multiplied by an adjustment before rca (see
+    /// `ProductSpaceBuilder::adjustment`)
+    pub adjusted: bool,
+    pub negative_policy: NegativePolicy,
+    pub proximity_basis: ProximityBasis,
+    pub proximity_method: ProximityMethod,
+    /// the threshold proximity was kept sparse at (see
+    /// `ProductSpaceBuilder::sparse_proximity`)
+    pub sparse_proximity: Option<f64>,
+    /// the proximity floor passed to the call (see `DensityOptions`)
+    pub min_proximity: Option<f64>,
+    /// the proximity transform passed to the call (see `DensityOptions`)
+    pub transform: Option<ProximityTransform>,
+    pub sanitize: SanitizePolicy,
+    pub smoothing: Smoothing,
+}
+
+impl Default for Provenance {
+    /// no years, and the builder defaults
+    fn default() -> Self {
+        Provenance {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            years: Vec::new(),
+            interpolate_missing_years: false,
+            proximity_years: None,
+            rca_cutoff: None,
+            build_cutoff: None,
+            rca_cap: None,
+            build_rca_cap: None,
+            comparison: Comparison::default(),
+            value_kind: ValueKind::default(),
+            rca_variant: RcaVariant::default(),
+            leave_one_out: false,
+            min_trade_filter: None,
+            adjusted: false,
+            negative_policy: NegativePolicy::default(),
+            proximity_basis: ProximityBasis::default(),
+            proximity_method: ProximityMethod::default(),
+            sparse_proximity: None,
+            min_proximity: None,
+            transform: None,
+            sanitize: SanitizePolicy::default(),
+            smoothing: Smoothing::SingleYear,
+        }
+    }
+}
+
+/// Which of the requested years an aggregation over years actually
+/// used. See `ProductSpace::year_usage`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct YearUsage {
+    /// found in the product space
+    pub years_used: Vec<u32>,
+    /// not found, and filled in from their neighbors (see
+    /// `ProductSpaceBuilder::interpolate_missing_years`)
+    pub years_interpolated: Vec<u32>,
+    /// not found, and skipped
+    pub years_missing: Vec<u32>,
+}
+
+impl YearUsage {
+    /// true if every requested year was found
+    pub fn is_complete(&self) -> bool {
+        self.years_interpolated.is_empty() && self.years_missing.is_empty()
+    }
+}
+
+impl ProductSpace {
+    /// How `years` would be used by an aggregating method (`rca`,
+    /// `density`, `complexity`, etc.), in the order given. Missing
+    /// years are skipped silently (logged at warn) by those methods, so
+    /// this lets a caller check before trusting a result. Interpolation
+    /// only applies to rca; proximity skips every missing year.
+    pub fn year_usage(&self, years: &[u32]) -> YearUsage {
+        let mut usage = YearUsage {
+            years_used: Vec::new(),
+            years_interpolated: Vec::new(),
+            years_missing: Vec::new(),
+        };
+
+        for year in years {
+            if self.rcas_by_year.contains_key(year) {
+                usage.years_used.push(*year);
+            } else if self.interpolate_missing_years && self.interpolation_years(years, *year).is_some() {
+                usage.years_interpolated.push(*year);
+            } else {
+                usage.years_missing.push(*year);
+            }
+        }
+
+        usage
+    }
+
+    /// `rca` with the `year_usage` of its years
+    pub fn rca_detailed(
+        &self,
+        years: &[u32],
+        cutoff: Option<f64>,
+        ) -> Option<(Rca, YearUsage)>
+    {
+        self.rca(years, cutoff)
+            .map(|rca| (rca, self.year_usage(years)))
+    }
+
+    pub(crate) fn provenance(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        smoothing: Smoothing,
+        ) -> Provenance
+    {
+        Provenance {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            years: years.to_vec(),
+            interpolate_missing_years: self.interpolate_missing_years,
+            proximity_years: None,
+            rca_cutoff,
+            build_cutoff: self.rca_cutoff,
+            rca_cap: None,
+            build_rca_cap: self.rca_cap,
+            comparison: self.comparison,
+            value_kind: self.value_kind,
+            rca_variant: self.rca_variant,
+            leave_one_out: self.leave_one_out,
+            min_trade_filter: self.min_trade_filter,
+            adjusted: self.adjusted,
+            negative_policy: self.negative_policy,
+            proximity_basis: self.proximity_basis,
+            proximity_method: self.proximity_method,
+            sparse_proximity: self.sparse_proximity,
+            min_proximity: None,
+            transform: None,
+            sanitize: self.sanitize,
+            smoothing,
+        }
+    }
+
+    /// provenance for `rca_matrix`
+    pub(crate) fn rca_provenance(&self, years: &[u32], rca_cutoff: Option<f64>) -> Provenance {
+        let smoothing = match rca_cutoff {
+            Some(_) => Smoothing::CutoffInEveryYear,
+            None => Smoothing::Average,
+        };
+        let smoothing = smoothing_by_len(years, smoothing);
+        self.provenance(years, rca_cutoff, smoothing)
+    }
+
+    /// provenance for `binary_rca_matrix`
+    pub(crate) fn binary_provenance(&self, years: &[u32], rca_cutoff: Option<f64>) -> Provenance {
+        let smoothing = match rca_cutoff {
+            Some(_) => Smoothing::CutoffInEveryYear,
+            None => Smoothing::AverageThenCutoff,
+        };
+        let smoothing = smoothing_by_len(years, smoothing);
+        self.provenance(years, rca_cutoff, smoothing)
+    }
+}
+
+/// `Smoothing::SingleYear` for one year, else `smoothing`
+pub(crate) fn smoothing_by_len(years: &[u32], smoothing: Smoothing) -> Smoothing {
+    if years.len() == 1 {
+        Smoothing::SingleYear
+    } else {
+        smoothing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use nalgebra::DMatrix;
+    use super::*;
+    use crate::{DensityOptions, ProductSpaceBuilder};
+
+    #[test]
+    fn test_provenance() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+        )
+            .rca_cutoff(Some(1.0))
+            .comparison(Comparison::Gt)
+            .proximity_method(ProximityMethod::Cosine)
+            .min_trade_filter(MinTradeFilter { value: 1.0 })
+            .adjustment(DMatrix::from_element(3, 3, 1.0))
+            .negative_policy(NegativePolicy::ClampZero)
+            .sparse_proximity(Some(0.1))
+            .interpolate_missing_years(true)
+            .rca_cap(Some(10.0))
+            .leave_one_out(true)
+            .build();
+
+        let rca = ps.rca(&[2016, 2017], None).unwrap();
+        assert_eq!(rca.provenance(), &Provenance {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            years: vec![2016, 2017],
+            interpolate_missing_years: true,
+            proximity_years: None,
+            rca_cutoff: None,
+            build_cutoff: Some(1.0),
+            rca_cap: None,
+            build_rca_cap: Some(10.0),
+            comparison: Comparison::Gt,
+            value_kind: ValueKind::Levels,
+            rca_variant: RcaVariant::Balassa,
+            leave_one_out: true,
+            min_trade_filter: Some(MinTradeFilter { value: 1.0 }),
+            adjusted: true,
+            negative_policy: NegativePolicy::ClampZero,
+            proximity_basis: ProximityBasis::BinaryRca,
+            proximity_method: ProximityMethod::Cosine,
+            sparse_proximity: Some(0.1),
+            min_proximity: None,
+            transform: None,
+            sanitize: SanitizePolicy::default(),
+            smoothing: Smoothing::Average,
+        });
+
+        let p = ps.rca_capped(&[2016, 2017], None, Some(2.0)).unwrap();
+        assert_eq!(p.provenance().rca_cap, Some(2.0));
+        assert_eq!(p.provenance().build_rca_cap, Some(10.0));
+
+        let p = ps.density(&[2016, 2017], Some(1.0)).unwrap();
+        assert_eq!(p.provenance().smoothing, Smoothing::CutoffInEveryYear);
+        assert_eq!(p.provenance().rca_cutoff, Some(1.0));
+        assert_eq!(p.provenance().min_proximity, None);
+
+        let options = DensityOptions { min_proximity: Some(0.5), transform: Some(ProximityTransform::Power(2.0)) };
+        let p = ps.density_with_options(&[2016, 2017], Some(1.0), options).unwrap();
+        assert_eq!(p.provenance().min_proximity, Some(0.5));
+        assert_eq!(p.provenance().transform, Some(ProximityTransform::Power(2.0)));
+
+        // carried over when rebuilding
+        let p = ps.rollup(2).unwrap().rca(&[2017], None).unwrap();
+        assert!(p.provenance().adjusted);
+        assert_eq!(p.provenance().negative_policy, NegativePolicy::ClampZero);
+
+        let p = ps.complexity(&[2016, 2017], None).unwrap();
+        assert_eq!(p.provenance().smoothing, Smoothing::AverageThenCutoff);
+
+        let p = ps.complexity(&[2017], None).unwrap();
+        assert_eq!(p.provenance().smoothing, Smoothing::SingleYear);
+        assert_eq!(p.provenance().years, vec![2017]);
+
+        let p = ps.density_windows(&[2017], &[2016, 2017], None).unwrap();
+        assert_eq!(p.provenance().years, vec![2017]);
+        assert_eq!(p.provenance().proximity_years, Some(vec![2016, 2017]));
+
+        let p = ps.density_delta(2016, 2017, None).unwrap();
+        assert_eq!(p.provenance().smoothing, Smoothing::Difference);
+        assert_eq!(p.provenance().years, vec![2016, 2017]);
+    }
+
+    #[test]
+    fn test_year_usage() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2015, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let builder = || ProductSpaceBuilder::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps.clone(),
+        );
+        let ps = builder().build();
+
+        let (rca, usage) = ps.rca_detailed(&[2015, 2016, 2017, 2099], None).unwrap();
+        assert_eq!(rca.matrix(), ps.rca(&[2015, 2016, 2017, 2099], None).unwrap().matrix());
+        assert_eq!(usage, YearUsage {
+            years_used: vec![2015, 2017],
+            years_interpolated: vec![],
+            years_missing: vec![2016, 2099],
+        });
+        assert!(!usage.is_complete());
+        assert!(ps.year_usage(&[2017, 2015]).is_complete());
+
+        // only the interior gap is filled
+        let ps = builder().interpolate_missing_years(true).build();
+        let usage = ps.year_usage(&[2015, 2016, 2017, 2099]);
+        assert_eq!(usage.years_interpolated, vec![2016]);
+        assert_eq!(usage.years_missing, vec![2099]);
+
+        // 2017 is found, but not requested
+        let usage = ps.year_usage(&[2015, 2016, 2099]);
+        assert_eq!(usage.years_missing, vec![2016, 2099]);
+    }
+}
@@ -1,5 +1,72 @@
 use nalgebra::DMatrix;
 
+/// Which rca the cached proximities are calculated from.
+///
+/// `BinaryRca` uses the cutoff (fair share) rca, so the numerator
+/// counts co-exporting countries.
+///
+/// `ContinuousRca` uses the raw rca values in the same formula:
+///
+/// phi(p, q) = sum_c(rca_cp * rca_cq) / sqrt(sum_c(rca_cp) * sum_c(rca_cq))
+///
+/// which weights each country by the strength of its advantage in both
+/// products, rather than just its presence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProximityBasis {
+    BinaryRca,
+    ContinuousRca,
+}
+
+impl Default for ProximityBasis {
+    fn default() -> Self {
+        ProximityBasis::BinaryRca
+    }
+}
+
+/// How the cached proximities are calculated.
+///
+/// `ConditionalProbability` is `proximity`.
+///
+/// `Cosine` is `cosine_proximity`, the cosine similarity of the
+/// products' rca columns (over countries).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProximityMethod {
+    ConditionalProbability,
+    Cosine,
+}
+
+impl Default for ProximityMethod {
+    fn default() -> Self {
+        ProximityMethod::ConditionalProbability
+    }
+}
+
+/// A transform of proximity applied before density is calculated, to
+/// change how much strong links count against weak ones without
+/// rebuilding the proximity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProximityTransform {
+    /// phi^p. A `p` above 1.0 emphasizes the strongest links
+    Power(f64),
+    /// 1 / (1 + e^(-steepness * (phi - midpoint))), a soft threshold at
+    /// `midpoint`
+    Sigmoid { midpoint: f64, steepness: f64 },
+}
+
+impl ProximityTransform {
+    pub fn apply(self, phi: f64) -> f64 {
+        match self {
+            ProximityTransform::Power(p) => phi.powf(p),
+            ProximityTransform::Sigmoid { midpoint, steepness } => {
+                1.0 / (1.0 + (-steepness * (phi - midpoint)).exp())
+            },
+        }
+    }
+}
+
 // rca input is matrix of rca, where
 // - col indexes are product
 // - row indexes are countries
@@ -10,12 +77,12 @@ pub fn proximity(rca: &DMatrix<f64>) -> DMatrix<f64> {
     // dot is just multiplication, not dot product
     // mul/div is componentwise, not sweeping or otherwise
 
-    // transpose matrix so row indexes are products
-    let mut rca_t = rca.transpose();
-    rca_t.apply(|x| if x.is_nan() { 0.0 } else { x });
+    let mut rca_nan_zeroed = rca.clone();
+    rca_nan_zeroed.apply(|x| if x.is_nan() { 0.0 } else { x });
 
-    // product of rca_tranpose and rca transpose transpose
-    let numerator_intersection = &rca_t * rca_t.transpose();
+    // product of rca transpose and rca. `tr_mul` doesn't allocate the
+    // transpose
+    let numerator_intersection = rca_nan_zeroed.tr_mul(&rca_nan_zeroed);
 
     // kp0 is vector of the sum of rca per product
     // (simoes says it's vector of the number of munics with RCA in given product,
@@ -37,6 +104,39 @@ pub fn proximity(rca: &DMatrix<f64>) -> DMatrix<f64> {
     phi
 }
 
+/// cosine similarity of each pair of product columns of rca (binary or
+/// continuous; rows are countries, cols are products):
+///
+/// phi(p, q) = sum_c(rca_cp * rca_cq) / sqrt(sum_c(rca_cp^2) * sum_c(rca_cq^2))
+///
+/// NaN rca is treated as 0.0. The diagonal is always 1.0, and the
+/// matrix is exactly symmetric. A product no country exports has NaN
+/// similarity to every other product.
+pub fn cosine_proximity(rca: &DMatrix<f64>) -> DMatrix<f64> {
+    let mut rca = rca.clone();
+    rca.apply(|x| if x.is_nan() { 0.0 } else { x });
+
+    let dot = rca.tr_mul(&rca);
+    let n = dot.nrows();
+
+    DMatrix::from_fn(n, n, |p, q| {
+        if p == q {
+            1.0
+        } else {
+            let (p, q) = (p.min(q), p.max(q));
+            dot[(p, q)] / (dot[(p, p)] * dot[(q, q)]).sqrt()
+        }
+    })
+}
+
+/// product x product count of countries exporting both products,
+/// from binary rca (rows are countries, cols are products).
+///
+/// This is the numerator of proximity, before normalizing.
+pub fn coexport_counts(rca: &DMatrix<f64>) -> DMatrix<f64> {
+    rca.tr_mul(rca)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -73,6 +173,45 @@ mod tests {
         assert_eq!(proximity, expected);
     }
 
+    #[test]
+    fn test_coexport_counts() {
+        // rows: [0,1,1], [1,1,0]
+        let m = DMatrix::from_vec(2,3,vec![0.0, 1.0, 1.0, 1.0, 1.0, 0.0]);
+
+        let counts = coexport_counts(&m);
+
+        let expected = DMatrix::from_vec(3,3,vec![1.0, 1.0, 0.0, 1.0, 2.0, 1.0, 0.0, 1.0, 1.0]);
+
+        assert_eq!(counts, expected);
+    }
+
+    #[test]
+    fn test_cosine_proximity() {
+        // product columns: [0,1], [1,1], [1,0]
+        let m = DMatrix::from_vec(2,3,vec![0.0, 1.0, 1.0, 1.0, 1.0, 0.0]);
+
+        let phi = cosine_proximity(&m);
+
+        let r = 1.0 / 2.0_f64.sqrt();
+        let expected = DMatrix::from_vec(3,3,vec![1.0, r, 0.0, r, 1.0, r, 0.0, r, 1.0]);
+        assert!((&phi - expected).iter().all(|x| x.abs() < 1e-12));
+
+        let phi = cosine_proximity(&rca(&DMatrix::from_vec(2,3,vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0])));
+        assert_eq!(phi, phi.transpose());
+        assert!(phi.diagonal().iter().all(|x| *x == 1.0));
+    }
+
+    #[test]
+    fn test_proximity_transform() {
+        assert_eq!(ProximityTransform::Power(2.0).apply(0.5), 0.25);
+        assert_eq!(ProximityTransform::Power(1.0).apply(0.3), 0.3);
+
+        let sigmoid = ProximityTransform::Sigmoid { midpoint: 0.55, steepness: 10.0 };
+        assert_eq!(sigmoid.apply(0.55), 0.5);
+        assert!(sigmoid.apply(0.9) > 0.95);
+        assert!(sigmoid.apply(0.1) < 0.05);
+    }
+
     #[test]
     fn test_proximity_0_1() {
         println!("columns: product, rows: country");
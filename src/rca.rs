@@ -1,4 +1,6 @@
-use nalgebra::DMatrix;
+use nalgebra::{DMatrix, DVector, RowDVector};
+
+use crate::Error;
 
 /// rca is (a/b) / (c/d)
 /// where
@@ -15,8 +17,42 @@ use nalgebra::DMatrix;
 /// The matrix should have dim1 indexed to columns, and dim2 to rows
 /// (e.g. jobs to columns, and countries to rows)
 ///
-/// No nulls in matrix; only zero values allowed
+/// No nulls in matrix; only zero values allowed. Values must not be
+/// negative (see `NegativePolicy`)
+///
+/// A product no one exports has NaN rca for every country (c/d is zero),
+/// as does a country with no exports. See `SanitizePolicy`.
 pub fn rca(m: &DMatrix<f64>) -> DMatrix<f64> {
+    rca_with(m, ValueKind::Levels)
+}
+
+/// What the values in a matrix given to `rca_with` are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub enum ValueKind {
+    /// export values (e.g. dollars), as `rca` expects
+    Levels,
+    /// each country's share of its own exports (rows summing to 1.0).
+    /// These are taken as a/b as is, so rows that don't sum to exactly
+    /// 1.0 (rounding, partial coverage) aren't normalized again.
+    Shares,
+}
+
+impl Default for ValueKind {
+    fn default() -> Self {
+        ValueKind::Levels
+    }
+}
+
+/// Like `rca`, but for values that may already be shares.
+///
+/// With `ValueKind::Shares`:
+/// - a/b is the matrix itself; b isn't used.
+/// - c/d is still column sum over total, but over shares that is the
+///   average share of the product across countries, each country
+///   weighted equally, rather than the product's share of world
+///   exports (which needs the country totals that shares don't have).
+pub fn rca_with(m: &DMatrix<f64>, kind: ValueKind) -> DMatrix<f64> {
     // Implementation:
     //
     // The given matrix is already `a`.
@@ -27,18 +63,8 @@ pub fn rca(m: &DMatrix<f64>) -> DMatrix<f64> {
     // this creates a new matrix to be the basis for the output
     let a = (*m).clone();
 
-    // find `b`
-    // the matrix op is col_sum, but it means adding all cols
-    // in a row
-    let b = a.column_sum();
-
-    // find `c`
-    // the matrix op is col_sum, but it means adding all rows
-    // in a col
-    let c = a.row_sum();
-
-    // find `d`
-    let d = a.sum();
+    // find `b`, `c` and `d`, see `aggregates`
+    let (b, c, d) = aggregates(&a);
 
     // c/d
     let mut c_d = c;
@@ -47,9 +73,11 @@ pub fn rca(m: &DMatrix<f64>) -> DMatrix<f64> {
 
     // to get a/b, sweep b across a
     let mut a_b = a;
-    for i in 0..a_b.nrows() {
-        let mut a_b_row = a_b.row_mut(i);
-        a_b_row.apply(|a_val| a_val / b[i]);
+    if kind == ValueKind::Levels {
+        for i in 0..a_b.nrows() {
+            let mut a_b_row = a_b.row_mut(i);
+            a_b_row.apply(|a_val| a_val / b[i]);
+        }
     }
     //dbg!(&a_b);
 
@@ -63,6 +91,90 @@ pub fn rca(m: &DMatrix<f64>) -> DMatrix<f64> {
     a_b_c_d
 }
 
+/// Leave-one-out rca: like `rca_with`, but each country is compared
+/// against the rest of the world, with its own exports taken out of the
+/// world aggregates:
+///
+/// (a/b) / ((c - a)/(d - b))
+///
+/// For a dominant exporter, its own exports are much of c and d, which
+/// pulls its rca towards 1.0; this doesn't.
+///
+/// The rest of the world is different for each country, so c/d can't
+/// be calculated once per product and swept across; it's calculated per
+/// cell instead. That's still one pass over the matrix, but slower than
+/// `rca_with`.
+///
+/// The only exporter of a product has no rest of the world to compare
+/// against (c - a is zero, which would give inf), so it gets the ordinary
+/// rca, d/b. That's finite and at least 1.0, so it has binary rca at the
+/// default cutoff, and isn't touched by the sanitize policy.
+pub fn rca_leave_one_out(m: &DMatrix<f64>, kind: ValueKind) -> DMatrix<f64> {
+    let (b, c, d) = aggregates(m);
+
+    DMatrix::from_fn(m.nrows(), m.ncols(), |i, j| {
+        let a = m[(i, j)];
+        let a_b = match kind {
+            ValueKind::Levels => a / b[i],
+            ValueKind::Shares => a,
+        };
+
+        let rest = c[j] - a;
+        if rest == 0.0 && a > 0.0 {
+            return a_b / (c[j] / d);
+        }
+
+        a_b / (rest / (d - b[i]))
+    })
+}
+
+// `b` is a vector of the sums of cols in each row. The matrix op is
+// col_sum, but it means adding all cols in a row.
+// `c` is a vector of the sums of rows in each col. The matrix op is
+// row_sum, but it means adding all rows in a col.
+// `d` is a scalar of the sum of all values in matrix
+#[cfg(not(feature = "kahan"))]
+pub(crate) fn aggregates(a: &DMatrix<f64>) -> (DVector<f64>, RowDVector<f64>, f64) {
+    (a.column_sum(), a.row_sum(), a.sum())
+}
+
+// With the `kahan` feature, the aggregates use compensated summation,
+// so precision isn't lost adding many small values to large totals.
+#[cfg(feature = "kahan")]
+pub(crate) fn aggregates(a: &DMatrix<f64>) -> (DVector<f64>, RowDVector<f64>, f64) {
+    let b = DVector::from_iterator(
+        a.nrows(),
+        (0..a.nrows()).map(|i| kahan_sum(a.row(i).iter().cloned())),
+    );
+    let c = RowDVector::from_iterator(
+        a.ncols(),
+        (0..a.ncols()).map(|j| kahan_sum(a.column(j).iter().cloned())),
+    );
+    let d = kahan_sum(a.iter().cloned());
+
+    (b, c, d)
+}
+
+// Neumaier's variant of Kahan summation, which also handles a value
+// larger than the running sum.
+#[cfg_attr(not(feature = "kahan"), allow(dead_code))]
+fn kahan_sum(xs: impl Iterator<Item=f64>) -> f64 {
+    let mut sum = 0.0_f64;
+    let mut compensation = 0.0_f64;
+
+    for x in xs {
+        let t = sum + x;
+        if sum.abs() >= x.abs() {
+            compensation += (sum - t) + x;
+        } else {
+            compensation += (x - t) + sum;
+        }
+        sum = t;
+    }
+
+    sum + compensation
+}
+
 // like rca, but in-place
 pub fn apply_rca(m: &mut DMatrix<f64>) {
     // Implementation:
@@ -75,18 +187,8 @@ pub fn apply_rca(m: &mut DMatrix<f64>) {
     // this creates a new matrix to be the basis for the output
     let a = m;
 
-    // find `b`
-    // the matrix op is col_sum, but it means adding all cols
-    // in a row
-    let b = a.column_sum();
-
-    // find `c`
-    // the matrix op is col_sum, but it means adding all rows
-    // in a col
-    let c = a.row_sum();
-
-    // find `d`
-    let d = a.sum();
+    // find `b`, `c` and `d`, see `aggregates`
+    let (b, c, d) = aggregates(a);
 
     // c/d
     let mut c_d = c;
@@ -109,37 +211,324 @@ pub fn apply_rca(m: &mut DMatrix<f64>) {
     }
 }
 
+/// rca, but with each country (row) scaled by a weight before the
+/// ratio is taken, e.g. to normalize exports by population or gdp.
+///
+/// Scaling row `i` by `w_i` scales both `a` and `b` for that row, so
+/// `a/b` is unchanged. The weights only enter through the world
+/// aggregates:
+///
+/// c: sum over countries of w_i * a
+/// d: sum over countries of w_i * b
+///
+/// so the weighting changes the reference `c/d` that every country
+/// is compared against.
+///
+/// Panics if the number of weights doesn't match the number of rows.
+pub fn rca_weighted(m: &DMatrix<f64>, country_weights: &[f64]) -> DMatrix<f64> {
+    assert_eq!(m.nrows(), country_weights.len(), "one weight per country (row) required");
+
+    let mut weighted = (*m).clone();
+    for (i, w) in country_weights.iter().enumerate() {
+        let mut row = weighted.row_mut(i);
+        row.apply(|x| x * w);
+    }
+
+    rca(&weighted)
+}
+
+/// A cutoff must be finite and at least 0.0, since NaN or negative
+/// cutoffs make all zeros or all ones binary rca. None (the default
+/// of 1.0) is always valid.
+pub fn validate_cutoff(cutoff: Option<f64>) -> Result<(), Error> {
+    match cutoff {
+        Some(cutoff) if !cutoff.is_finite() || cutoff < 0.0 => {
+            Err(Error::InvalidCutoff { cutoff })
+        },
+        _ => Ok(()),
+    }
+}
+
+/// How rca is compared against the cutoff for fair share.
+/// Defaults to `Gte`, rca >= cutoff
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub enum Comparison {
+    /// rca >= cutoff
+    Gte,
+    /// rca > cutoff, for methodologies that leave out rca of exactly 1.0
+    Gt,
+}
+
+impl Default for Comparison {
+    fn default() -> Self {
+        Comparison::Gte
+    }
+}
+
+impl Comparison {
+    /// true if `x` passes the cutoff
+    pub fn passes(self, x: f64, cutoff: f64) -> bool {
+        match self {
+            Comparison::Gte => x >= cutoff,
+            Comparison::Gt => x > cutoff,
+        }
+    }
+}
+
+/// How rca is expressed, applied to the Balassa rca once it's
+/// calculated. Defaults to `Balassa`.
+///
+/// Cutoffs are always given as Balassa rca (None is still 1.0), and
+/// mapped into the variant's terms before fair share; every variant is
+/// increasing in rca, so binary rca is the same whichever is used.
+/// Continuous rca (no cutoff), as in `rca` and density, is in the
+/// variant's terms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+pub enum RcaVariant {
+    /// a/b / c/d, 1.0 at fair share
+    Balassa,
+    /// (rca - 1) / (rca + 1), in [-1, 1] with 0.0 at fair share. Better
+    /// behaved in regressions than the unbounded Balassa rca
+    Symmetric,
+    /// ln(rca), 0.0 at fair share. rca of 0.0 is clamped to
+    /// `LOG_RCA_FLOOR` rather than -inf, so it stays finite in averages
+    /// and density, and still fails every cutoff above 0.0
+    Log,
+}
+
+impl Default for RcaVariant {
+    fn default() -> Self {
+        RcaVariant::Balassa
+    }
+}
+
+/// `RcaVariant::Log` of rca 0.0, ln of the smallest positive normal
+/// f64 (about -708)
+pub const LOG_RCA_FLOOR: f64 = -708.3964185322641;
+
+impl RcaVariant {
+    /// a Balassa rca (or cutoff) in this variant's terms
+    pub fn apply(self, rca: f64) -> f64 {
+        match self {
+            RcaVariant::Balassa => rca,
+            RcaVariant::Symmetric => (rca - 1.0) / (rca + 1.0),
+            RcaVariant::Log => if rca == 0.0 { LOG_RCA_FLOOR } else { rca.ln() },
+        }
+    }
+}
+
+/// rca against a reference group of countries (rows), rather than
+/// the world:
+///
+/// c: sum over peer countries of a
+/// d: sum over peer countries of b
+///
+/// `a/b` is still each country's own share, so every country, peer or
+/// not, is compared against the peers' `c/d`. This differs from taking
+/// the rca of only the peer rows, which would drop non-peers entirely.
+///
+/// Products the peers don't export at all have a `c/d` of 0.0, giving
+/// an rca of inf (or NaN where the country doesn't export it either).
+pub fn rca_relative_to(m: &DMatrix<f64>, peer_rows: &[usize]) -> DMatrix<f64> {
+    rca_relative_to_with(m, peer_rows, ValueKind::Levels)
+}
+
+/// Like `rca_relative_to`, but for values that may already be shares.
+/// With `ValueKind::Shares`, a/b is the matrix itself, and c/d is the
+/// peers' average share, as in `rca_with`.
+pub fn rca_relative_to_with(m: &DMatrix<f64>, peer_rows: &[usize], kind: ValueKind) -> DMatrix<f64> {
+    let b = m.column_sum();
+
+    let mut c = nalgebra::RowDVector::zeros(m.ncols());
+    for row in peer_rows {
+        c += m.row(*row);
+    }
+    let d = peer_rows.iter().map(|row| b[*row]).sum::<f64>();
+
+    let mut c_d = c;
+    c_d.apply(|x| x / d);
+
+    let mut res = (*m).clone();
+    if kind == ValueKind::Levels {
+        for i in 0..res.nrows() {
+            let mut row = res.row_mut(i);
+            row.apply(|a_val| a_val / b[i]);
+        }
+    }
+    for i in 0..res.ncols() {
+        let mut col = res.column_mut(i);
+        col.apply(|a_b_val| a_b_val / c_d[i]);
+    }
+
+    res
+}
+
+/// rca against an external reference basket, e.g. a region against its
+/// national economy: `c/d` is `reference_shares` (each product's share
+/// of the reference's total exports, one per column) rather than taken
+/// from the matrix. `a/b` is still each row's own share.
+///
+/// Returns `Error::LengthMismatch` unless there's one share per
+/// product. Products with a reference share of 0.0 give inf, or NaN
+/// where the row doesn't export them either.
+pub fn rca_vs_reference(m: &DMatrix<f64>, reference_shares: &[f64]) -> Result<DMatrix<f64>, Error> {
+    if reference_shares.len() != m.ncols() {
+        return Err(Error::LengthMismatch { expected: m.ncols(), found: reference_shares.len() });
+    }
+
+    let b = m.column_sum();
+
+    let mut res = (*m).clone();
+    for i in 0..res.nrows() {
+        let mut row = res.row_mut(i);
+        row.apply(|a_val| a_val / b[i]);
+    }
+    for (i, c_d) in reference_shares.iter().enumerate() {
+        let mut col = res.column_mut(i);
+        col.apply(|a_b_val| a_b_val / c_d);
+    }
+
+    Ok(res)
+}
+
+/// each country's share of the world total for a product, `a / c` in
+/// the terms of `rca`. Products no one exports are NaN. Needs export
+/// values (`ValueKind::Levels`): shares don't sum to a world total.
+pub fn world_share(m: &DMatrix<f64>) -> DMatrix<f64> {
+    let (_, c, _) = aggregates(m);
+
+    let mut res = (*m).clone();
+    for i in 0..res.ncols() {
+        let mut col = res.column_mut(i);
+        col.apply(|a_val| a_val / c[i]);
+    }
+
+    res
+}
+
 pub fn fair_share(m: &DMatrix<f64>, cutoff: Option<f64>) -> DMatrix<f64> {
-    let cutoff = cutoff.unwrap_or(1.0);
+    fair_share_with(m, cutoff, Comparison::Gte)
+}
 
+// like fair_share, but with a choice of comparison
+pub fn fair_share_with(m: &DMatrix<f64>, cutoff: Option<f64>, comparison: Comparison) -> DMatrix<f64> {
     let mut m = (*m).clone();
 
-    m.apply(|x| if x >= cutoff { 1.0 } else { 0.0 });
+    apply_fair_share_with(&mut m, cutoff, comparison);
 
     m
 }
 
 // like fair_share, but in place
 pub fn apply_fair_share(m: &mut DMatrix<f64>, cutoff: Option<f64>) {
+    apply_fair_share_with(m, cutoff, Comparison::Gte);
+}
+
+// like apply_fair_share, but with a choice of comparison
+pub fn apply_fair_share_with(m: &mut DMatrix<f64>, cutoff: Option<f64>, comparison: Comparison) {
     let cutoff = cutoff.unwrap_or(1.0);
 
-    m.apply(|x| if x >= cutoff { 1.0 } else { 0.0 });
+    m.apply(|x| if comparison.passes(x, cutoff) { 1.0 } else { 0.0 });
+}
+
+/// like apply_fair_share, but with a cutoff per product (column), e.g.
+/// for methodologies where the bar for an rca varies by product.
+/// Compares with `Comparison::Gte`.
+///
+/// Returns `Error::LengthMismatch` unless there's one threshold per
+/// column, and `Error::InvalidCutoff` for a threshold that isn't a
+/// valid cutoff (see `validate_cutoff`). The matrix is left unchanged
+/// on error.
+pub fn apply_fair_share_cols(m: &mut DMatrix<f64>, thresholds: &[f64]) -> Result<(), Error> {
+    if thresholds.len() != m.ncols() {
+        return Err(Error::LengthMismatch { expected: m.ncols(), found: thresholds.len() });
+    }
+    for cutoff in thresholds {
+        validate_cutoff(Some(*cutoff))?;
+    }
+
+    for (i, cutoff) in thresholds.iter().enumerate() {
+        let mut col = m.column_mut(i);
+        col.apply(|x| if Comparison::Gte.passes(x, *cutoff) { 1.0 } else { 0.0 });
+    }
+
+    Ok(())
 }
 
 // like fair_share, but in place
 /// This one does the cutoff for the first matrix, then multiplies
 /// it into the second
 pub fn apply_fair_share_into(m1: &mut DMatrix<f64>, into_m: &mut DMatrix<f64>, cutoff: Option<f64>) {
-    let cutoff = cutoff.unwrap_or(1.0);
+    apply_fair_share_into_with(m1, into_m, cutoff, Comparison::Gte);
+}
 
-    m1.apply(|x| if x >= cutoff { 1.0 } else { 0.0 });
+// like apply_fair_share_into, but with a choice of comparison
+pub fn apply_fair_share_into_with(
+    m1: &mut DMatrix<f64>,
+    into_m: &mut DMatrix<f64>,
+    cutoff: Option<f64>,
+    comparison: Comparison,
+    )
+{
+    apply_fair_share_with(m1, cutoff, comparison);
     into_m.component_mul_assign(m1);
 }
 
+/// true if every value is exactly 0.0 or 1.0, as fair share
+/// should produce
+pub fn is_binary(m: &DMatrix<f64>) -> bool {
+    m.iter().all(|x| *x == 0.0 || *x == 1.0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_rca_shares() {
+        // b's shares only sum to 0.8
+        let m = DMatrix::from_vec(2,2,vec![0.25, 0.5, 0.75, 0.3]);
+        let res = rca_with(&m, ValueKind::Shares);
+
+        // c/d: [0.75/1.8, 1.05/1.8]
+        let expected = DMatrix::from_vec(2,2,vec![
+            0.25 / (0.75 / 1.8),
+            0.5 / (0.75 / 1.8),
+            0.75 / (1.05 / 1.8),
+            0.3 / (1.05 / 1.8),
+        ]);
+        assert!((&res - expected).iter().all(|x| x.abs() < 1e-12));
+
+        // as levels, b's row is normalized again
+        assert!((rca(&m) - res).iter().any(|x| x.abs() > 1e-3));
+
+        assert_eq!(rca_with(&m, ValueKind::Levels), rca(&m));
+    }
+
+    #[test]
+    fn test_rca_leave_one_out() {
+        // a: [1,3,5], b: [2,4,6]. With two countries, each is compared
+        // to the other alone
+        let m = DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]);
+        let res = rca_leave_one_out(&m, ValueKind::Levels);
+
+        assert!((res[(0, 0)] - (1.0 / 9.0) / (2.0 / 12.0)).abs() < 1e-12);
+        assert!((res[(1, 0)] - (2.0 / 12.0) / (1.0 / 9.0)).abs() < 1e-12);
+        assert!((res[(0, 2)] - (5.0 / 9.0) / (6.0 / 12.0)).abs() < 1e-12);
+
+        // further from 1.0 than with the country in the totals
+        let with = rca(&m);
+        assert!((res[(0, 0)] - 1.0).abs() > (with[(0, 0)] - 1.0).abs());
+
+        // the only exporter of 01 gets the ordinary rca
+        let m = DMatrix::from_vec(2,2,vec![1.0,0.0,1.0,1.0]);
+        let res = rca_leave_one_out(&m, ValueKind::Levels);
+        assert_eq!(res[(0, 0)], rca(&m)[(0, 0)]);
+        assert_eq!(res[(1, 0)], 0.0);
+    }
+
     #[test]
     fn test_basic_rca() {
         println!("columns: product, rows: country");
@@ -160,6 +549,33 @@ mod tests {
         assert_eq!(res, expected);
     }
 
+    #[test]
+    fn test_kahan_sum() {
+        // each 1.0 is below the precision of 1e16 in naive summation
+        let mut xs = vec![1e16];
+        xs.extend(vec![1.0; 10]);
+
+        assert_eq!(xs.iter().sum::<f64>(), 1e16);
+        assert_eq!(kahan_sum(xs.iter().cloned()), 1e16 + 10.0);
+    }
+
+    #[cfg(feature = "kahan")]
+    #[test]
+    fn test_kahan_aggregates() {
+        let mut vals = vec![1e16, 0.0];
+        vals.extend(vec![1.0; 20]);
+        // first column [1e16, 0], then 10 columns of [1, 1]
+        let m = DMatrix::from_vec(2, 11, vals);
+
+        let (b, c, d) = aggregates(&m);
+
+        assert_eq!(b[0], 1e16 + 10.0);
+        assert_eq!(b[1], 10.0);
+        assert_eq!(c[0], 1e16);
+        assert_eq!(c[1], 2.0);
+        assert_eq!(d, 1e16 + 20.0);
+    }
+
     #[test]
     fn test_apply_rca() {
         let mut m = DMatrix::from_vec(2,3,vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
@@ -170,6 +586,98 @@ mod tests {
         assert_eq!(m, expected);
     }
 
+    #[test]
+    fn test_rca_weighted() {
+        let m = DMatrix::from_vec(2,3,vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        // unit weights are the same as plain rca
+        let res = rca_weighted(&m, &[1.0, 1.0]);
+        assert_eq!(res, rca(&m));
+
+        // second country counts double in the world aggregates
+        let res = rca_weighted(&m, &[1.0, 2.0]);
+
+        let expected = DMatrix::from_vec(2,3,vec![0.7333333333333333,1.0999999999999999,1.0,1.0,1.0784313725490198,0.9705882352941176]);
+
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn test_rca_relative_to() {
+        let m = DMatrix::from_vec(2,3,vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        // all countries as peers is the same as plain rca
+        assert_eq!(rca_relative_to(&m, &[0, 1]), rca(&m));
+
+        // only the second country as reference
+        let res = rca_relative_to(&m, &[1]);
+        let expected = DMatrix::from_vec(2,3,vec![0.6666666666666666,1.0,1.0,1.0,1.1111111111111112,1.0]);
+
+        assert_eq!(res, expected);
+
+        // shares are taken as a/b, as in `rca_with`
+        let shares = DMatrix::from_vec(2,3,vec![0.25,0.5,0.25,0.25,0.5,0.25]);
+        assert_eq!(rca_relative_to_with(&shares, &[0, 1], ValueKind::Shares), rca_with(&shares, ValueKind::Shares));
+        assert_eq!(rca_relative_to_with(&m, &[1], ValueKind::Levels), expected);
+    }
+
+    #[test]
+    fn test_rca_vs_reference() {
+        let m = DMatrix::from_vec(2,3,vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        // the matrix's own c/d is the same as plain rca
+        let (_, c, d) = aggregates(&m);
+        let shares: Vec<f64> = c.iter().map(|x| x / d).collect();
+        assert_eq!(rca_vs_reference(&m, &shares).unwrap(), rca(&m));
+
+        // reference with half its exports in the first product
+        let res = rca_vs_reference(&m, &[0.5, 0.25, 0.25]).unwrap();
+        assert_eq!(res[(0, 0)], (1.0 / 9.0) / 0.5);
+        assert_eq!(res[(1, 2)], (6.0 / 12.0) / 0.25);
+
+        match rca_vs_reference(&m, &[0.5, 0.5]) {
+            Err(Error::LengthMismatch { expected, found }) => {
+                assert_eq!(expected, 3);
+                assert_eq!(found, 2);
+            },
+            _ => panic!("expected length mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_world_share() {
+        let m = DMatrix::from_vec(2,3,vec![1.0, 3.0, 3.0, 1.0, 0.0, 0.0]);
+
+        let expected = DMatrix::from_vec(2,3,vec![0.25, 0.75, 0.75, 0.25, std::f64::NAN, std::f64::NAN]);
+        let res = world_share(&m);
+
+        assert_eq!(res.columns(0, 2), expected.columns(0, 2));
+        assert!(res.column(2).iter().all(|x| x.is_nan()));
+    }
+
+    #[test]
+    fn test_rca_variant() {
+        assert_eq!(RcaVariant::Balassa.apply(2.0), 2.0);
+        assert_eq!(RcaVariant::Symmetric.apply(1.0), 0.0);
+        assert_eq!(RcaVariant::Symmetric.apply(3.0), 0.5);
+        assert_eq!(RcaVariant::Symmetric.apply(0.0), -1.0);
+        assert_eq!(RcaVariant::Log.apply(1.0), 0.0);
+        assert_eq!(RcaVariant::Log.apply(0.0), LOG_RCA_FLOOR);
+        assert_eq!(LOG_RCA_FLOOR, std::f64::MIN_POSITIVE.ln());
+        assert!(RcaVariant::Log.apply(std::f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn test_validate_cutoff() {
+        assert!(validate_cutoff(None).is_ok());
+        assert!(validate_cutoff(Some(0.0)).is_ok());
+        assert!(validate_cutoff(Some(1.5)).is_ok());
+
+        assert!(validate_cutoff(Some(-1.0)).is_err());
+        assert!(validate_cutoff(Some(std::f64::NAN)).is_err());
+        assert!(validate_cutoff(Some(std::f64::INFINITY)).is_err());
+    }
+
     #[test]
     fn test_fair_share() {
         let m = DMatrix::from_vec(2,3,vec![0.7777777777777778,1.1666666666666667,1.0,1.0,1.0606060606060606,0.9545454545454545]);
@@ -192,6 +700,49 @@ mod tests {
         assert_eq!(m, expected);
     }
 
+    #[test]
+    fn test_apply_fair_share_cols() {
+        let mut m = DMatrix::from_vec(2,3,vec![0.7777777777777778,1.1666666666666667,1.0,1.0,1.0606060606060606,0.9545454545454545]);
+
+        // the same threshold everywhere is apply_fair_share
+        let mut same = m.clone();
+        apply_fair_share_cols(&mut same, &[1.0, 1.0, 1.0]).unwrap();
+        assert_eq!(same, fair_share(&m, None));
+
+        assert!(apply_fair_share_cols(&mut m, &[1.0, 1.0]).is_err());
+        assert!(apply_fair_share_cols(&mut m, &[1.0, -1.0, 1.0]).is_err());
+
+        apply_fair_share_cols(&mut m, &[0.5, 1.5, 0.9]).unwrap();
+        let expected = DMatrix::from_vec(2,3,vec![1.0,1.0,0.0,0.0,1.0,1.0]);
+
+        assert_eq!(m, expected);
+    }
+
+    #[test]
+    fn test_fair_share_comparison() {
+        let m = DMatrix::from_vec(2,3,vec![0.7777777777777778,1.1666666666666667,1.0,1.0,1.0606060606060606,0.9545454545454545]);
+
+        assert_eq!(fair_share_with(&m, None, Comparison::Gte), fair_share(&m, None));
+
+        // exactly 1.0 is left out
+        let res = fair_share_with(&m, None, Comparison::Gt);
+        let expected = DMatrix::from_vec(2,3,vec![0.0,1.0,0.0,0.0,1.0,0.0]);
+
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn test_is_binary() {
+        let m = DMatrix::from_vec(2,2,vec![0.0,1.0,1.0,0.0]);
+        assert!(is_binary(&m));
+
+        let m = DMatrix::from_vec(2,2,vec![0.0,1.0,0.5,0.0]);
+        assert!(!is_binary(&m));
+
+        let m = DMatrix::from_vec(2,3,vec![0.7777777777777778,1.1666666666666667,1.0,1.0,1.0606060606060606,0.9545454545454545]);
+        assert!(is_binary(&fair_share(&m, None)));
+    }
+
     #[test]
     fn test_apply_fair_share_into() {
         let mut m0 = DMatrix::from_element(4,2,1.0);
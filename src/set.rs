@@ -0,0 +1,124 @@
+use nalgebra::DMatrix;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{Error, ProductSpace, ProductSpaceBuilder};
+
+/// Product spaces for the same countries under several product
+/// classifications (e.g. hs92 and sitc), keyed by classification.
+///
+/// The country index is shared between them rather than copied. (To
+/// share it without a set, build each product space with
+/// `ProductSpaceBuilder::with_country_index` and the same `Arc`.)
+pub struct ProductSpaceSet {
+    country_idx: Arc<HashMap<String, usize>>,
+    spaces: HashMap<String, ProductSpace>,
+}
+
+impl ProductSpaceSet {
+    pub fn new(country_idx: HashMap<String, usize>) -> Self {
+        Self {
+            country_idx: Arc::new(country_idx),
+            spaces: HashMap::new(),
+        }
+    }
+
+    pub fn country_index(&self) -> &HashMap<String, usize> {
+        &self.country_idx
+    }
+
+    /// builder sharing this set's country index, for a product space to
+    /// `insert`. Mcp rows must follow the country index.
+    pub fn builder(
+        &self,
+        product_idx: HashMap<String, usize>,
+        mcps: HashMap<u32, DMatrix<f64>>,
+        ) -> ProductSpaceBuilder
+    {
+        ProductSpaceBuilder::with_country_index(self.country_idx.clone(), product_idx, mcps)
+    }
+
+    /// Adds a product space under `classification`, replacing any
+    /// already there. It must have been built with this set's country
+    /// index (see `builder`), or `Error::IndexMismatch` is returned.
+    pub fn insert(
+        &mut self,
+        classification: impl Into<String>,
+        ps: ProductSpace,
+        ) -> Result<(), Error>
+    {
+        if !Arc::ptr_eq(&self.country_idx, &ps.country_idx) {
+            return Err(Error::IndexMismatch { index: "country".into() });
+        }
+
+        self.spaces.insert(classification.into(), ps);
+
+        Ok(())
+    }
+
+    pub fn get(&self, classification: &str) -> Option<&ProductSpace> {
+        self.spaces.get(classification)
+    }
+
+    pub fn get_mut(&mut self, classification: &str) -> Option<&mut ProductSpace> {
+        self.spaces.get_mut(classification)
+    }
+
+    /// classifications in the set, sorted
+    pub fn classifications(&self) -> Vec<&str> {
+        let mut res: Vec<_> = self.spaces.keys().map(|c| c.as_str()).collect();
+        res.sort();
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_product_space_set() {
+        let mut set = ProductSpaceSet::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+        );
+
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,3,vec![1.0,2.0,3.0,4.0,5.0,6.0]));
+        let hs = set.builder(
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+        )
+            .rca_cutoff(Some(1.0))
+            .build();
+
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,2,vec![1.0,2.0,3.0,4.0]));
+        let sitc = set.builder(
+            [("0".to_string(),0usize), ("1".to_string(),1)].iter().cloned().collect(),
+            mcps,
+        )
+            .build();
+
+        set.insert("hs92", hs).unwrap();
+        set.insert("sitc", sitc).unwrap();
+
+        assert_eq!(set.classifications(), vec!["hs92", "sitc"]);
+        assert!(Arc::ptr_eq(&set.get("hs92").unwrap().country_idx, &set.get("sitc").unwrap().country_idx));
+        assert!(set.get("sitc").unwrap().rca(&[2017], None).is_some());
+        assert!(set.get("hs6").is_none());
+
+        // same countries, but not the shared index
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(2,2,vec![1.0,2.0,3.0,4.0]));
+        let other = ProductSpace::new(
+            set.country_index().clone(),
+            [("0".to_string(),0usize), ("1".to_string(),1)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+        match set.insert("other", other) {
+            Err(Error::IndexMismatch { index }) => assert_eq!(index, "country"),
+            _ => panic!("expected index mismatch"),
+        }
+    }
+}
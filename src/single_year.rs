@@ -0,0 +1,70 @@
+use crate::{Complexity, Density, Proximity, ProductSpace, Rca};
+
+/// Shortcuts for a single year, the same as the slice-based methods
+/// called with `&[year]`, which take the single-year path (no
+/// aggregation over years).
+impl ProductSpace {
+    /// `rca(&[year], cutoff)`
+    pub fn rca_year(&self, year: u32, cutoff: Option<f64>) -> Option<Rca> {
+        self.rca(&[year], cutoff)
+    }
+
+    /// `rca_cutoff(&[year])`
+    pub fn rca_cutoff_year(&self, year: u32) -> Option<Rca> {
+        self.rca_cutoff(&[year])
+    }
+
+    /// `proximity(&[year])`
+    pub fn proximity_year(&self, year: u32) -> Option<Proximity> {
+        self.proximity(&[year])
+    }
+
+    /// `density(&[year], rca_cutoff)`
+    pub fn density_year(&self, year: u32, rca_cutoff: Option<f64>) -> Option<Density> {
+        self.density(&[year], rca_cutoff)
+    }
+
+    /// `complexity(&[year], rca_cutoff)`
+    pub fn complexity_year(&self, year: u32, rca_cutoff: Option<f64>) -> Option<Complexity> {
+        self.complexity(&[year], rca_cutoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::DMatrix;
+    use std::collections::HashMap;
+    use super::*;
+    use crate::Mcp;
+
+    #[test]
+    fn test_single_year() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        for year in &[2016, 2017] {
+            assert_eq!(ps.rca_year(*year, None).unwrap().matrix(), ps.rca(&[*year], None).unwrap().matrix());
+            assert_eq!(ps.rca_cutoff_year(*year).unwrap().matrix(), ps.rca_cutoff(&[*year]).unwrap().matrix());
+            assert_eq!(ps.proximity_year(*year).unwrap().m, ps.proximity(&[*year]).unwrap().m);
+            assert_eq!(
+                ps.density_year(*year, Some(1.0)).unwrap().matrix(),
+                ps.density(&[*year], Some(1.0)).unwrap().matrix(),
+            );
+            assert_eq!(
+                ps.complexity_year(*year, Some(1.0)).unwrap().eci_vector(),
+                ps.complexity(&[*year], Some(1.0)).unwrap().eci_vector(),
+            );
+        }
+
+        assert!(ps.rca_year(2099, None).is_none());
+        assert!(ps.density_year(2099, None).is_none());
+    }
+}
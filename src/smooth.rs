@@ -1,20 +1,95 @@
+//! Smoothing for matrixes calculated over several years, e.g. the
+//! proximity of each year in a product space.
+//!
+//! All matrixes must have the same shape.
+
 use nalgebra::DMatrix;
 
-pub fn avg<'a>(ms: impl Iterator<Item=&'a DMatrix<f64>>, size: usize, count: usize) -> DMatrix<f64> {
-    let zeros = DMatrix::zeros(
-        size,
-        size,
-    );
+/// Elementwise average of `ms`, dividing by the number of matrixes.
+/// None if `ms` is empty.
+///
+/// A missing matrix (a year that isn't in the data) should be left
+/// out of `ms`, and then doesn't count towards the divisor. Passing an
+/// all zeros matrix instead counts it as a year with no links. Note
+/// that `ProductSpace::proximity` over several years does the latter,
+/// dividing by the number of years requested.
+///
+/// There's no special handling of NaN, so a NaN in any matrix makes
+/// that cell NaN. Proximity cached in a product space has NaN zeroed.
+pub fn avg(ms: &[&DMatrix<f64>]) -> Option<DMatrix<f64>> {
+    let first = ms.first()?;
+
+    Some(avg_over(ms, first.nrows(), first.ncols(), ms.len()))
+}
+
+// sum of `ms` divided by `count`, which can be more than `ms.len()`
+// to count missing matrixes as zeros
+pub(crate) fn avg_over(ms: &[&DMatrix<f64>], nrows: usize, ncols: usize, count: usize) -> DMatrix<f64> {
+    let mut mean = RunningMean::new(nrows, ncols);
+    for m in ms {
+        mean.add(m);
+    }
+    for _ in ms.len()..count {
+        mean.add_missing();
+    }
+
+    mean.into_mean().unwrap_or_else(|| DMatrix::from_element(nrows, ncols, std::f64::NAN))
+}
+
+/// Elementwise average built up one matrix at a time, e.g. as each
+/// year's proximity is calculated, so only the running total is held
+/// rather than every matrix. The result is identical to `avg` over the
+/// same matrixes, since it's the same sum divided at the end.
+///
+/// ```
+/// # use nalgebra::DMatrix;
+/// # use product_space::smooth::RunningMean;
+/// let mut mean = RunningMean::new(1, 2);
+/// mean.add(&DMatrix::from_vec(1,2,vec![2.0,4.0]));
+/// mean.add(&DMatrix::from_vec(1,2,vec![4.0,8.0]));
+/// assert_eq!(mean.into_mean(), Some(DMatrix::from_vec(1,2,vec![3.0,6.0])));
+/// ```
+pub struct RunningMean {
+    sum: DMatrix<f64>,
+    count: usize,
+}
+
+impl RunningMean {
+    pub fn new(nrows: usize, ncols: usize) -> Self {
+        RunningMean {
+            sum: DMatrix::zeros(nrows, ncols),
+            count: 0,
+        }
+    }
+
+    /// Panics if `m` isn't the shape given to `new`
+    pub fn add(&mut self, m: &DMatrix<f64>) {
+        self.sum += m;
+        self.count += 1;
+    }
+
+    /// counts a missing matrix as all zeros; see `avg`
+    pub fn add_missing(&mut self) {
+        self.count += 1;
+    }
+
+    /// matrixes added so far, including missing ones
+    pub fn count(&self) -> usize {
+        self.count
+    }
 
-    let mut res = ms
-        .fold(zeros, |mut z, proximity_matrix| {
-            z += proximity_matrix;
-            z
-        });
+    /// the average, or None if nothing was added
+    pub fn into_mean(self) -> Option<DMatrix<f64>> {
+        if self.count == 0 {
+            return None;
+        }
 
-    res.apply(|x| x / count as f64);
+        let count = self.count as f64;
+        let mut res = self.sum;
+        res.apply(|x| x / count);
 
-    res
+        Some(res)
+    }
 }
 
 #[cfg(test)]
@@ -30,11 +105,52 @@ mod test {
             DMatrix::from_vec(2,2,vec![4.0,2.0,6.0,9.0]),
             DMatrix::from_vec(2,2,vec![5.0,6.0,7.0,2.0]),
         ];
+        let refs: Vec<_> = matrixes.iter().collect();
 
-        let my_avg = avg(matrixes.iter(), 2, 5);
+        let my_avg = avg(&refs).unwrap();
 
         let expected = DMatrix::from_vec(2,2,vec![3.0,4.0,5.0,6.0]);
 
         assert_eq!(my_avg, expected);
+
+        assert!(avg(&[]).is_none());
+    }
+
+    #[test]
+    fn test_avg_over() {
+        let matrixes = [
+            DMatrix::from_vec(1,2,vec![2.0,4.0]),
+            DMatrix::from_vec(1,2,vec![4.0,8.0]),
+        ];
+        let refs: Vec<_> = matrixes.iter().collect();
+
+        // a third, missing, matrix counts as zeros
+        let res = avg_over(&refs, 1, 2, 3);
+
+        assert_eq!(res, DMatrix::from_vec(1,2,vec![2.0,4.0]));
+    }
+
+    #[test]
+    fn test_running_mean() {
+        let matrixes = [
+            DMatrix::from_vec(2,2,vec![1.0,5.0,3.0,8.0]),
+            DMatrix::from_vec(2,2,vec![2.0,3.1,4.0,5.0]),
+            DMatrix::from_vec(2,2,vec![3.0,4.0,5.7,6.0]),
+        ];
+        let refs: Vec<_> = matrixes.iter().collect();
+
+        let mut mean = RunningMean::new(2, 2);
+        for m in &matrixes {
+            mean.add(m);
+        }
+        assert_eq!(mean.count(), 3);
+        assert_eq!(mean.into_mean().unwrap(), avg(&refs).unwrap());
+
+        let mut mean = RunningMean::new(2, 2);
+        mean.add(&matrixes[0]);
+        mean.add_missing();
+        assert_eq!(mean.into_mean().unwrap(), matrixes[0].map(|x| x / 2.0));
+
+        assert!(RunningMean::new(2, 2).into_mean().is_none());
     }
 }
@@ -0,0 +1,123 @@
+use nalgebra::DMatrix;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Error, ProductSpace};
+
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    years: &'a [u32],
+    rca_cutoff: Option<f64>,
+    eci: BTreeMap<&'a str, f64>,
+    pci: BTreeMap<&'a str, f64>,
+    rca: BTreeMap<&'a str, BTreeMap<&'a str, f64>>,
+    density: BTreeMap<&'a str, BTreeMap<&'a str, f64>>,
+    mst: Vec<Edge>,
+}
+
+#[derive(Serialize)]
+struct Edge {
+    source: String,
+    target: String,
+    proximity: f64,
+}
+
+impl ProductSpace {
+    /// All results for a set of years in one json object, for feeding a
+    /// frontend. Keys are sorted, so output is stable.
+    ///
+    /// ```text
+    /// {
+    ///   "years": [2017],
+    ///   "rca_cutoff": 1.0,                  // or null
+    ///   "eci": { "<country>": f64, .. },
+    ///   "pci": { "<product>": f64, .. },
+    ///   "rca": { "<country>": { "<product>": f64, .. }, .. },
+    ///   "density": { "<country>": { "<product>": f64, .. }, .. },
+    ///   "mst": [ { "source": "<product>", "target": "<product>", "proximity": f64 }, .. ]
+    /// }
+    /// ```
+    ///
+    /// `rca` is binary when there's a cutoff, as returned by `rca`.
+    /// Non-finite values are written as null.
+    pub fn snapshot_json(
+        &self,
+        years: &[u32],
+        rca_cutoff: Option<f64>,
+        ) -> Result<String, Error>
+    {
+        let missing = || Error::MissingYears { years: years.to_vec() };
+
+        let complexity = self.try_complexity(years, rca_cutoff)?;
+        let rca = self.try_rca_matrix(years, rca_cutoff)?;
+        let density = self.try_density_matrix(years, rca_cutoff)?;
+        let proximity = self.proximity(years).ok_or_else(missing)?;
+
+        let snapshot = Snapshot {
+            years,
+            rca_cutoff,
+            eci: named_vector(complexity.eci_vector().as_slice(), &self.country_idx),
+            pci: named_vector(complexity.pci_vector().as_slice(), &self.product_idx),
+            rca: named_matrix(&rca, &self.country_idx, &self.product_idx),
+            density: named_matrix(&density, &self.country_idx, &self.product_idx),
+            mst: proximity.mst().into_iter()
+                .map(|(source, target, proximity)| Edge { source, target, proximity })
+                .collect(),
+        };
+
+        Ok(serde_json::to_string(&snapshot)
+            .expect("logic error, snapshot is always serializable"))
+    }
+}
+
+fn named_vector<'a>(v: &[f64], index: &'a HashMap<String, usize>) -> BTreeMap<&'a str, f64> {
+    index.iter()
+        .map(|(name, i)| (name.as_str(), v[*i]))
+        .collect()
+}
+
+fn named_matrix<'a>(
+    m: &DMatrix<f64>,
+    country_idx: &'a HashMap<String, usize>,
+    product_idx: &'a HashMap<String, usize>,
+    ) -> BTreeMap<&'a str, BTreeMap<&'a str, f64>>
+{
+    country_idx.iter()
+        .map(|(country, row)| {
+            let products = product_idx.iter()
+                .map(|(product, col)| (product.as_str(), m[(*row, *col)]))
+                .collect();
+            (country.as_str(), products)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_json() {
+        let vals = DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]);
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, vals);
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let json = ps.snapshot_json(&[2017], Some(1.0)).unwrap();
+        let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(v["years"], serde_json::json!([2017]));
+        assert_eq!(v["rca"]["a"]["02"], 1.0);
+        assert_eq!(v["rca"]["b"]["02"], 0.0);
+        assert_eq!(v["mst"].as_array().unwrap().len(), 2);
+        assert!(v["eci"]["a"].is_number());
+
+        assert!(ps.snapshot_json(&[2099], Some(1.0)).is_err());
+    }
+}
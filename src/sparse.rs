@@ -0,0 +1,161 @@
+use log::warn;
+use nalgebra::DMatrix;
+
+/// Proximity keeping only the entries at or above a threshold, with
+/// everything else taken as 0.0. For large product spaces most
+/// proximities are tiny, so this holds a fraction of the dense
+/// products x products matrix. See `ProductSpaceBuilder::sparse_proximity`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseProximity {
+    threshold: f64,
+    // for each product (column), its (row, proximity) entries kept,
+    // sorted by row
+    cols: Vec<Vec<(usize, f64)>>,
+}
+
+impl SparseProximity {
+    /// Keeps entries of a square proximity matrix that are at least
+    /// `threshold` (as the `min_proximity` floor of density). Zeros and
+    /// NaN are never kept: NaN is taken as 0.0, as the dense proximity
+    /// zeroes it, and the count dropped is logged at warn.
+    pub fn from_dense(m: &DMatrix<f64>, threshold: f64) -> Self {
+        let nans = m.iter().filter(|x| x.is_nan()).count();
+        if nans > 0 {
+            warn!("{} NaN proximity cells dropped as 0.0", nans);
+        }
+
+        let cols = (0..m.ncols())
+            .map(|q| {
+                m.column(q).iter()
+                    .enumerate()
+                    .filter(|(_, phi)| **phi != 0.0 && **phi >= threshold)
+                    .map(|(p, phi)| (p, *phi))
+                    .collect()
+            })
+            .collect();
+
+        SparseProximity { threshold, cols }
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// number of products, the size of each side
+    pub fn nproducts(&self) -> usize {
+        self.cols.len()
+    }
+
+    /// number of entries kept
+    pub fn nnz(&self) -> usize {
+        self.cols.iter().map(|col| col.len()).sum()
+    }
+
+    /// proximity between products `p` and `q` (by index), 0.0 if it
+    /// wasn't kept. Panics if either is out of bounds.
+    pub fn get(&self, p: usize, q: usize) -> f64 {
+        assert!(p < self.nproducts(), "product index out of bounds");
+
+        let col = &self.cols[q];
+        col.binary_search_by_key(&p, |(row, _)| *row)
+            .map(|i| col[i].1)
+            .unwrap_or(0.0)
+    }
+
+    /// the same entries with a higher threshold. Entries already dropped
+    /// aren't restored, so a lower threshold changes nothing.
+    pub fn with_threshold(&self, threshold: f64) -> Self {
+        let cols = self.cols.iter()
+            .map(|col| col.iter().filter(|(_, phi)| *phi >= threshold).cloned().collect())
+            .collect();
+
+        SparseProximity { threshold: self.threshold.max(threshold), cols }
+    }
+
+    /// dense matrix, with 0.0 for every entry not kept
+    pub fn to_dense(&self) -> DMatrix<f64> {
+        let n = self.nproducts();
+        let mut m = DMatrix::zeros(n, n);
+        for (q, col) in self.cols.iter().enumerate() {
+            for (p, phi) in col {
+                m[(*p, q)] = *phi;
+            }
+        }
+
+        m
+    }
+}
+
+/// `density` from sparse proximity, with entries not kept taken as
+/// 0.0, so only the kept entries are visited. This is an approximation:
+/// dropping small proximities lowers both the numerator and the
+/// denominator of each density, most for products whose proximities are
+/// all small.
+pub fn density_sparse(rca: &DMatrix<f64>, proximity: &SparseProximity) -> DMatrix<f64> {
+    let mut res = DMatrix::zeros(rca.nrows(), proximity.nproducts());
+
+    for (q, col) in proximity.cols.iter().enumerate() {
+        let denominator: f64 = col.iter().map(|(_, phi)| phi).sum();
+
+        for c in 0..rca.nrows() {
+            let numerator: f64 = col.iter()
+                .map(|(p, phi)| rca[(c, *p)] * phi)
+                .sum();
+            res[(c, q)] = numerator / denominator;
+        }
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{density, fair_share, proximity, rca};
+
+    #[test]
+    fn test_sparse_proximity() {
+        let m = DMatrix::from_row_slice(3,3,&[
+            1.0, 0.5, 0.2,
+            0.5, 1.0, 0.0,
+            0.2, 0.0, 1.0,
+        ]);
+
+        let sparse = SparseProximity::from_dense(&m, 0.0);
+        assert_eq!(sparse.nnz(), 7);
+        assert_eq!(sparse.to_dense(), m);
+
+        let sparse = SparseProximity::from_dense(&m, 0.5);
+        assert_eq!(sparse.nnz(), 5);
+        assert_eq!(sparse.get(0, 1), 0.5);
+        assert_eq!(sparse.get(0, 2), 0.0);
+
+        let raised = sparse.with_threshold(0.6);
+        assert_eq!(raised.threshold(), 0.6);
+        assert_eq!(raised.to_dense(), DMatrix::identity(3, 3));
+        assert_eq!(sparse.with_threshold(0.1), sparse);
+
+        // NaN is dropped, the same as the zeroed dense proximity
+        let mut with_nan = m.clone();
+        with_nan[(1, 2)] = std::f64::NAN;
+        assert_eq!(SparseProximity::from_dense(&with_nan, 0.0), SparseProximity::from_dense(&m, 0.0));
+    }
+
+    #[test]
+    fn test_density_sparse() {
+        let m = DMatrix::from_vec(4,3,vec![5.0,1.0,2.0,6.0,1.0,4.0,2.0,5.0,3.0,2.0,6.0,1.0]);
+        let rca = fair_share(&rca(&m), Some(1.0));
+        let proximity = proximity(&rca);
+
+        // nothing dropped
+        let res = density_sparse(&rca, &SparseProximity::from_dense(&proximity, 0.0));
+        let expected = density(&rca, &proximity);
+        for (x, y) in res.iter().zip(expected.iter()) {
+            assert!((x - y).abs() < 1e-12);
+        }
+
+        // only the diagonal is kept, so density is the rca
+        let res = density_sparse(&rca, &SparseProximity::from_dense(&proximity, 0.6));
+        assert_eq!(res, rca);
+    }
+}
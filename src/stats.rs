@@ -0,0 +1,146 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Spearman rank correlation between two sets of named values, e.g.
+/// eci from this crate against a published eci.
+///
+/// Values are aligned by name, and names only in one set are ignored.
+/// Tied values get the average of their ranks. NaN if fewer than two
+/// names are shared, or if either set's shared values are all tied.
+pub fn rank_correlation(a: &[(String, f64)], b: &[(String, f64)]) -> f64 {
+    let b_lookup: HashMap<&str, f64> = b.iter()
+        .map(|(name, x)| (name.as_str(), *x))
+        .collect();
+
+    let (xs, ys): (Vec<f64>, Vec<f64>) = a.iter()
+        .filter_map(|(name, x)| {
+            b_lookup.get(name.as_str()).map(|y| (*x, *y))
+        })
+        .unzip();
+
+    if xs.len() < 2 {
+        return std::f64::NAN;
+    }
+
+    pearson(&ranks(&xs), &ranks(&ys))
+}
+
+// Rankings (e.g. `upgrades`) are sorted by value descending, with NaN
+// last and ties broken by name, so the order never depends on HashMap
+// iteration order.
+pub(crate) fn sort_ranked(items: &mut [(String, f64)]) {
+    items.sort_by(|(name1, x1), (name2, x2)| cmp_ranked((name1, *x1), (name2, *x2)));
+}
+
+// the order of `sort_ranked`, for picking the top item without sorting
+pub(crate) fn cmp_ranked((name1, x1): (&str, f64), (name2, x2): (&str, f64)) -> Ordering {
+    cmp_nan_last(x2, x1)
+        .then_with(|| name1.cmp(name2))
+}
+
+// total order for floats, ascending, with NaN after everything else.
+// With `reverse` on the result for descending, NaN would come first, so
+// descending sorts swap the arguments instead.
+fn cmp_nan_last(x: f64, y: f64) -> Ordering {
+    match (x.is_nan(), y.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+    }
+}
+
+// 1-based ranks, ties averaged. NaN ranks last.
+pub(crate) fn ranks(xs: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..xs.len()).collect();
+    order.sort_by(|i, j| cmp_nan_last(xs[*i], xs[*j]));
+
+    let mut res = vec![0.0; xs.len()];
+
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && xs[order[end]] == xs[order[start]] {
+            end += 1;
+        }
+
+        // ranks start..end (0-based) are tied, so average them
+        let rank = (start + end + 1) as f64 / 2.0;
+        for i in &order[start..end] {
+            res[*i] = rank;
+        }
+
+        start = end;
+    }
+
+    res
+}
+
+// NaN if either has zero variance (all values equal, or fewer than
+// two), or for empty input, since the correlation is undefined. Callers
+// comparing against it (e.g. `orient`'s `< 0.0`) treat NaN as false.
+pub(crate) fn pearson(xs: &[f64], ys: &[f64]) -> f64 {
+    let n = xs.len() as f64;
+    let x_mean = xs.iter().sum::<f64>() / n;
+    let y_mean = ys.iter().sum::<f64>() / n;
+
+    let mut cov = 0.0;
+    let mut x_var = 0.0;
+    let mut y_var = 0.0;
+    for (x, y) in xs.iter().zip(ys) {
+        cov += (x - x_mean) * (y - y_mean);
+        x_var += (x - x_mean).powi(2);
+        y_var += (y - y_mean).powi(2);
+    }
+
+    cov / (x_var * y_var).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(vals: &[(&str, f64)]) -> Vec<(String, f64)> {
+        vals.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn test_ranks() {
+        assert_eq!(ranks(&[3.0, 1.0, 2.0]), vec![3.0, 1.0, 2.0]);
+        assert_eq!(ranks(&[1.0, 2.0, 2.0, 5.0]), vec![1.0, 2.5, 2.5, 4.0]);
+    }
+
+    #[test]
+    fn test_sort_ranked() {
+        let mut items = named(&[("c", 1.0), ("b", std::f64::NAN), ("a", 1.0), ("d", 2.0), ("e", 1.0)]);
+        sort_ranked(&mut items);
+
+        let names: Vec<_> = items.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["d", "a", "c", "e", "b"]);
+    }
+
+    #[test]
+    fn test_rank_correlation() {
+        let a = named(&[("usa", 2.0), ("deu", 1.5), ("bra", 0.1), ("tuv", 3.0)]);
+        let b = named(&[("bra", -1.0), ("deu", 0.5), ("usa", 0.9)]);
+
+        assert_eq!(rank_correlation(&a, &b), 1.0);
+
+        let b = named(&[("bra", 1.0), ("deu", 0.5), ("usa", 0.1)]);
+        assert_eq!(rank_correlation(&a, &b), -1.0);
+
+        let b = named(&[("bra", 1.0)]);
+        assert!(rank_correlation(&a, &b).is_nan());
+
+        let b = named(&[("bra", 1.0), ("deu", 1.0), ("usa", 1.0)]);
+        assert!(rank_correlation(&a, &b).is_nan());
+    }
+
+    #[test]
+    fn test_pearson_zero_variance() {
+        assert!(pearson(&[1.0, 1.0, 1.0], &[1.0, 2.0, 3.0]).is_nan());
+        assert!(pearson(&[1.0, 2.0, 3.0], &[2.0, 2.0, 2.0]).is_nan());
+        assert!(pearson(&[], &[]).is_nan());
+        assert_eq!(pearson(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]), 1.0);
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use crate::rca::aggregates;
+use crate::{Error, ProductSpace};
+
+/// Export totals from the raw mcps, the `b`, `c` and `d` of `rca`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct Totals {
+    /// each country's total exports
+    pub by_country: HashMap<String, f64>,
+    /// world exports of each product
+    pub by_product: HashMap<String, f64>,
+    /// world exports
+    pub world: f64,
+}
+
+impl ProductSpace {
+    /// export totals by country, by product and for the world, from the
+    /// raw mcps summed over years. Years not found are skipped. Uses
+    /// compensated summation with the `kahan` feature, as rca does.
+    ///
+    /// Returns `Error::McpsDropped` if the mcps were dropped, or
+    /// `Error::MissingYears` if no year is found.
+    pub fn totals(&self, years: &[u32]) -> Result<Totals, Error> {
+        let total = self.summed_mcps(years)?;
+        let (by_country, by_product, world) = aggregates(&total);
+
+        Ok(Totals {
+            by_country: self.country_idx.iter()
+                .map(|(country, i)| (country.clone(), by_country[*i]))
+                .collect(),
+            by_product: self.product_idx.iter()
+                .map(|(product, i)| (product.clone(), by_product[*i]))
+                .collect(),
+            world,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::DMatrix;
+    use super::*;
+
+    #[test]
+    fn test_totals() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(2,2,vec![1.0,3.0,2.0,2.0]));
+        mcps.insert(2017, DMatrix::from_vec(2,2,vec![3.0,1.0,2.0,2.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1)].iter().cloned().collect(),
+            mcps,
+            None,
+        );
+
+        let totals = ps.totals(&[2016]).unwrap();
+        assert_eq!(totals.by_country["a"], 3.0);
+        assert_eq!(totals.by_country["b"], 5.0);
+        assert_eq!(totals.by_product["01"], 4.0);
+        assert_eq!(totals.by_product["02"], 4.0);
+        assert_eq!(totals.world, 8.0);
+
+        let totals = ps.totals(&[2016, 2017, 2099]).unwrap();
+        assert_eq!(totals.by_country["a"], 8.0);
+        assert_eq!(totals.by_product["01"], 8.0);
+        assert_eq!(totals.world, 16.0);
+
+        assert!(ps.totals(&[2099]).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use crate::mcp::lookup;
+use crate::stats::sort_ranked;
+use crate::{density_row, Density, Error, ProductSpace, Smoothing};
+
+/// base rca below which `rca_growth` leaves a product out, since a
+/// ratio over a near-zero base is meaningless
+pub const MIN_BASE_RCA: f64 = 0.1;
+
+impl ProductSpace {
+    /// Products `country` gained binary rca in between `from` and `to`,
+    /// each with its pci in `to`, sorted by pci descending. Ties are by
+    /// product code, and NaN is last.
+    ///
+    /// Binary rca and pci use the fair share default of 1.0 if there's
+    /// no cutoff, as in `complexity`.
+    pub fn upgrades(
+        &self,
+        country: &str,
+        from: u32,
+        to: u32,
+        rca_cutoff: Option<f64>,
+        ) -> Result<Vec<(String, f64)>, Error>
+    {
+        let row = lookup(&self.country_idx, country, "country")?;
+
+        let rca_from = self.try_binary_rca_matrix(&[from], rca_cutoff)?;
+        let rca_to = self.try_binary_rca_matrix(&[to], rca_cutoff)?;
+        let complexity = self.try_complexity(&[to], rca_cutoff)?;
+        let pci = complexity.pci_vector();
+
+        let mut res: Vec<_> = self.product_idx.iter()
+            .filter(|(_, col)| rca_from[(row, **col)] == 0.0 && rca_to[(row, **col)] == 1.0)
+            .map(|(product, col)| (product.clone(), pci[*col]))
+            .collect();
+
+        sort_ranked(&mut res);
+
+        Ok(res)
+    }
+
+    /// The `n` products whose rca for `country` grew the most from `from`
+    /// to `to`, as the ratio of continuous rca in `to` over `from`, sorted
+    /// descending. Ties are by product code, and NaN is last.
+    ///
+    /// Products with rca in `from` below `MIN_BASE_RCA` are left out.
+    /// The ratio is of the rca as cached, so is only meaningful for
+    /// `RcaVariant::Balassa`.
+    pub fn rca_growth(
+        &self,
+        country: &str,
+        from: u32,
+        to: u32,
+        n: usize,
+        ) -> Result<Vec<(String, f64)>, Error>
+    {
+        let row = lookup(&self.country_idx, country, "country")?;
+
+        let rca_from = self.try_rca_matrix(&[from], None)?;
+        let rca_to = self.try_rca_matrix(&[to], None)?;
+
+        let mut res: Vec<_> = self.product_idx.iter()
+            .filter(|(_, col)| rca_from[(row, **col)] >= MIN_BASE_RCA)
+            .map(|(product, col)| (product.clone(), rca_to[(row, *col)] / rca_from[(row, *col)]))
+            .collect();
+
+        sort_ranked(&mut res);
+        res.truncate(n);
+
+        Ok(res)
+    }
+
+    /// density in `to` minus density in `from`, for every country and
+    /// product. Positive means the country got closer to the product.
+    /// Each year's density is as from `density(&[year], rca_cutoff)`.
+    pub fn density_delta(
+        &self,
+        from: u32,
+        to: u32,
+        rca_cutoff: Option<f64>,
+        ) -> Result<Density, Error>
+    {
+        let density_from = self.try_density_matrix(&[from], rca_cutoff)?;
+        let density_to = self.try_density_matrix(&[to], rca_cutoff)?;
+
+        Ok(Density {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            m: density_to - density_from,
+            provenance: self.provenance(&[from, to], rca_cutoff, Smoothing::Difference),
+        })
+    }
+
+    /// density for `country` from only the products it gained binary
+    /// rca in between `recent_from` and `recent_to` (as in `upgrades`),
+    /// rather than everything it exports, with proximity from
+    /// `base_years`: where its recent diversification pulls it next.
+    /// Density is returned for every product, and is 0.0 everywhere if
+    /// nothing was gained.
+    ///
+    /// Binary rca uses the fair share default of 1.0 if there's no
+    /// cutoff, as in `complexity`.
+    pub fn momentum_density(
+        &self,
+        country: &str,
+        base_years: &[u32],
+        recent_from: u32,
+        recent_to: u32,
+        rca_cutoff: Option<f64>,
+        ) -> Result<HashMap<String, f64>, Error>
+    {
+        let row = lookup(&self.country_idx, country, "country")?;
+
+        let missing = |years: &[u32]| Error::MissingYears { years: years.to_vec() };
+
+        let rca_from = self.try_binary_rca_matrix(&[recent_from], rca_cutoff)?;
+        let rca_to = self.try_binary_rca_matrix(&[recent_to], rca_cutoff)?;
+        let proximity = self.try_proximity_matrix(base_years)?.ok_or_else(|| missing(base_years))?;
+
+        let mut gained = rca_to.row(row).into_owned();
+        for (col, x) in gained.iter_mut().enumerate() {
+            if rca_from[(row, col)] == 1.0 {
+                *x = 0.0;
+            }
+        }
+
+        let res = density_row(&gained, &proximity);
+
+        Ok(self.product_idx.iter()
+            .map(|(product, idx)| (product.clone(), self.sanitized(res[*idx])))
+            .collect())
+    }
+
+    /// Each country's change in eci rank from `from` to `to`, where rank
+    /// 1 is the highest eci, so positive is moving up. Sorted by change
+    /// descending, ties by country.
+    ///
+    /// eci in `to` is oriented to correlate with eci in `from` (see
+    /// `complexity_oriented`), so a sign flip between the years doesn't
+    /// show up as every country trading places. Countries are ranked
+    /// only among those with finite eci in both years, so the change is
+    /// their movement relative to each other; the rest are left out.
+    pub fn eci_rank_changes(
+        &self,
+        from: u32,
+        to: u32,
+        rca_cutoff: Option<f64>,
+        ) -> Result<Vec<(String, i32)>, Error>
+    {
+        let eci_from = self.try_complexity(&[from], rca_cutoff)?;
+        let reference: HashMap<String, f64> = self.country_idx.iter()
+            .map(|(country, i)| (country.clone(), eci_from.eci[*i]))
+            .filter(|(_, x)| x.is_finite())
+            .collect();
+        let eci_to = self.complexity_oriented(&[to], rca_cutoff, &reference)?;
+
+        let (ranked_from, ranked_to): (Vec<_>, Vec<_>) = self.country_idx.iter()
+            .filter(|(_, i)| eci_from.eci[**i].is_finite() && eci_to.eci[**i].is_finite())
+            .map(|(country, i)| ((country.clone(), eci_from.eci[*i]), (country.clone(), eci_to.eci[*i])))
+            .unzip();
+        let ranks_from = ranks_by_name(ranked_from);
+        let ranks_to = ranks_by_name(ranked_to);
+
+        let mut res: Vec<_> = ranks_from.into_iter()
+            .map(|(country, rank)| {
+                let change = rank - ranks_to[&country];
+                (country, change)
+            })
+            .collect();
+        res.sort_by(|(country1, change1), (country2, change2)| {
+            change2.cmp(change1).then_with(|| country1.cmp(country2))
+        });
+
+        Ok(res)
+    }
+}
+
+// rank of each name, from 1, in the order of `sort_ranked`
+fn ranks_by_name(mut items: Vec<(String, f64)>) -> HashMap<String, i32> {
+    sort_ranked(&mut items);
+
+    items.into_iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name, i as i32 + 1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::DMatrix;
+    use super::*;
+    use crate::Mcp;
+
+    #[test]
+    fn test_upgrades() {
+        let mut mcps = HashMap::new();
+        // binary rows: a [1,0,0], b [0,1,1], c [0,1,1]
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        // binary rows: a [0,1,1], b [1,0,0], c [1,0,0]
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let res = ps.upgrades("a", 2016, 2017, Some(1.0)).unwrap();
+        let products: Vec<_> = res.iter().map(|(p, _)| p.as_str()).collect();
+
+        let complexity = ps.complexity(&[2017], Some(1.0)).unwrap();
+        let mut expected = vec!["02", "03"];
+        expected.sort_by(|p1, p2| {
+            complexity.pci(p2).unwrap().partial_cmp(&complexity.pci(p1).unwrap()).unwrap()
+        });
+
+        assert_eq!(products, expected);
+        assert_eq!(res[0].1, complexity.pci(expected[0]).unwrap());
+
+        // going back, only 01 is gained
+        let res = ps.upgrades("a", 2017, 2016, Some(1.0)).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].0, "01");
+
+        assert!(ps.upgrades("zz", 2016, 2017, Some(1.0)).is_err());
+        assert!(ps.upgrades("a", 2015, 2017, Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_rca_growth() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // a: 01 2.142857 -> 0.681818, 02 0.6 -> 2.25, 03 has no base
+        let res = ps.rca_growth("a", 2016, 2017, 5).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].0, "02");
+        assert!((res[0].1 - 3.75).abs() < 1e-9);
+        assert_eq!(res[1].0, "01");
+        assert!((res[1].1 - (0.681818181818 / 2.142857142857)).abs() < 1e-9);
+
+        let res = ps.rca_growth("a", 2016, 2017, 1).unwrap();
+        assert_eq!(res.len(), 1);
+
+        assert!(ps.rca_growth("zz", 2016, 2017, 1).is_err());
+        assert!(ps.rca_growth("a", 2016, 2099, 1).is_err());
+    }
+
+    #[test]
+    fn test_density_delta() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        let delta = ps.density_delta(2016, 2017, Some(1.0)).unwrap();
+        let from = ps.density(&[2016], Some(1.0)).unwrap();
+        let to = ps.density(&[2017], Some(1.0)).unwrap();
+
+        assert_eq!(
+            delta.get("a", "02").unwrap(),
+            to.get("a", "02").unwrap() - from.get("a", "02").unwrap(),
+        );
+        assert!(ps.density_delta(2016, 2099, Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_momentum_density() {
+        let mut mcps = HashMap::new();
+        // binary rows: a [1,0,0], b [0,1,1], c [0,1,1]
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![5.0,1.0,1.0,1.0,2.0,2.0,0.0,3.0,3.0]));
+        // binary rows: a [0,1,1], b [1,0,0], c [1,0,0]
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![5.0,8.0,9.0,3.0,1.0,0.0,1.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(
+            [("a".to_string(),0usize), ("b".to_string(),1), ("c".to_string(),2)].iter().cloned().collect(),
+            [("01".to_string(),0usize), ("02".to_string(),1), ("03".to_string(),2)].iter().cloned().collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // a gained 02 and 03, which are only related to each other
+        let res = ps.momentum_density("a", &[2017], 2016, 2017, Some(1.0)).unwrap();
+        let proximity = ps.proximity_matrix(&[2017]).unwrap();
+        let expected = density_row(&nalgebra::RowDVector::from_vec(vec![0.0, 1.0, 1.0]), &proximity);
+        assert_eq!(res["01"], 0.0);
+        assert_eq!(res["02"], 1.0);
+        assert_eq!(res["03"], expected[2]);
+
+        // nothing gained
+        let res = ps.momentum_density("a", &[2017], 2017, 2017, Some(1.0)).unwrap();
+        assert!(res.values().all(|x| *x == 0.0));
+
+        assert!(ps.momentum_density("zz", &[2017], 2016, 2017, Some(1.0)).is_err());
+        assert!(ps.momentum_density("a", &[2099], 2016, 2017, Some(1.0)).is_err());
+        assert!(ps.momentum_density("a", &[2017], 2015, 2017, Some(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_eci_rank_changes() {
+        let m = DMatrix::from_row_slice(5,5,&[
+            3.0, 1.0, 1.0, 2.0, 0.0,
+            2.0, 2.0, 0.0, 1.0, 1.0,
+            0.0, 3.0, 2.0, 0.0, 1.0,
+            1.0, 1.0, 4.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 3.0, 2.0,
+        ]);
+        // a and b trade exports
+        let mut swapped = m.clone();
+        swapped.swap_rows(0, 1);
+
+        let mut mcps = HashMap::new();
+        mcps.insert(2016, m);
+        mcps.insert(2017, swapped);
+
+        let ps = ProductSpace::new(
+            ["a", "b", "c", "d", "e"].iter().enumerate().map(|(i, c)| (c.to_string(), i)).collect(),
+            ["01", "02", "03", "04", "05"].iter().enumerate().map(|(i, p)| (p.to_string(), i)).collect(),
+            mcps,
+            Some(1.0),
+        );
+
+        // eci ranks in 2016: a, e, b, c, d
+        let res = ps.eci_rank_changes(2016, 2017, Some(1.0)).unwrap();
+        assert_eq!(res, vec![
+            ("b".to_string(), 2),
+            ("c".to_string(), 0),
+            ("d".to_string(), 0),
+            ("e".to_string(), 0),
+            ("a".to_string(), -2),
+        ]);
+
+        assert!(ps.eci_rank_changes(2016, 2016, Some(1.0)).unwrap().iter().all(|(_, change)| *change == 0));
+        assert!(ps.eci_rank_changes(2016, 2099, Some(1.0)).is_err());
+        assert!(ps.eci_rank_changes(2099, 2017, Some(1.0)).is_err());
+    }
+}
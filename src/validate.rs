@@ -0,0 +1,201 @@
+use nalgebra::DMatrix;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{Error, Mcp, ProductSpace};
+
+/// Index keys that look like the same member, from `ProductSpace::validate`.
+/// Each group is sorted, and groups are sorted by their first key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexReport {
+    pub countries: Vec<Vec<String>>,
+    pub products: Vec<Vec<String>>,
+}
+
+impl IndexReport {
+    /// true if no near-duplicates were found
+    pub fn is_empty(&self) -> bool {
+        self.countries.is_empty() && self.products.is_empty()
+    }
+}
+
+impl ProductSpace {
+    /// Checks the country and product indexes for near-duplicate keys
+    /// (see `near_duplicates`), which split one member's exports in two.
+    pub fn validate(&self) -> IndexReport {
+        IndexReport {
+            countries: near_duplicates(&self.country_idx),
+            products: near_duplicates(&self.product_idx),
+        }
+    }
+}
+
+/// How many of the requested years have nonzero raw exports, from
+/// `ProductSpace::coverage`, as a fraction of the years requested.
+/// Cells are addressable by name through `Mcp`.
+pub struct CoverageReport {
+    country_idx: Arc<HashMap<String, usize>>,
+    product_idx: Arc<HashMap<String, usize>>,
+    m: DMatrix<f64>,
+    /// years with any exports, by country
+    pub countries: HashMap<String, f64>,
+    /// years with any country exporting, by product
+    pub products: HashMap<String, f64>,
+}
+
+impl Mcp for CoverageReport {
+    fn matrix(&self) -> &DMatrix<f64> {
+        &self.m
+    }
+    fn country_index(&self) -> &HashMap<String, usize> {
+        &self.country_idx
+    }
+    fn product_index(&self) -> &HashMap<String, usize> {
+        &self.product_idx
+    }
+}
+
+impl ProductSpace {
+    /// Coverage of the raw mcps over `years`: for each cell, country
+    /// and product, the fraction of the requested years with nonzero
+    /// exports. Years not found count as no exports, as they do when
+    /// rca is averaged, so a cell at 1/3 over three years is mostly
+    /// gaps, not a stable advantage.
+    ///
+    /// Returns `Error::McpsDropped` if the mcps were dropped, or
+    /// `Error::MissingYears` if no year is found.
+    pub fn coverage(&self, years: &[u32]) -> Result<CoverageReport, Error> {
+        let mcps = self.mcps()?;
+        let found: Vec<_> = years.iter()
+            .filter_map(|y| mcps.get(y))
+            .collect();
+        if found.is_empty() {
+            return Err(Error::MissingYears { years: years.to_vec() });
+        }
+
+        let n = years.len() as f64;
+        let (countries, products) = (self.country_idx.len(), self.product_idx.len());
+
+        let mut m = DMatrix::zeros(countries, products);
+        let mut country_years = vec![0.0; countries];
+        let mut product_years = vec![0.0; products];
+
+        for mcp in found {
+            for (count, x) in m.iter_mut().zip(mcp.iter()) {
+                if *x != 0.0 {
+                    *count += 1.0;
+                }
+            }
+
+            for (c, row) in mcp.row_iter().enumerate() {
+                if row.iter().any(|x| *x != 0.0) {
+                    country_years[c] += 1.0;
+                }
+            }
+            for (p, col) in mcp.column_iter().enumerate() {
+                if col.iter().any(|x| *x != 0.0) {
+                    product_years[p] += 1.0;
+                }
+            }
+        }
+        m.apply(|count| count / n);
+
+        Ok(CoverageReport {
+            country_idx: self.country_idx.clone(),
+            product_idx: self.product_idx.clone(),
+            m,
+            countries: self.country_idx.iter()
+                .map(|(country, i)| (country.clone(), country_years[*i] / n))
+                .collect(),
+            products: self.product_idx.iter()
+                .map(|(product, i)| (product.clone(), product_years[*i] / n))
+                .collect(),
+        })
+    }
+}
+
+/// Groups of keys that are the same once trimmed and lowercased, e.g.
+/// "usa" and " USA", or "0101" and "0101 ". Can be run on indexes
+/// before building a product space.
+pub fn near_duplicates(idx: &HashMap<String, usize>) -> Vec<Vec<String>> {
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    for key in idx.keys() {
+        groups.entry(key.trim().to_lowercase())
+            .or_insert_with(Vec::new)
+            .push(key.clone());
+    }
+
+    let mut res: Vec<Vec<String>> = groups.into_iter()
+        .map(|(_, mut keys)| {
+            keys.sort();
+            keys
+        })
+        .filter(|keys| keys.len() > 1)
+        .collect();
+    res.sort();
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idx(names: &[&str]) -> HashMap<String, usize> {
+        names.iter()
+            .enumerate()
+            .map(|(v,k)| (k.to_string(), v))
+            .collect()
+    }
+
+    #[test]
+    fn test_near_duplicates() {
+        let res = near_duplicates(&idx(&["usa", " USA", "can", "0101", "0101 ", "0102", "Usa"]));
+
+        assert_eq!(res, vec![
+            vec!["0101".to_string(), "0101 ".to_string()],
+            vec![" USA".to_string(), "Usa".to_string(), "usa".to_string()],
+        ]);
+
+        assert!(near_duplicates(&idx(&["usa", "can"])).is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut mcps = HashMap::new();
+        mcps.insert(2017, DMatrix::from_vec(3,2,vec![1.0,2.0,3.0,4.0,5.0,6.0]));
+
+        let ps = ProductSpace::new(idx(&["usa", "usa ", "can"]), idx(&["01", "02"]), mcps, None);
+        let report = ps.validate();
+
+        assert!(!report.is_empty());
+        assert_eq!(report.countries, vec![vec!["usa".to_string(), "usa ".to_string()]]);
+        assert!(report.products.is_empty());
+    }
+
+    #[test]
+    fn test_coverage() {
+        let mut mcps = HashMap::new();
+        // b exports only in 2016, and 03 isn't exported in 2017
+        mcps.insert(2016, DMatrix::from_vec(3,3,vec![1.0,2.0,3.0,4.0,5.0,6.0,7.0,8.0,9.0]));
+        mcps.insert(2017, DMatrix::from_vec(3,3,vec![1.0,0.0,3.0,4.0,0.0,0.0,0.0,0.0,0.0]));
+
+        let ps = ProductSpace::new(idx(&["a", "b", "c"]), idx(&["01", "02", "03"]), mcps, None);
+
+        let report = ps.coverage(&[2016, 2017]).unwrap();
+        assert_eq!(report.get("a", "01").unwrap(), 1.0);
+        assert_eq!(report.get("b", "01").unwrap(), 0.5);
+        assert_eq!(report.get("c", "02").unwrap(), 0.5);
+        assert_eq!(report.countries["a"], 1.0);
+        assert_eq!(report.countries["b"], 0.5);
+        assert_eq!(report.products["02"], 1.0);
+        assert_eq!(report.products["03"], 0.5);
+
+        // missing years count as no exports
+        let report = ps.coverage(&[2015, 2016, 2017, 2018]).unwrap();
+        assert_eq!(report.get("a", "01").unwrap(), 0.5);
+        assert_eq!(report.countries["b"], 0.25);
+
+        assert!(ps.coverage(&[2099]).is_err());
+    }
+}